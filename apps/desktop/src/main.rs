@@ -1,6 +1,7 @@
 use clap::{Parser, Subcommand};
 use eframe::egui;
-use engine::{AppEvent, SharedAppState};
+use engine::{AppEvent, PendingReply, SharedAppState};
+use history::{HistoryDb, HistoryEntry, HistoryQuery};
 use log::{error, info};
 use std::env;
 use std::thread;
@@ -92,12 +93,28 @@ struct ZenTermApp {
     wizard_gpu_limit: u8,
     wizard_theme_dark: bool,
     wizard_voice_enabled: bool,
+    // Command history shared with the terminal frontend (Ctrl+R)
+    history: Option<HistoryDb>,
+    show_history: bool,
+    history_query: String,
+    history_results: Vec<HistoryEntry>,
+    // GPU limit change waiting for the engine to accept or reject it
+    gpu_limit_request: Option<PendingReply<()>>,
+    gpu_limit_error: Option<String>,
 }
 
 impl ZenTermApp {
     fn new() -> Self {
         let shared_state = SharedAppState::new();
 
+        let history = match HistoryDb::open(&HistoryDb::default_path()) {
+            Ok(db) => Some(db),
+            Err(e) => {
+                error!("Failed to open command history: {}", e);
+                None
+            }
+        };
+
         Self {
             shared_state,
             log_scroll_to_bottom: true,
@@ -108,6 +125,12 @@ impl ZenTermApp {
             wizard_gpu_limit: 25,
             wizard_theme_dark: true,
             wizard_voice_enabled: false,
+            history,
+            show_history: false,
+            history_query: String::new(),
+            history_results: Vec::new(),
+            gpu_limit_request: None,
+            gpu_limit_error: None,
         }
     }
 }
@@ -145,12 +168,19 @@ impl eframe::App for ZenTermApp {
                         }
                     }
                 }
-                egui::Event::Key { key, pressed: true, .. } => {
+                egui::Event::Key { key, pressed: true, modifiers, .. } => {
+                    if *key == egui::Key::R && modifiers.ctrl {
+                        self.show_history = !self.show_history;
+                        if self.show_history {
+                            self.refresh_history();
+                        }
+                    }
                     if *key == egui::Key::Escape {
                         if self.show_help {
                             self.show_help = false;
                             info!("help.hide");
                         }
+                        self.show_history = false;
                     }
                     // If wizard is open, ESC cancels it via sending WizardClosed
                     if *key == egui::Key::Escape && self.wizard_open {
@@ -205,6 +235,11 @@ impl eframe::App for ZenTermApp {
             self.render_help_overlay(ctx);
         }
 
+        // History search (Ctrl+R)
+        if self.show_history {
+            self.render_history_search(ctx);
+        }
+
         // Request repaint for live updates (e.g., GPU usage, logs)
         ctx.request_repaint_after(std::time::Duration::from_millis(500));
     }
@@ -233,13 +268,31 @@ impl ZenTermApp {
                             && !selected
                         {
                             let sender = self.shared_state.get_event_sender();
-                            if let Err(e) = sender.send(AppEvent::GpuLimitChanged(limit)) {
-                                error!("Failed to send GPU limit change event: {}", e);
+                            match sender.request(
+                                AppEvent::GpuLimitChanged(limit),
+                                std::time::Duration::from_secs(2),
+                            ) {
+                                Ok(pending) => {
+                                    self.gpu_limit_request = Some(pending);
+                                    self.gpu_limit_error = None;
+                                }
+                                Err(e) => {
+                                    error!("Failed to send GPU limit change event: {}", e);
+                                }
                             }
                         }
                     }
                 });
 
+                // Show why the engine rejected the last change, if it did
+                if let Some(result) = self.gpu_limit_request.as_ref().and_then(|p| p.poll()) {
+                    self.gpu_limit_error = result.err().map(|e| e.to_string());
+                    self.gpu_limit_request = None;
+                }
+                if let Some(message) = &self.gpu_limit_error {
+                    ui.colored_label(egui::Color32::RED, message);
+                }
+
                 ui.separator();
 
                 // Theme toggle
@@ -337,7 +390,8 @@ impl ZenTermApp {
             let send_clicked = ui.button("Send").clicked();
 
             if send_clicked || (input.lost_focus() && ui.input().key_pressed(egui::Key::Enter)) {
-                let trimmed = self.chat_input.trim().to_string();
+                // Secrets are masked before the message is logged or sent out
+                let trimmed = self.shared_state.redact(self.chat_input.trim());
                 if !trimmed.is_empty() {
                     let sender = self.shared_state.get_event_sender();
 
@@ -410,6 +464,59 @@ impl ZenTermApp {
         });
     }
 
+    /// Re-run the history search for the current query
+    fn refresh_history(&mut self) {
+        let Some(db) = &self.history else {
+            return;
+        };
+        let query = HistoryQuery::Fuzzy {
+            text: self.history_query.clone(),
+        };
+        match db.search(&query, history::DEFAULT_LIMIT) {
+            Ok(entries) => self.history_results = entries,
+            Err(e) => error!("Failed to search command history: {}", e),
+        }
+    }
+
+    /// Fuzzy search over commands run in any ZenTerm window; picking one
+    /// copies it into the chat input
+    fn render_history_search(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        let mut chosen = None;
+
+        egui::Window::new("Command History")
+            .collapsible(false)
+            .resizable(true)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let input = ui.text_edit_singleline(&mut self.history_query);
+                input.request_focus();
+                if input.changed() {
+                    self.refresh_history();
+                }
+
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    if self.history.is_none() {
+                        ui.label("Command history is not available");
+                    }
+                    for entry in &self.history_results {
+                        let label = format!("{}    ({})", entry.command, entry.cwd);
+                        if ui.selectable_label(false, label).clicked() {
+                            chosen = Some(entry.command.clone());
+                        }
+                    }
+                });
+            });
+
+        if let Some(command) = chosen {
+            self.chat_input = command;
+            open = false;
+        }
+        self.show_history = open;
+    }
+
     fn render_help_overlay(&mut self, ctx: &egui::Context) {
         egui::Window::new("Help")
             .collapsible(false)
@@ -422,6 +529,7 @@ impl ZenTermApp {
                     ui.separator();
                     ui.label("Global Keybindings:");
                     ui.label("  - Help (this window)");
+                    ui.label("  - Ctrl+R: search command history");
                     ui.label("  - Setup Wizard from sidebar");
                     ui.label("  - Use the Live Log and sidebar controls to change GPU/Theme/Voice");
                     ui.separator();
@@ -571,6 +679,15 @@ impl ZenTermApp {
 
             ui.separator();
 
+            // Terminal tab burning the most CPU, once a sample arrived
+            if let Some(busiest) = self.shared_state.get_busiest_session() {
+                ui.label(format!(
+                    "CPU: {} {:.0}%",
+                    busiest.session_id, busiest.usage.cpu_percent
+                ));
+                ui.separator();
+            }
+
             // Theme status
             let theme = self.shared_state.get_theme();
             ui.label(format!("Theme: {}", theme.name()));
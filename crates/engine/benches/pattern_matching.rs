@@ -0,0 +1,57 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use engine::{AppEvent, EventBus, Pattern, Subscriber};
+
+/// `count` patterns over many first segments, a tenth of them starting with
+/// a wildcard, plus one that matches `gpu.limit_changed`
+fn patterns(count: usize) -> Vec<String> {
+    let mut patterns: Vec<String> = (0..count - 1)
+        .map(|i| match i % 10 {
+            0 => format!("*.topic{}.**", i),
+            1 => format!("plugin{}.**.done", i),
+            _ => format!("plugin{}.events.*", i),
+        })
+        .collect();
+    patterns.push("gpu.*".to_string());
+    patterns
+}
+
+fn bench_matching(c: &mut Criterion) {
+    let deep = Pattern::compile("terminal.**.pane.**.finished").unwrap();
+    let topic = "terminal.window.tab.split.pane.left.command.finished";
+    c.bench_function("match deep topic", |b| {
+        b.iter(|| deep.matches(black_box(topic)))
+    });
+
+    for count in [20, 500] {
+        let compiled: Vec<Pattern> = patterns(count)
+            .iter()
+            .map(|p| Pattern::compile(p).unwrap())
+            .collect();
+        c.bench_function(&format!("linear scan, {} patterns", count), |b| {
+            b.iter(|| {
+                compiled
+                    .iter()
+                    .filter(|p| p.matches(black_box("gpu.limit_changed")))
+                    .count()
+            })
+        });
+
+        let bus = EventBus::new();
+        let subscribers: Vec<Subscriber> = patterns(count)
+            .iter()
+            .map(|p| bus.subscribe(p).unwrap())
+            .collect();
+        let sender = bus.sender();
+        c.bench_function(&format!("publish, {} subscribers", count), |b| {
+            b.iter(|| {
+                sender.send(AppEvent::GpuLimitChanged(50)).unwrap();
+                // Keep the queues from growing
+                while bus.try_recv().is_ok() {}
+                while subscribers[count - 1].try_recv().is_ok() {}
+            })
+        });
+    }
+}
+
+criterion_group!(benches, bench_matching);
+criterion_main!(benches);
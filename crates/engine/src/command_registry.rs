@@ -21,6 +21,7 @@ pub enum CommandCategory {
     Theme,
     Voice,
     Wizard,
+    Terminal,
     System,
 }
 
@@ -134,6 +135,32 @@ impl CommandRegistry {
                     category: CommandCategory::Wizard,
                 },
             ),
+            (
+                "terminal.broadcast.join",
+                Command {
+                    name: "Broadcast Input to Group".to_string(),
+                    description: "Add the current session to a synchronized input group"
+                        .to_string(),
+                    category: CommandCategory::Terminal,
+                },
+            ),
+            (
+                "terminal.broadcast.leave",
+                Command {
+                    name: "Stop Broadcasting Input".to_string(),
+                    description: "Take the current session out of its synchronized input group"
+                        .to_string(),
+                    category: CommandCategory::Terminal,
+                },
+            ),
+            (
+                "terminal.broadcast.opt_out",
+                Command {
+                    name: "Toggle Broadcast Opt-Out".to_string(),
+                    description: "Pause or resume shared input for the current session".to_string(),
+                    category: CommandCategory::Terminal,
+                },
+            ),
             (
                 "system.quit",
                 Command {
@@ -165,6 +192,7 @@ impl PartialEq for CommandCategory {
                 | (CommandCategory::Theme, CommandCategory::Theme)
                 | (CommandCategory::Voice, CommandCategory::Voice)
                 | (CommandCategory::Wizard, CommandCategory::Wizard)
+                | (CommandCategory::Terminal, CommandCategory::Terminal)
                 | (CommandCategory::System, CommandCategory::System)
         )
     }
@@ -189,6 +217,17 @@ mod tests {
         assert!(registry.get("system.quit").is_some());
     }
 
+    #[test]
+    fn test_broadcast_commands_registered() {
+        let registry = CommandRegistry::new();
+
+        let terminal_commands = registry.commands_by_category(&CommandCategory::Terminal);
+        assert_eq!(terminal_commands.len(), 3);
+        assert!(registry.execute("terminal.broadcast.join").is_ok());
+        assert!(registry.execute("terminal.broadcast.leave").is_ok());
+        assert!(registry.execute("terminal.broadcast.opt_out").is_ok());
+    }
+
     #[test]
     fn test_command_registration() {
         let mut registry = CommandRegistry::new();
@@ -1,4 +1,5 @@
 use log::{info, warn};
+use redaction::RedactionConfig;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -10,6 +11,15 @@ pub struct Config {
     pub gpu: GpuConfig,
     pub theme: ThemeConfig,
     pub voice: VoiceConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    /// Secrets masked in chat messages before they leave the app
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    /// When set, every event on the bus is written to this JSON-lines
+    /// journal, to attach to bug reports and replay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_journal: Option<PathBuf>,
 
     // Skip serialization - internal state for first-run detection
     #[serde(skip)]
@@ -38,6 +48,23 @@ pub struct VoiceConfig {
     pub enabled: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    /// Commands running at least this long notify when they finish in a
+    /// background tab
+    pub long_command_secs: u64,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            long_command_secs: notifications::DEFAULT_THRESHOLD.as_secs(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -46,6 +73,9 @@ impl Default for Config {
             },
             theme: ThemeConfig { dark_mode: true },
             voice: VoiceConfig { enabled: false },
+            notifications: NotificationConfig::default(),
+            redaction: RedactionConfig::default(),
+            event_journal: None,
             was_fresh: false,
             last_save: None,
             dirty: false,
@@ -216,4 +246,13 @@ mod tests {
         assert!(!deserialized.dirty);
         assert!(deserialized.last_save.is_none());
     }
+
+    #[test]
+    fn test_config_without_notifications() {
+        // Config files written before notifications existed still load
+        let json = r#"{"gpu":{"limit_percentage":50},"theme":{"dark_mode":false},"voice":{"enabled":true}}"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert!(config.notifications.enabled);
+        assert_eq!(config.notifications.long_command_secs, 10);
+    }
 }
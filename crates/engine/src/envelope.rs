@@ -0,0 +1,255 @@
+use crate::rpc::REPLY_TOPIC;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A payload that can travel on the event bus. `AppEvent` covers the app's
+/// own events; plugins implement this for their own types and publish them
+/// with `EventSender::publish`, without touching `AppEvent`.
+pub trait Event: Serialize + DeserializeOwned + fmt::Debug {
+    /// Schema version of the payload. Bump it when a change would break
+    /// readers of the previous version; readers reject newer versions.
+    const VERSION: u32;
+
+    /// Dotted topic the event is published under, e.g. `gpu.limit_changed`
+    fn topic(&self) -> &str;
+
+    /// Lane the event is queued in
+    fn priority(&self) -> Priority {
+        Priority::Bulk
+    }
+
+    /// Tells apart events of the same topic for `Overflow::Coalesce`, e.g.
+    /// a session id, so only the latest of each is kept
+    fn key(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Subscriber queues have a lane per priority; control events are
+/// received before any bulk event that is waiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// User actions and lifecycle events such as `QuitRequested`
+    Control,
+    /// Logs, samples and anything else that may arrive in bursts
+    #[default]
+    Bulk,
+}
+
+/// An event as it travels on the bus, in a form that can be written to
+/// disk, sent to another process and compared in tests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    /// Unique per bus, in publishing order
+    pub id: u64,
+    pub topic: String,
+    /// `Event::VERSION` of the payload's type when it was published
+    pub version: u32,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Who published the event, e.g. `gui` or `voice`
+    pub source: String,
+    #[serde(default)]
+    pub priority: Priority,
+    /// `Event::key` of the payload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Set on requests, to their own id, and on replies, to the id of the
+    /// request they answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<u64>,
+    pub payload: serde_json::Value,
+}
+
+impl Envelope {
+    /// Wrap `event` for publishing
+    pub fn new<E: Event>(id: u64, source: &str, event: &E) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            id,
+            topic: event.topic().to_string(),
+            version: E::VERSION,
+            timestamp: now(),
+            source: source.to_string(),
+            priority: event.priority(),
+            key: event.key(),
+            correlation_id: None,
+            payload: serde_json::to_value(event)?,
+        })
+    }
+
+    /// A reply to this request carrying `payload`
+    pub(crate) fn reply(&self, id: u64, source: &str, payload: serde_json::Value) -> Self {
+        Self {
+            id,
+            topic: REPLY_TOPIC.to_string(),
+            version: 1,
+            timestamp: now(),
+            source: source.to_string(),
+            priority: Priority::Control,
+            key: None,
+            correlation_id: self.correlation_id,
+            payload,
+        }
+    }
+
+    /// Read the payload as `E`, failing if it was written by a newer
+    /// version of `E` or is not an `E` at all
+    pub fn decode<E: Event>(&self) -> Result<E, DecodeError> {
+        if self.version > E::VERSION {
+            return Err(DecodeError::UnsupportedVersion {
+                topic: self.topic.clone(),
+                version: self.version,
+                supported: E::VERSION,
+            });
+        }
+        let event: E = serde_json::from_value(self.payload.clone())
+            .map_err(|e| DecodeError::Payload(e.to_string()))?;
+        if event.topic() != self.topic {
+            return Err(DecodeError::TopicMismatch {
+                envelope: self.topic.clone(),
+                payload: event.topic().to_string(),
+            });
+        }
+        Ok(event)
+    }
+}
+
+/// Milliseconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Why an envelope could not be decoded into the requested type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload was written by a newer schema than the reader knows
+    UnsupportedVersion {
+        topic: String,
+        version: u32,
+        supported: u32,
+    },
+    /// The payload does not deserialize into the requested type
+    Payload(String),
+    /// The payload deserialized, but belongs to another topic
+    TopicMismatch { envelope: String, payload: String },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion {
+                topic,
+                version,
+                supported,
+            } => write!(
+                f,
+                "{} has schema version {}, only {} and older are supported",
+                topic, version, supported
+            ),
+            DecodeError::Payload(e) => write!(f, "Invalid event payload: {}", e),
+            DecodeError::TopicMismatch { envelope, payload } => write!(
+                f,
+                "Envelope topic {} does not match payload topic {}",
+                envelope, payload
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a plugin's own event looks like
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct BuildFinished {
+        target: String,
+        #[serde(default)]
+        warnings: u32,
+    }
+
+    impl Event for BuildFinished {
+        const VERSION: u32 = 2;
+
+        fn topic(&self) -> &str {
+            "plugin.build.finished"
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Deployed {
+        target: String,
+    }
+
+    impl Event for Deployed {
+        const VERSION: u32 = 1;
+
+        fn topic(&self) -> &str {
+            "plugin.deploy.finished"
+        }
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let event = BuildFinished {
+            target: "release".to_string(),
+            warnings: 3,
+        };
+        let envelope = Envelope::new(7, "build-plugin", &event).unwrap();
+        assert_eq!(envelope.topic, "plugin.build.finished");
+        assert_eq!(envelope.version, 2);
+        assert_eq!(envelope.source, "build-plugin");
+
+        let line = serde_json::to_string(&envelope).unwrap();
+        let parsed: Envelope = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.decode::<BuildFinished>().unwrap(), event);
+    }
+
+    #[test]
+    fn test_decode_checks_version_and_topic() {
+        let mut envelope = Envelope::new(
+            1,
+            "test",
+            &BuildFinished {
+                target: "debug".to_string(),
+                warnings: 0,
+            },
+        )
+        .unwrap();
+
+        // An older payload without `warnings` still reads
+        envelope.version = 1;
+        envelope.payload = serde_json::json!({"target": "debug"});
+        assert_eq!(envelope.decode::<BuildFinished>().unwrap().warnings, 0);
+
+        envelope.version = 3;
+        assert_eq!(
+            envelope.decode::<BuildFinished>(),
+            Err(DecodeError::UnsupportedVersion {
+                topic: "plugin.build.finished".to_string(),
+                version: 3,
+                supported: 2,
+            })
+        );
+
+        // Same shape, different event
+        envelope.version = 1;
+        assert!(matches!(
+            envelope.decode::<Deployed>(),
+            Err(DecodeError::TopicMismatch { .. })
+        ));
+        envelope.payload = serde_json::json!({"name": "x"});
+        assert!(matches!(
+            envelope.decode::<BuildFinished>(),
+            Err(DecodeError::Payload(_))
+        ));
+    }
+}
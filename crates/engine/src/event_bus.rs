@@ -1,15 +1,34 @@
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use log::debug;
+use crate::envelope::{Envelope, Event, Priority};
+use crate::event_queue::{queue, EventQueue, QueueConfig, QueueSender, QueueStats};
+use crate::rpc::{PendingReply, RequestError, REPLY_TOPIC};
+use crossbeam_channel::{bounded, RecvError, RecvTimeoutError, SendError, Sender, TryRecvError};
+use log::{debug, error};
+use notifications::FinishedCommand;
+use resources::SessionUsage;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 
-/// Event bus for inter-component communication using crossbeam channels
+/// Event bus for inter-component communication using crossbeam channels.
+///
+/// Events travel as `Envelope`s, each with a dotted topic such as
+/// `gpu.limit_changed`. Consumers `subscribe` with a pattern and each gets
+/// its own copy of every matching event. The bus also has a queue of its
+/// own (`recv`, `try_recv`, `receiver`) that receives every event; callers
+/// of it share that queue. Queues are bounded; see `QueueConfig`.
 #[derive(Debug, Clone)]
 pub struct EventBus {
-    sender: Sender<AppEvent>,
-    receiver: Receiver<AppEvent>,
+    registry: Arc<Mutex<Registry>>,
+    queue: EventQueue,
 }
 
-/// Events that can be sent through the event bus
-#[derive(Debug, Clone)]
+/// The app's own events. Plugins define their own types instead, see
+/// `Event`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum AppEvent {
     GpuLimitChanged(u8),
     ThemeToggled(bool), // true = dark mode
@@ -19,39 +38,539 @@ pub enum AppEvent {
     ConfigSaveRequested,
     LogMessage(String),
     QuitRequested,
+    CommandFinished(FinishedCommand), // a command in a terminal tab completed
+    ResourcesSampled(Vec<SessionUsage>), // latest usage of each terminal tab's processes
+}
+
+impl AppEvent {
+    /// Every topic an app event can have
+    pub const TOPICS: &'static [&'static str] = &[
+        "gpu.limit_changed",
+        "theme.toggled",
+        "voice.toggled",
+        "wizard.opened",
+        "wizard.closed",
+        "config.save_requested",
+        "log.message",
+        "app.quit_requested",
+        "terminal.command.finished",
+        "terminal.resources.sampled",
+    ];
+
+    /// Dotted topic that subscription patterns are matched against
+    pub fn topic(&self) -> &'static str {
+        match self {
+            AppEvent::GpuLimitChanged(_) => "gpu.limit_changed",
+            AppEvent::ThemeToggled(_) => "theme.toggled",
+            AppEvent::VoiceToggled(_) => "voice.toggled",
+            AppEvent::WizardOpened => "wizard.opened",
+            AppEvent::WizardClosed => "wizard.closed",
+            AppEvent::ConfigSaveRequested => "config.save_requested",
+            AppEvent::LogMessage(_) => "log.message",
+            AppEvent::QuitRequested => "app.quit_requested",
+            AppEvent::CommandFinished(_) => "terminal.command.finished",
+            AppEvent::ResourcesSampled(_) => "terminal.resources.sampled",
+        }
+    }
+}
+
+impl Event for AppEvent {
+    const VERSION: u32 = 1;
+
+    fn topic(&self) -> &str {
+        AppEvent::topic(self)
+    }
+
+    fn priority(&self) -> Priority {
+        match self {
+            AppEvent::LogMessage(_)
+            | AppEvent::CommandFinished(_)
+            | AppEvent::ResourcesSampled(_) => Priority::Bulk,
+            _ => Priority::Control,
+        }
+    }
+
+    fn key(&self) -> Option<String> {
+        match self {
+            // Each sample covers every tab, so only the latest matters
+            AppEvent::ResourcesSampled(_) => Some("all".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Why a subscription pattern was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    Empty,
+    /// Only `[A-Za-z0-9_.*]` may appear in a pattern
+    InvalidChar(char),
+    /// A segment between dots is empty, as in `gpu..changed`
+    EmptySegment,
+    /// A wildcard shares its segment with other text, as in `gpu*`
+    PartialWildcard(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::Empty => write!(f, "Pattern is empty"),
+            PatternError::InvalidChar(c) => write!(f, "Invalid character {:?} in pattern", c),
+            PatternError::EmptySegment => write!(f, "Pattern has an empty segment"),
+            PatternError::PartialWildcard(segment) => {
+                write!(f, "Wildcard must be a whole segment, got {:?}", segment)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    /// `*`: exactly one segment
+    Single,
+    /// `**`: any number of segments, including none
+    Multi,
+}
+
+/// A subscription pattern compiled for matching, e.g. `gpu.*` or
+/// `terminal.**`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// Parse a pattern; consecutive `**` segments collapse into one
+    pub fn compile(pattern: &str) -> Result<Self, PatternError> {
+        if pattern.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        if let Some(c) = pattern
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '*')))
+        {
+            return Err(PatternError::InvalidChar(c));
+        }
+
+        let mut segments = Vec::new();
+        for segment in pattern.split('.') {
+            let compiled = match segment {
+                "" => return Err(PatternError::EmptySegment),
+                "*" => Segment::Single,
+                "**" => Segment::Multi,
+                s if s.contains('*') => return Err(PatternError::PartialWildcard(s.to_string())),
+                s => Segment::Literal(s.to_string()),
+            };
+            if compiled == Segment::Multi && segments.last() == Some(&Segment::Multi) {
+                continue;
+            }
+            segments.push(compiled);
+        }
+
+        Ok(Self {
+            source: pattern.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The literal a matching topic must start with, if any
+    fn first_literal(&self) -> Option<&str> {
+        match self.segments.first() {
+            Some(Segment::Literal(literal)) => Some(literal),
+            _ => None,
+        }
+    }
+
+    /// Whether `topic` matches. `**` is matched by backtracking to the most
+    /// recent one, so this stays linear in practice without recursion.
+    pub fn matches(&self, topic: &str) -> bool {
+        let topic: Vec<&str> = topic.split('.').collect();
+        let (mut p, mut t) = (0, 0);
+        // Pattern index after the last `**`, and the topic index it resumes at
+        let mut resume: Option<(usize, usize)> = None;
+
+        while t < topic.len() {
+            match self.segments.get(p) {
+                Some(Segment::Multi) => {
+                    resume = Some((p + 1, t));
+                    p += 1;
+                    continue;
+                }
+                Some(Segment::Single) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                Some(Segment::Literal(literal)) if literal == topic[t] => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            // Let the last `**` swallow one more segment and try again
+            match resume {
+                Some((after, from)) => {
+                    resume = Some((after, from + 1));
+                    p = after;
+                    t = from + 1;
+                }
+                None => return false,
+            }
+        }
+
+        self.segments[p..].iter().all(|s| *s == Segment::Multi)
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    id: u64,
+    pattern: Pattern,
+    /// Shared with publishers that are delivering to it without the lock
+    sender: Arc<QueueSender>,
+}
+
+/// Subscriptions indexed by the first segment of their pattern, so an
+/// event is only matched against patterns that can match its topic
+#[derive(Debug, Default)]
+pub(crate) struct Registry {
+    next_subscription_id: u64,
+    next_event_id: u64,
+    by_first_literal: HashMap<String, Vec<Subscription>>,
+    /// Patterns starting with a wildcard
+    wildcard: Vec<Subscription>,
+    /// Requests waiting for a reply, by correlation id
+    pending: HashMap<u64, Sender<Envelope>>,
+}
+
+impl Registry {
+    fn add(&mut self, pattern: Pattern, config: QueueConfig) -> (u64, EventQueue) {
+        let (sender, receiver) = queue(config);
+        self.next_subscription_id += 1;
+        let subscription = Subscription {
+            id: self.next_subscription_id,
+            pattern,
+            sender: Arc::new(sender),
+        };
+        match subscription.pattern.first_literal() {
+            Some(literal) => self
+                .by_first_literal
+                .entry(literal.to_string())
+                .or_default()
+                .push(subscription),
+            None => self.wildcard.push(subscription),
+        }
+        (self.next_subscription_id, receiver)
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.wildcard.retain(|s| s.id != id);
+        self.by_first_literal.retain(|_, subscriptions| {
+            subscriptions.retain(|s| s.id != id);
+            !subscriptions.is_empty()
+        });
+    }
+
+    pub(crate) fn cancel_request(&mut self, correlation_id: u64) {
+        self.pending.remove(&correlation_id);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.wildcard.is_empty() && self.by_first_literal.is_empty()
+    }
+
+    fn stats(&self) -> Vec<(String, QueueStats)> {
+        let mut subscriptions: Vec<&Subscription> = self
+            .wildcard
+            .iter()
+            .chain(self.by_first_literal.values().flatten())
+            .collect();
+        subscriptions.sort_by_key(|s| s.id);
+        subscriptions
+            .into_iter()
+            .map(|s| (s.pattern.as_str().to_string(), s.sender.stats()))
+            .collect()
+    }
+
+    /// Return the queues of the subscribers `envelope` goes to, dropping
+    /// subscriptions whose receiver is gone. The caller pushes to them after
+    /// releasing the lock, since a `Block` queue may wait for a consumer
+    /// that publishes itself; see `deliver`.
+    fn route(&mut self, envelope: &Envelope) -> Vec<Arc<QueueSender>> {
+        if envelope.topic == REPLY_TOPIC {
+            if let Some(waiting) = envelope
+                .correlation_id
+                .and_then(|id| self.pending.remove(&id))
+            {
+                // The requester may have stopped waiting already
+                let _ = waiting.send(envelope.clone());
+            }
+        }
+
+        let topic = envelope.topic.as_str();
+        let first = topic.split('.').next().unwrap_or(topic);
+
+        let mut targets = Vec::new();
+        let mut collect = |subscriptions: &mut Vec<Subscription>| {
+            subscriptions.retain(|s| !s.sender.is_closed());
+            targets.extend(
+                subscriptions
+                    .iter()
+                    .filter(|s| s.pattern.matches(topic))
+                    .map(|s| Arc::clone(&s.sender)),
+            );
+        };
+        collect(&mut self.wildcard);
+        if let Some(subscriptions) = self.by_first_literal.get_mut(first) {
+            collect(subscriptions);
+            if subscriptions.is_empty() {
+                self.by_first_literal.remove(first);
+            }
+        }
+        targets
+    }
+}
+
+/// Give each of `targets` a copy of `envelope`. Called without the registry
+/// lock, so events published concurrently from different threads may reach
+/// a subscriber out of id order; those from one thread stay in order.
+fn deliver(targets: Vec<Arc<QueueSender>>, envelope: &Envelope) {
+    for target in targets {
+        target.push(envelope.clone());
+    }
+}
+
+/// Publishes events to every subscriber of the bus they came from,
+/// stamped with the sender's source
+#[derive(Debug, Clone)]
+pub struct EventSender {
+    registry: Arc<Mutex<Registry>>,
+    source: String,
+}
+
+impl EventSender {
+    /// A sender whose events name `source` as their publisher
+    pub fn with_source(&self, source: &str) -> Self {
+        Self {
+            registry: Arc::clone(&self.registry),
+            source: source.to_string(),
+        }
+    }
+
+    /// Publish an app event
+    pub fn send(&self, event: AppEvent) -> Result<(), SendError<AppEvent>> {
+        self.publish(event)
+    }
+
+    /// Publish an event of any type. Fails when the event cannot be
+    /// serialized or nobody is subscribed to the bus any more, e.g.
+    /// because the bus was dropped.
+    pub fn publish<E: Event>(&self, event: E) -> Result<(), SendError<E>> {
+        self.publish_envelope(event, None).map(|_| ())
+    }
+
+    /// Publish `event` as a request whose handler answers with `reply`,
+    /// typed as `R`. Poll the returned `PendingReply` for the answer; it
+    /// fails with `RequestError::Timeout` after `timeout`.
+    pub fn request<E: Event, R: DeserializeOwned>(
+        &self,
+        event: E,
+        timeout: Duration,
+    ) -> Result<PendingReply<R>, SendError<E>> {
+        let (sender, receiver) = bounded(1);
+        let deadline = Instant::now() + timeout;
+        let id = self.publish_envelope(event, Some(sender))?;
+        Ok(PendingReply::new(
+            id,
+            receiver,
+            deadline,
+            Arc::downgrade(&self.registry),
+        ))
+    }
+
+    /// Answer `request` with `result`. Does nothing unless it was sent
+    /// with `request`.
+    pub fn reply<R: Serialize>(&self, request: &Envelope, result: Result<R, RequestError>) {
+        if request.correlation_id.is_none() {
+            return;
+        }
+        let payload = serde_json::to_value(&result).unwrap_or_else(|e| {
+            error!("Failed to serialize reply to {}: {}", request.topic, e);
+            let failed: Result<(), _> = Err(RequestError::Failed(e.to_string()));
+            serde_json::to_value(failed).unwrap_or_default()
+        });
+
+        let (envelope, targets) = {
+            let mut registry = self.registry.lock().unwrap();
+            registry.next_event_id += 1;
+            let envelope = request.reply(registry.next_event_id, &self.source, payload);
+            let targets = registry.route(&envelope);
+            (envelope, targets)
+        };
+        deliver(targets, &envelope);
+    }
+
+    /// Publish `event`, routing its reply to `reply` if given. Returns the
+    /// event's id.
+    fn publish_envelope<E: Event>(
+        &self,
+        event: E,
+        reply: Option<Sender<Envelope>>,
+    ) -> Result<u64, SendError<E>> {
+        debug!("Sending event {}: {:?}", event.topic(), event);
+        let mut registry = self.registry.lock().unwrap();
+        registry.next_event_id += 1;
+        let id = registry.next_event_id;
+        let mut envelope = match Envelope::new(id, &self.source, &event) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                error!("Failed to serialize {} event: {}", event.topic(), e);
+                return Err(SendError(event));
+            }
+        };
+        if let Some(reply) = reply {
+            envelope.correlation_id = Some(id);
+            registry.pending.insert(id, reply);
+        }
+
+        let targets = registry.route(&envelope);
+        if registry.is_empty() {
+            registry.pending.remove(&id);
+            return Err(SendError(event));
+        }
+        drop(registry);
+
+        deliver(targets, &envelope);
+        Ok(id)
+    }
+}
+
+/// A consumer's own queue of the events matching its pattern. Dropping it
+/// unsubscribes.
+#[derive(Debug)]
+pub struct Subscriber {
+    id: u64,
+    pattern: String,
+    queue: EventQueue,
+    registry: Weak<Mutex<Registry>>,
+}
+
+impl Subscriber {
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Try to receive an event (non-blocking)
+    pub fn try_recv(&self) -> Result<Envelope, TryRecvError> {
+        self.queue.try_recv()
+    }
+
+    /// Receive an event (blocking)
+    pub fn recv(&self) -> Result<Envelope, RecvError> {
+        self.queue.recv()
+    }
+
+    /// Receive an event, waiting at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Envelope, RecvTimeoutError> {
+        self.queue.recv_timeout(timeout)
+    }
+
+    /// How many events are waiting and how many were lost
+    pub fn stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        // Wake a publisher blocked on this queue
+        self.queue.close();
+        if let Some(registry) = self.registry.upgrade() {
+            registry.lock().unwrap().remove(self.id);
+        }
+    }
 }
 
 impl EventBus {
-    /// Create a new event bus
+    /// Create a new event bus whose own queue has the default size and
+    /// drops the oldest events when full
     pub fn new() -> Self {
-        let (sender, receiver) = unbounded();
-        Self { sender, receiver }
+        Self::with_queue(QueueConfig::default())
+    }
+
+    /// Create a new event bus with its own queue configured by `config`
+    pub fn with_queue(config: QueueConfig) -> Self {
+        let mut registry = Registry::default();
+        let (_, queue) = registry.add(Pattern::compile("**").unwrap(), config);
+        Self {
+            registry: Arc::new(Mutex::new(registry)),
+            queue,
+        }
+    }
+
+    /// Get a sender for publishing events, with `app` as their source
+    pub fn sender(&self) -> EventSender {
+        EventSender {
+            registry: Arc::clone(&self.registry),
+            source: "app".to_string(),
+        }
+    }
+
+    /// Get a receiver for the bus's own queue of every event
+    pub fn receiver(&self) -> EventQueue {
+        self.queue.clone()
+    }
+
+    /// Receive copies of the events whose topic matches `pattern`. Segments
+    /// are separated by dots; `*` matches one segment and `**` any number,
+    /// so `terminal.**` matches `terminal.command.finished`.
+    pub fn subscribe(&self, pattern: &str) -> Result<Subscriber, PatternError> {
+        self.subscribe_with(pattern, QueueConfig::default())
     }
 
-    /// Get a sender for publishing events
-    pub fn sender(&self) -> Sender<AppEvent> {
-        self.sender.clone()
+    /// Like `subscribe`, with a queue configured by `config`
+    pub fn subscribe_with(
+        &self,
+        pattern: &str,
+        config: QueueConfig,
+    ) -> Result<Subscriber, PatternError> {
+        let compiled = Pattern::compile(pattern)?;
+        let (id, queue) = self.registry.lock().unwrap().add(compiled, config);
+        Ok(Subscriber {
+            id,
+            pattern: pattern.to_string(),
+            queue,
+            registry: Arc::downgrade(&self.registry),
+        })
     }
 
-    /// Get a receiver for consuming events
-    pub fn receiver(&self) -> Receiver<AppEvent> {
-        self.receiver.clone()
+    /// Depth counters of every queue on the bus, its own first, by pattern
+    pub fn queue_stats(&self) -> Vec<(String, QueueStats)> {
+        self.registry.lock().unwrap().stats()
     }
 
     /// Send an event to the bus
-    pub fn send(&self, event: AppEvent) -> Result<(), crossbeam_channel::SendError<AppEvent>> {
-        debug!("Sending event: {:?}", event);
-        self.sender.send(event)
+    pub fn send(&self, event: AppEvent) -> Result<(), SendError<AppEvent>> {
+        self.sender().send(event)
     }
 
     /// Try to receive an event from the bus (non-blocking)
-    pub fn try_recv(&self) -> Result<AppEvent, crossbeam_channel::TryRecvError> {
-        self.receiver.try_recv()
+    pub fn try_recv(&self) -> Result<Envelope, TryRecvError> {
+        self.queue.try_recv()
     }
 
     /// Receive an event from the bus (blocking)
-    pub fn recv(&self) -> Result<AppEvent, crossbeam_channel::RecvError> {
-        self.receiver.recv()
+    pub fn recv(&self) -> Result<Envelope, RecvError> {
+        self.queue.recv()
     }
 }
 
@@ -64,6 +583,13 @@ impl Default for EventBus {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::event_queue::Overflow;
+    use std::thread;
+
+    /// Decode the next app event from a queue
+    fn next(receiver: &EventQueue) -> AppEvent {
+        receiver.try_recv().unwrap().decode().unwrap()
+    }
 
     #[test]
     fn test_event_bus_basic_send_receive() {
@@ -75,13 +601,10 @@ mod tests {
         sender.send(test_event.clone()).unwrap();
 
         // Receive the event
-        let received_event = event_bus.try_recv().unwrap();
+        let received_event = next(&event_bus.queue);
 
-        // Verify it's the same event (we can't use PartialEq directly, so match)
-        match (test_event, received_event) {
-            (AppEvent::GpuLimitChanged(a), AppEvent::GpuLimitChanged(b)) => assert_eq!(a, b),
-            _ => panic!("Events don't match"),
-        }
+        // Verify it's the same event
+        assert_eq!(test_event, received_event);
     }
 
     #[test]
@@ -95,17 +618,17 @@ mod tests {
         sender.send(AppEvent::VoiceToggled(false)).unwrap();
 
         // Receive events in order
-        match event_bus.try_recv().unwrap() {
+        match next(&event_bus.queue) {
             AppEvent::GpuLimitChanged(25) => {}
             _ => panic!("First event should be GpuLimitChanged(25)"),
         }
 
-        match event_bus.try_recv().unwrap() {
+        match next(&event_bus.queue) {
             AppEvent::ThemeToggled(true) => {}
             _ => panic!("Second event should be ThemeToggled(true)"),
         }
 
-        match event_bus.try_recv().unwrap() {
+        match next(&event_bus.queue) {
             AppEvent::VoiceToggled(false) => {}
             _ => panic!("Third event should be VoiceToggled(false)"),
         }
@@ -129,8 +652,8 @@ mod tests {
             .unwrap();
 
         // Should receive both messages
-        let msg1 = event_bus.try_recv().unwrap();
-        let msg2 = event_bus.try_recv().unwrap();
+        let msg1 = next(&event_bus.queue);
+        let msg2 = next(&event_bus.queue);
 
         match (msg1, msg2) {
             (AppEvent::LogMessage(m1), AppEvent::LogMessage(m2)) => {
@@ -141,4 +664,183 @@ mod tests {
             _ => panic!("Should receive LogMessage events"),
         }
     }
+
+    #[test]
+    fn test_pattern_compile() {
+        assert_eq!(Pattern::compile(""), Err(PatternError::Empty));
+        assert_eq!(Pattern::compile("gpu..x"), Err(PatternError::EmptySegment));
+        assert_eq!(Pattern::compile("gpu."), Err(PatternError::EmptySegment));
+        assert_eq!(
+            Pattern::compile("gpu/x"),
+            Err(PatternError::InvalidChar('/'))
+        );
+        assert_eq!(
+            Pattern::compile("gpu*"),
+            Err(PatternError::PartialWildcard("gpu*".to_string()))
+        );
+        assert_eq!(
+            Pattern::compile("a.***"),
+            Err(PatternError::PartialWildcard("***".to_string()))
+        );
+
+        // Consecutive `**` collapse into one
+        let pattern = Pattern::compile("terminal.**.**.finished").unwrap();
+        assert_eq!(pattern.segments.len(), 3);
+        assert_eq!(pattern.as_str(), "terminal.**.**.finished");
+        assert_eq!(
+            pattern,
+            Pattern::compile("terminal.**.**.finished").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        let matches =
+            |pattern: &str, topic: &str| Pattern::compile(pattern).unwrap().matches(topic);
+
+        assert!(matches("gpu.limit_changed", "gpu.limit_changed"));
+        assert!(!matches("gpu.limit_changed", "gpu.limit"));
+        assert!(!matches("gpu", "gpu.limit_changed"));
+
+        assert!(matches("gpu.*", "gpu.limit_changed"));
+        assert!(!matches("gpu.*", "gpu"));
+        assert!(!matches("gpu.*", "terminal.command.finished"));
+        assert!(matches("*.toggled", "theme.toggled"));
+        assert!(!matches("*.toggled", "a.b.toggled"));
+
+        assert!(matches("**", "log.message"));
+        assert!(matches("terminal.**", "terminal.command.finished"));
+        assert!(matches("terminal.**", "terminal"));
+        assert!(matches("**.finished", "terminal.command.finished"));
+        assert!(matches("**.finished", "finished"));
+        assert!(!matches("**.finished", "terminal.finished.not"));
+        assert!(matches("a.**.b.**.c", "a.x.b.y.z.c"));
+        assert!(matches("a.**.b.**.c", "a.b.c"));
+        assert!(!matches("a.**.b.**.c", "a.c.b"));
+        assert!(matches("**.*.c", "a.b.c"));
+        assert!(!matches("**.*.c", "c"));
+    }
+
+    #[test]
+    fn test_subscribers_each_get_a_copy() {
+        let event_bus = EventBus::new();
+        let gpu = event_bus.subscribe("gpu.*").unwrap();
+        let everything = event_bus.subscribe("**").unwrap();
+        let terminal = event_bus.subscribe("terminal.**").unwrap();
+        assert_eq!(gpu.pattern(), "gpu.*");
+        assert!(event_bus.subscribe("gpu*").is_err());
+
+        let sender = event_bus.sender();
+        sender.send(AppEvent::GpuLimitChanged(50)).unwrap();
+        sender.send(AppEvent::ThemeToggled(true)).unwrap();
+
+        assert_eq!(next(&gpu.queue), AppEvent::GpuLimitChanged(50));
+        assert!(gpu.try_recv().is_err());
+        assert_eq!(next(&everything.queue), AppEvent::GpuLimitChanged(50));
+        assert_eq!(next(&everything.queue), AppEvent::ThemeToggled(true));
+        assert!(terminal.try_recv().is_err());
+
+        // The bus's own queue still sees everything
+        assert_eq!(next(&event_bus.queue), AppEvent::GpuLimitChanged(50));
+        assert_eq!(next(&event_bus.queue), AppEvent::ThemeToggled(true));
+    }
+
+    #[test]
+    fn test_envelopes_carry_id_and_source() {
+        let event_bus = EventBus::new();
+        let voice = event_bus.sender().with_source("voice");
+        event_bus.sender().send(AppEvent::WizardOpened).unwrap();
+        voice
+            .send(AppEvent::LogMessage("hello".to_string()))
+            .unwrap();
+
+        let first = event_bus.try_recv().unwrap();
+        let second = event_bus.try_recv().unwrap();
+        assert_eq!(first.source, "app");
+        assert_eq!(second.source, "voice");
+        assert_eq!(second.topic, "log.message");
+        assert!(second.id > first.id);
+        assert_eq!(
+            second.payload,
+            serde_json::json!({ "type": "log_message", "data": "hello" })
+        );
+    }
+
+    #[test]
+    fn test_queue_stats_by_pattern() {
+        let event_bus = EventBus::new();
+        let samples = event_bus
+            .subscribe_with(
+                "terminal.resources.*",
+                QueueConfig {
+                    capacity: 1,
+                    overflow: Overflow::Coalesce,
+                },
+            )
+            .unwrap();
+        let sender = event_bus.sender();
+        sender.send(AppEvent::ResourcesSampled(Vec::new())).unwrap();
+        sender.send(AppEvent::ResourcesSampled(Vec::new())).unwrap();
+        sender.send(AppEvent::QuitRequested).unwrap();
+
+        let stats = event_bus.queue_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].0, "**");
+        assert_eq!((stats[0].1.control, stats[0].1.bulk), (1, 2));
+        assert_eq!(stats[1].0, "terminal.resources.*");
+        assert_eq!(stats[1].1, samples.stats());
+        assert_eq!((samples.stats().bulk, samples.stats().coalesced), (1, 1));
+
+        // The quit request overtakes the samples
+        assert_eq!(next(&event_bus.queue), AppEvent::QuitRequested);
+    }
+
+    #[test]
+    fn test_blocked_publisher_does_not_hold_up_the_bus() {
+        let event_bus = EventBus::new();
+        let limits = event_bus
+            .subscribe_with(
+                "gpu.*",
+                QueueConfig {
+                    capacity: 1,
+                    overflow: Overflow::Block,
+                },
+            )
+            .unwrap();
+
+        // The handler publishes while the publisher waits for room in its
+        // queue
+        let handler = event_bus.sender().with_source("gpu");
+        let (done, finished) = bounded(1);
+        thread::spawn(move || {
+            for _ in 0..3 {
+                let envelope = limits.recv().unwrap();
+                thread::sleep(Duration::from_millis(20));
+                let message = format!("Handled event {}", envelope.id);
+                handler.send(AppEvent::LogMessage(message)).unwrap();
+            }
+            done.send(()).unwrap();
+        });
+        let sender = event_bus.sender();
+        thread::spawn(move || {
+            for limit in [25, 50, 75] {
+                sender.send(AppEvent::GpuLimitChanged(limit)).unwrap();
+            }
+        });
+
+        assert!(finished.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn test_dropping_subscriber_unsubscribes() {
+        let event_bus = EventBus::new();
+        let sender = event_bus.sender();
+        let subscriber = event_bus.subscribe("log.*").unwrap();
+        drop(subscriber);
+        assert_eq!(event_bus.registry.lock().unwrap().by_first_literal.len(), 0);
+
+        // With the bus gone nobody can receive, so sending fails
+        drop(event_bus);
+        assert!(sender.send(AppEvent::WizardOpened).is_err());
+    }
 }
@@ -0,0 +1,409 @@
+use crate::envelope::{Envelope, Priority};
+use crossbeam_channel::{RecvError, RecvTimeoutError, TryRecvError};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// What a full queue lane does with another event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// The publisher waits until the subscriber makes room; the rest of the
+    /// bus keeps going meanwhile. Not for queues drained by the thread that
+    /// publishes to them.
+    Block,
+    /// The oldest waiting event is dropped
+    #[default]
+    DropOldest,
+    /// The new event is dropped
+    DropNewest,
+    /// The waiting event with the same topic and `Event::key` as the new
+    /// one is dropped, so only the latest of each is kept. Events without a
+    /// key are never merged; for them the oldest waiting event is dropped.
+    Coalesce,
+}
+
+/// Size and overflow policy of a subscriber's queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Events each priority lane holds
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: Overflow::DropOldest,
+        }
+    }
+}
+
+/// Depth counters of a queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+    /// Control events waiting
+    pub control: usize,
+    /// Bulk events waiting
+    pub bulk: usize,
+    /// Events each lane holds
+    pub capacity: usize,
+    /// Events lost to overflow
+    pub dropped: u64,
+    /// Events replaced by a newer one with the same topic and key
+    pub coalesced: u64,
+}
+
+#[derive(Debug, Default)]
+struct Lanes {
+    control: VecDeque<Envelope>,
+    bulk: VecDeque<Envelope>,
+    dropped: u64,
+    coalesced: u64,
+    /// Nothing will drain the queue any more
+    closed: bool,
+    /// Nothing will fill the queue any more
+    disconnected: bool,
+}
+
+impl Lanes {
+    fn lane(&mut self, priority: Priority) -> &mut VecDeque<Envelope> {
+        match priority {
+            Priority::Control => &mut self.control,
+            Priority::Bulk => &mut self.bulk,
+        }
+    }
+
+    fn pop(&mut self) -> Option<Envelope> {
+        self.control.pop_front().or_else(|| self.bulk.pop_front())
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: QueueConfig,
+    lanes: Mutex<Lanes>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl Shared {
+    fn stats(&self) -> QueueStats {
+        let lanes = self.lanes.lock().unwrap();
+        QueueStats {
+            control: lanes.control.len(),
+            bulk: lanes.bulk.len(),
+            capacity: self.config.capacity,
+            dropped: lanes.dropped,
+            coalesced: lanes.coalesced,
+        }
+    }
+
+    fn close(&self) {
+        self.lanes.lock().unwrap().closed = true;
+        self.not_full.notify_all();
+    }
+
+    /// Hand out the next event, control lane first
+    fn take(&self, mut lanes: MutexGuard<'_, Lanes>) -> Option<Envelope> {
+        let envelope = lanes.pop()?;
+        drop(lanes);
+        self.not_full.notify_all();
+        Some(envelope)
+    }
+}
+
+/// A new queue, as the bus's end and the subscriber's end
+pub(crate) fn queue(config: QueueConfig) -> (QueueSender, EventQueue) {
+    let shared = Arc::new(Shared {
+        config,
+        lanes: Mutex::new(Lanes::default()),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    let receiver = EventQueue {
+        shared: Arc::clone(&shared),
+        _handle: Arc::new(ReceiverHandle(Arc::clone(&shared))),
+    };
+    (QueueSender(shared), receiver)
+}
+
+/// The bus's end of a queue. Dropping it disconnects the queue.
+#[derive(Debug)]
+pub(crate) struct QueueSender(Arc<Shared>);
+
+impl QueueSender {
+    /// Queue `envelope` according to the overflow policy. Returns false
+    /// once the receiving end is gone.
+    pub(crate) fn push(&self, envelope: Envelope) -> bool {
+        let shared = &self.0;
+        let capacity = shared.config.capacity.max(1);
+        let priority = envelope.priority;
+        let mut lanes = shared.lanes.lock().unwrap();
+        loop {
+            if lanes.closed {
+                return false;
+            }
+            if lanes.lane(priority).len() < capacity {
+                break;
+            }
+            match shared.config.overflow {
+                Overflow::Block => lanes = shared.not_full.wait(lanes).unwrap(),
+                Overflow::DropOldest => {
+                    lanes.lane(priority).pop_front();
+                    lanes.dropped += 1;
+                    break;
+                }
+                Overflow::DropNewest => {
+                    lanes.dropped += 1;
+                    return true;
+                }
+                Overflow::Coalesce => {
+                    let lane = lanes.lane(priority);
+                    let same = envelope.key.as_ref().and_then(|key| {
+                        lane.iter()
+                            .position(|e| e.topic == envelope.topic && e.key.as_ref() == Some(key))
+                    });
+                    match same {
+                        Some(index) => {
+                            lane.remove(index);
+                            lanes.coalesced += 1;
+                        }
+                        None => {
+                            lane.pop_front();
+                            lanes.dropped += 1;
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+
+        lanes.lane(priority).push_back(envelope);
+        drop(lanes);
+        shared.not_empty.notify_one();
+        true
+    }
+
+    pub(crate) fn stats(&self) -> QueueStats {
+        self.0.stats()
+    }
+
+    /// Whether the receiving end is gone
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.lanes.lock().unwrap().closed
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        self.0.lanes.lock().unwrap().disconnected = true;
+        self.0.not_empty.notify_all();
+    }
+}
+
+/// Closes the queue when the last `EventQueue` clone is dropped
+#[derive(Debug)]
+struct ReceiverHandle(Arc<Shared>);
+
+impl Drop for ReceiverHandle {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// The receiving end of a subscriber's queue. Clones share the queue.
+#[derive(Debug, Clone)]
+pub struct EventQueue {
+    shared: Arc<Shared>,
+    _handle: Arc<ReceiverHandle>,
+}
+
+impl EventQueue {
+    /// Try to receive an event (non-blocking)
+    pub fn try_recv(&self) -> Result<Envelope, TryRecvError> {
+        let lanes = self.shared.lanes.lock().unwrap();
+        let disconnected = lanes.disconnected;
+        match self.shared.take(lanes) {
+            Some(envelope) => Ok(envelope),
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Receive an event (blocking)
+    pub fn recv(&self) -> Result<Envelope, RecvError> {
+        let mut lanes = self.shared.lanes.lock().unwrap();
+        loop {
+            if !lanes.control.is_empty() || !lanes.bulk.is_empty() {
+                return self.shared.take(lanes).ok_or(RecvError);
+            }
+            if lanes.disconnected {
+                return Err(RecvError);
+            }
+            lanes = self.shared.not_empty.wait(lanes).unwrap();
+        }
+    }
+
+    /// Receive an event, waiting at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Envelope, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut lanes = self.shared.lanes.lock().unwrap();
+        loop {
+            if !lanes.control.is_empty() || !lanes.bulk.is_empty() {
+                return self
+                    .shared
+                    .take(lanes)
+                    .ok_or(RecvTimeoutError::Disconnected);
+            }
+            if lanes.disconnected {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            lanes = self
+                .shared
+                .not_empty
+                .wait_timeout(lanes, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// How many events are waiting and how many were lost
+    pub fn stats(&self) -> QueueStats {
+        self.shared.stats()
+    }
+
+    /// Stop accepting events, waking a publisher blocked on this queue
+    pub(crate) fn close(&self) {
+        self.shared.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_bus::AppEvent;
+    use std::thread;
+
+    fn envelope(event: AppEvent) -> Envelope {
+        Envelope::new(0, "test", &event).unwrap()
+    }
+
+    fn log(message: &str) -> Envelope {
+        envelope(AppEvent::LogMessage(message.to_string()))
+    }
+
+    /// A log message with a coalescing key, as a plugin event might have
+    fn keyed(message: &str, key: &str) -> Envelope {
+        let mut envelope = log(message);
+        envelope.key = Some(key.to_string());
+        envelope
+    }
+
+    fn messages(receiver: &EventQueue) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Ok(envelope) = receiver.try_recv() {
+            match envelope.decode().unwrap() {
+                AppEvent::LogMessage(m) => messages.push(m),
+                other => messages.push(format!("{:?}", other)),
+            }
+        }
+        messages
+    }
+
+    fn config(capacity: usize, overflow: Overflow) -> QueueConfig {
+        QueueConfig { capacity, overflow }
+    }
+
+    #[test]
+    fn test_control_events_overtake_bulk() {
+        let (sender, receiver) = queue(config(8, Overflow::DropOldest));
+        sender.push(log("a"));
+        sender.push(log("b"));
+        sender.push(envelope(AppEvent::QuitRequested));
+        assert_eq!(
+            receiver.stats(),
+            QueueStats {
+                control: 1,
+                bulk: 2,
+                capacity: 8,
+                dropped: 0,
+                coalesced: 0,
+            }
+        );
+        assert_eq!(messages(&receiver), ["QuitRequested", "a", "b"]);
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let (sender, receiver) = queue(config(2, Overflow::DropOldest));
+        for message in ["a", "b", "c"] {
+            sender.push(log(message));
+        }
+        assert_eq!(receiver.stats().dropped, 1);
+        assert_eq!(messages(&receiver), ["b", "c"]);
+
+        let (sender, receiver) = queue(config(2, Overflow::DropNewest));
+        for message in ["a", "b", "c"] {
+            sender.push(log(message));
+        }
+        // A full bulk lane does not hold back control events
+        sender.push(envelope(AppEvent::WizardOpened));
+        assert_eq!(receiver.stats().dropped, 1);
+        assert_eq!(messages(&receiver), ["WizardOpened", "a", "b"]);
+
+        let (sender, receiver) = queue(config(2, Overflow::Coalesce));
+        sender.push(keyed("tab 1 at 10%", "1"));
+        sender.push(keyed("tab 2 at 20%", "2"));
+        sender.push(keyed("tab 1 at 30%", "1"));
+        assert_eq!(receiver.stats().coalesced, 1);
+        // Events without a key are not merged with each other
+        sender.push(log("a"));
+        sender.push(log("b"));
+        let stats = receiver.stats();
+        assert_eq!((stats.coalesced, stats.dropped), (1, 2));
+        assert_eq!(messages(&receiver), ["a", "b"]);
+
+        let (sender, receiver) = queue(config(2, Overflow::Coalesce));
+        sender.push(keyed("tab 1 at 10%", "1"));
+        sender.push(log("a"));
+        sender.push(keyed("tab 1 at 30%", "1"));
+        assert_eq!(messages(&receiver), ["a", "tab 1 at 30%"]);
+    }
+
+    #[test]
+    fn test_block_waits_for_room() {
+        let (sender, receiver) = queue(config(1, Overflow::Block));
+        let publisher = thread::spawn(move || {
+            for message in ["a", "b", "c"] {
+                assert!(sender.push(log(message)));
+            }
+            sender
+        });
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let envelope = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            received.push(envelope.decode::<AppEvent>().unwrap());
+        }
+        assert_eq!(received[2], AppEvent::LogMessage("c".to_string()));
+
+        // Dropping the sender disconnects the queue
+        drop(publisher.join().unwrap());
+        assert_eq!(receiver.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn test_closing_unblocks_publisher() {
+        let (sender, receiver) = queue(config(1, Overflow::Block));
+        sender.push(log("a"));
+        let publisher = thread::spawn(move || sender.push(log("b")));
+        thread::sleep(Duration::from_millis(20));
+        drop(receiver);
+        assert!(!publisher.join().unwrap());
+    }
+}
@@ -0,0 +1,294 @@
+use crate::envelope::Envelope;
+use crate::{Config, SharedAppState};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// One line of a journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Microseconds since the journal was started, from a monotonic clock
+    pub offset_us: u64,
+    /// Number of the `process_events` call that handled the event; the
+    /// events of a frame are replayed together, in journal order
+    #[serde(default)]
+    pub frame: u64,
+    pub envelope: Envelope,
+}
+
+/// Writes the events `SharedAppState::process_events` handles to a
+/// JSON-lines file, in the order it took them from its queue
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+    frame: u64,
+}
+
+impl Journal {
+    /// Start a journal at `path`, replacing any previous one there
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(File::create(path)?),
+            started: Instant::now(),
+            frame: 0,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Start the entries of the next frame
+    pub(crate) fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Append `envelope` to the current frame, flushed so the journal
+    /// survives a crash
+    pub(crate) fn write(&mut self, envelope: &Envelope) -> io::Result<()> {
+        let entry = JournalEntry {
+            offset_us: self.started.elapsed().as_micros() as u64,
+            frame: self.frame,
+            envelope: envelope.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &entry)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+/// How fast a journal is replayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// With the gaps between events as recorded
+    Recorded,
+    /// With the recorded gaps divided by the factor
+    Accelerated(f64),
+    /// Without waiting between events
+    Immediate,
+}
+
+/// Feeds a recorded journal back into a `SharedAppState`
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    entries: Vec<JournalEntry>,
+}
+
+impl Replayer {
+    /// Read the journal at `path`
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Read a journal from `reader`, one entry per line
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Journal line {}: {}", index + 1, e),
+                )
+            })?;
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Handle the recorded frames one by one on a fresh state built from
+    /// `config`, each frame's events in the order they were handled live,
+    /// so the state goes through the recorded transitions. The state has no
+    /// side effects outside the app: the config file is not written and no
+    /// voice engine starts.
+    pub fn replay(&self, config: Config, speed: ReplaySpeed) -> SharedAppState {
+        let state = SharedAppState::for_replay(config);
+        let first = self.entries.first().map_or(0, |entry| entry.offset_us);
+        let started = Instant::now();
+
+        for frame in self.entries.chunk_by(|a, b| a.frame == b.frame) {
+            let recorded = Duration::from_micros(frame[0].offset_us.saturating_sub(first));
+            let due = match speed {
+                ReplaySpeed::Recorded => Some(recorded),
+                ReplaySpeed::Accelerated(factor) if factor > 0.0 => Some(recorded.div_f64(factor)),
+                ReplaySpeed::Accelerated(_) | ReplaySpeed::Immediate => None,
+            };
+            if let Some(wait) = due.and_then(|due| due.checked_sub(started.elapsed())) {
+                thread::sleep(wait);
+            }
+
+            let envelopes: Vec<Envelope> = frame.iter().map(|e| e.envelope.clone()).collect();
+            state.process_frame(&envelopes);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_bus::AppEvent;
+    use notifications::MemorySink;
+    use std::io::Cursor;
+
+    fn fresh_state() -> SharedAppState {
+        SharedAppState::with_config(Config::default(), Box::new(MemorySink::new()))
+    }
+
+    fn messages(state: &SharedAppState) -> Vec<String> {
+        state
+            .get_log_messages(100)
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir()
+            .join("zenterm_test_journal")
+            .join("events.jsonl");
+        let recorded = fresh_state();
+        recorded.start_journal(&path).unwrap();
+
+        let sender = recorded.get_event_sender();
+        sender.send(AppEvent::GpuLimitChanged(50)).unwrap();
+        sender.send(AppEvent::ThemeToggled(false)).unwrap();
+        sender.send(AppEvent::GpuLimitChanged(30)).unwrap();
+        recorded.process_events();
+        sender
+            .send(AppEvent::LogMessage("after the first frame".to_string()))
+            .unwrap();
+        recorded.process_events();
+        recorded.stop_journal();
+        sender.send(AppEvent::WizardOpened).unwrap();
+
+        let replayer = Replayer::open(&path).unwrap();
+        assert_eq!(replayer.entries().len(), 4);
+        let offsets: Vec<u64> = replayer.entries().iter().map(|e| e.offset_us).collect();
+        assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let replayed = replayer.replay(Config::default(), ReplaySpeed::Immediate);
+        assert_eq!(replayed.get_gpu_status().0, 50);
+        assert!(!replayed.get_theme().dark_mode);
+        assert_eq!(messages(&replayed), messages(&recorded));
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_replay_keeps_queue_order_within_a_frame() {
+        let path = std::env::temp_dir()
+            .join("zenterm_test_journal_frames")
+            .join("events.jsonl");
+        let recorded = fresh_state();
+        recorded.start_journal(&path).unwrap();
+
+        // Control events overtake bulk ones queued before them
+        let sender = recorded.get_event_sender();
+        sender
+            .send(AppEvent::LogMessage("bulk first".to_string()))
+            .unwrap();
+        sender.send(AppEvent::GpuLimitChanged(50)).unwrap();
+        sender
+            .send(AppEvent::LogMessage("bulk second".to_string()))
+            .unwrap();
+        sender.send(AppEvent::ThemeToggled(false)).unwrap();
+        recorded.process_events();
+        sender.send(AppEvent::GpuLimitChanged(75)).unwrap();
+        recorded.process_events();
+        recorded.stop_journal();
+
+        let replayer = Replayer::open(&path).unwrap();
+        let frames: Vec<u64> = replayer.entries().iter().map(|e| e.frame).collect();
+        assert_eq!(frames[..4], [frames[0]; 4]);
+        assert!(frames[4..].iter().all(|frame| *frame > frames[0]));
+
+        let replayed = replayer.replay(Config::default(), ReplaySpeed::Immediate);
+        let live = messages(&recorded);
+        let position = |message: &str| live.iter().position(|m| m == message).unwrap();
+        assert!(position("GPU limit set to 50%") < position("bulk first"));
+        assert_eq!(messages(&replayed), live);
+        assert_eq!(replayed.get_gpu_status().0, 75);
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_replay_speed() {
+        let journal = [0, 40_000, 80_000]
+            .iter()
+            .enumerate()
+            .map(|(id, offset_us)| {
+                let event = AppEvent::LogMessage(format!("event {}", id));
+                let entry = JournalEntry {
+                    offset_us: *offset_us,
+                    frame: id as u64,
+                    envelope: Envelope::new(id as u64 + 1, "test", &event).unwrap(),
+                };
+                serde_json::to_string(&entry).unwrap() + "\n"
+            })
+            .collect::<String>();
+        let replayer = Replayer::from_reader(Cursor::new(journal)).unwrap();
+
+        let started = Instant::now();
+        replayer.replay(Config::default(), ReplaySpeed::Recorded);
+        assert!(started.elapsed() >= Duration::from_millis(80));
+
+        let started = Instant::now();
+        replayer.replay(Config::default(), ReplaySpeed::Accelerated(4.0));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(20) && elapsed < Duration::from_millis(80));
+
+        let err = Replayer::from_reader(Cursor::new("{\"offset_us\": 1}\n")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_replay_leaves_config_file_alone() {
+        let journal = [
+            AppEvent::GpuLimitChanged(75),
+            AppEvent::VoiceToggled(true),
+            AppEvent::ConfigSaveRequested,
+        ]
+        .iter()
+        .enumerate()
+        .map(|(id, event)| {
+            let entry = JournalEntry {
+                offset_us: 0,
+                frame: id as u64,
+                envelope: Envelope::new(id as u64 + 1, "test", event).unwrap(),
+            };
+            serde_json::to_string(&entry).unwrap() + "\n"
+        })
+        .collect::<String>();
+        let replayer = Replayer::from_reader(Cursor::new(journal)).unwrap();
+
+        let config_file = || fs::read(Config::config_path()).ok();
+        let before = config_file();
+        let state = replayer.replay(Config::default(), ReplaySpeed::Immediate);
+        assert_eq!(config_file(), before);
+
+        let config = state.get_config();
+        assert_eq!(config.gpu.limit_percentage, 75);
+        assert!(config.voice.enabled);
+        assert_eq!(state.get_voice_status(), "OFF");
+        assert!(messages(&state).contains(&"Voice engine not started during replay".to_string()));
+    }
+}
@@ -1,7 +1,11 @@
 pub mod command_registry;
 pub mod config;
+pub mod envelope;
 pub mod event_bus;
+pub mod event_queue;
 pub mod gpu_mock;
+pub mod journal;
+pub mod rpc;
 pub mod shared_state;
 pub mod theme;
 pub mod voice_mock;
@@ -9,8 +13,12 @@ pub mod wizard;
 
 pub use command_registry::CommandRegistry;
 pub use config::Config;
-pub use event_bus::{AppEvent, EventBus};
+pub use envelope::{DecodeError, Envelope, Event, Priority};
+pub use event_bus::{AppEvent, EventBus, EventSender, Pattern, PatternError, Subscriber};
+pub use event_queue::{EventQueue, Overflow, QueueConfig, QueueStats};
 pub use gpu_mock::GpuMock;
+pub use journal::{JournalEntry, ReplaySpeed, Replayer};
+pub use rpc::{PendingReply, RequestError};
 pub use shared_state::SharedAppState;
 pub use theme::{Theme, ThemePalette};
 pub use voice_mock::VoiceMock;
@@ -0,0 +1,194 @@
+use crate::envelope::Envelope;
+use crate::event_bus::Registry;
+use crossbeam_channel::{Receiver, TryRecvError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Mutex, Weak};
+use std::time::Instant;
+
+/// Topic replies are published under
+pub const REPLY_TOPIC: &str = "rpc.reply";
+
+/// Why a request did not succeed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum RequestError {
+    /// The handler refused the request, e.g. a GPU limit other than 25, 50,
+    /// 75 or 100
+    Invalid(String),
+    /// The request was valid but carrying it out failed
+    Failed(String),
+    /// No reply arrived before the timeout
+    Timeout,
+    /// The bus is gone
+    Disconnected,
+    /// The reply is not of the expected type
+    Decode(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Invalid(message) => write!(f, "{}", message),
+            RequestError::Failed(message) => write!(f, "{}", message),
+            RequestError::Timeout => write!(f, "No reply in time"),
+            RequestError::Disconnected => write!(f, "Event bus is gone"),
+            RequestError::Decode(e) => write!(f, "Invalid reply: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// The reply to a request sent with `EventSender::request`, typed as `R`.
+/// Dropping it stops waiting for the reply.
+#[derive(Debug)]
+pub struct PendingReply<R> {
+    correlation_id: u64,
+    receiver: Receiver<Envelope>,
+    deadline: Instant,
+    registry: Weak<Mutex<Registry>>,
+    _reply: PhantomData<fn() -> R>,
+}
+
+impl<R: DeserializeOwned> PendingReply<R> {
+    pub(crate) fn new(
+        correlation_id: u64,
+        receiver: Receiver<Envelope>,
+        deadline: Instant,
+        registry: Weak<Mutex<Registry>>,
+    ) -> Self {
+        Self {
+            correlation_id,
+            receiver,
+            deadline,
+            registry,
+            _reply: PhantomData,
+        }
+    }
+
+    /// Id of the request, which its reply carries as well
+    pub fn correlation_id(&self) -> u64 {
+        self.correlation_id
+    }
+
+    /// The reply if it has arrived or the request has timed out, without
+    /// blocking; meant to be called once per frame
+    pub fn poll(&self) -> Option<Result<R, RequestError>> {
+        match self.receiver.try_recv() {
+            Ok(envelope) => Some(Self::decode(envelope)),
+            Err(TryRecvError::Disconnected) => Some(Err(RequestError::Disconnected)),
+            Err(TryRecvError::Empty) if Instant::now() >= self.deadline => {
+                Some(Err(RequestError::Timeout))
+            }
+            Err(TryRecvError::Empty) => None,
+        }
+    }
+
+    /// Block until the reply arrives or the request times out. Not for the
+    /// thread that handles the request.
+    pub fn wait(self) -> Result<R, RequestError> {
+        match self.receiver.recv_deadline(self.deadline) {
+            Ok(envelope) => Self::decode(envelope),
+            Err(e) if e.is_timeout() => Err(RequestError::Timeout),
+            Err(_) => Err(RequestError::Disconnected),
+        }
+    }
+
+    fn decode(envelope: Envelope) -> Result<R, RequestError> {
+        serde_json::from_value::<Result<R, RequestError>>(envelope.payload)
+            .map_err(|e| RequestError::Decode(e.to_string()))?
+    }
+}
+
+impl<R> Drop for PendingReply<R> {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.lock().unwrap().cancel_request(self.correlation_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::Event;
+    use crate::EventBus;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Add(u32, u32);
+
+    impl Event for Add {
+        const VERSION: u32 = 1;
+
+        fn topic(&self) -> &str {
+            "plugin.math.add"
+        }
+    }
+
+    #[test]
+    fn test_request_reply() {
+        let event_bus = EventBus::new();
+        let requests = event_bus.subscribe("plugin.math.*").unwrap();
+        let handler = event_bus.sender().with_source("math");
+        thread::spawn(move || {
+            while let Ok(envelope) = requests.recv() {
+                let result = match envelope.decode::<Add>() {
+                    Ok(Add(a, b)) => a
+                        .checked_add(b)
+                        .ok_or_else(|| RequestError::Invalid(format!("{} + {} overflows", a, b))),
+                    Err(e) => Err(RequestError::Decode(e.to_string())),
+                };
+                handler.reply(&envelope, result);
+            }
+        });
+
+        let sender = event_bus.sender();
+        let sum = sender
+            .request::<_, u32>(Add(2, 3), Duration::from_secs(5))
+            .unwrap();
+        let overflow = sender
+            .request::<_, u32>(Add(u32::MAX, 1), Duration::from_secs(5))
+            .unwrap();
+        assert_ne!(sum.correlation_id(), overflow.correlation_id());
+        assert_eq!(
+            overflow.wait(),
+            Err(RequestError::Invalid(
+                "4294967295 + 1 overflows".to_string()
+            ))
+        );
+        assert_eq!(sum.wait(), Ok(5));
+
+        // The wrong reply type is reported, not guessed at
+        let text = sender
+            .request::<_, String>(Add(1, 1), Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(text.wait(), Err(RequestError::Decode(_))));
+
+        // Replies are ordinary events on the bus as well
+        let mut replies = Vec::new();
+        while let Ok(envelope) = event_bus.try_recv() {
+            if envelope.topic == REPLY_TOPIC {
+                replies.push(envelope.source);
+            }
+        }
+        assert_eq!(replies, ["math", "math", "math"]);
+    }
+
+    #[test]
+    fn test_request_timeout() {
+        let event_bus = EventBus::new();
+        let pending = event_bus
+            .sender()
+            .request::<_, ()>(Add(1, 2), Duration::from_millis(10))
+            .unwrap();
+        assert!(pending.poll().is_none());
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(pending.poll(), Some(Err(RequestError::Timeout)));
+        assert_eq!(pending.wait(), Err(RequestError::Timeout));
+    }
+}
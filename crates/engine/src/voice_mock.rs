@@ -1,5 +1,4 @@
-use crate::event_bus::AppEvent;
-use crossbeam_channel::Sender;
+use crate::event_bus::{AppEvent, EventSender};
 use log::{debug, info, warn};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -16,11 +15,12 @@ pub struct VoiceMock {
 
 impl VoiceMock {
     /// Create a new voice mock engine
-    pub fn new(enabled: bool, event_sender: Sender<AppEvent>) -> Self {
+    pub fn new(enabled: bool, event_sender: EventSender) -> Self {
         let enabled_flag = Arc::new(AtomicBool::new(enabled));
 
         let handle = if enabled {
             let enabled_clone = enabled_flag.clone();
+            let event_sender = event_sender.with_source("voice");
             Some(thread::spawn(move || {
                 Self::voice_thread_loop(enabled_clone, event_sender);
             }))
@@ -61,7 +61,7 @@ impl VoiceMock {
     }
 
     /// Voice thread main loop - sends heartbeat messages every 10 seconds when enabled
-    fn voice_thread_loop(enabled: Arc<AtomicBool>, event_sender: Sender<AppEvent>) {
+    fn voice_thread_loop(enabled: Arc<AtomicBool>, event_sender: EventSender) {
         info!("Voice engine thread started");
 
         let mut heartbeat_counter = 0u64;
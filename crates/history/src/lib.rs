@@ -0,0 +1,383 @@
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use rusqlite::{Error, Result};
+
+/// Results returned when the caller gives no limit
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// Window `HistoryQuery::Failed` looks back over by default
+pub const FAILED_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a write waits for another process holding the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+const COLUMNS: &str =
+    "id, command, cwd, exit_code, duration_ms, session_id, hostname, start_time, end_time";
+
+/// One executed command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Row id, assigned when the entry is recorded
+    #[serde(default)]
+    pub id: i64,
+    pub command: String,
+    pub cwd: String,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub session_id: String,
+    pub hostname: String,
+    /// Milliseconds since the Unix epoch
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+impl HistoryEntry {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            command: row.get(1)?,
+            cwd: row.get(2)?,
+            exit_code: row.get(3)?,
+            duration_ms: row.get::<_, i64>(4)? as u64,
+            session_id: row.get(5)?,
+            hostname: row.get(6)?,
+            start_time: row.get::<_, i64>(7)? as u64,
+            end_time: row.get::<_, i64>(8)? as u64,
+        })
+    }
+}
+
+/// What to look up. Prefix, fuzzy and directory searches list each distinct
+/// command once, at its latest run; failed searches list every failure.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryQuery {
+    /// Commands starting with `text` (case-sensitive), newest first
+    Prefix { text: String },
+    /// Commands containing the characters of `text` in order, best match first
+    Fuzzy { text: String },
+    /// Commands run in `cwd`, newest first
+    Directory { cwd: String },
+    /// Commands that exited non-zero since `since` (milliseconds since the
+    /// epoch), or within the last day
+    Failed { since: Option<u64> },
+}
+
+/// Command history in a SQLite database, shared by every tab, window and
+/// frontend on the machine
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Where the database lives unless told otherwise
+    pub fn default_path() -> PathBuf {
+        match dirs::data_dir() {
+            Some(data_dir) => data_dir.join("zenterm").join("history.db"),
+            None => PathBuf::from("zenterm_history.db"),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            // A missing directory surfaces as an open error below
+            let _ = fs::create_dir_all(parent);
+        }
+        let conn = Connection::open(path)?;
+        // Several frontends may write at once; WAL lets readers carry on
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY,
+                command TEXT NOT NULL,
+                cwd TEXT NOT NULL,
+                exit_code INTEGER,
+                duration_ms INTEGER NOT NULL,
+                session_id TEXT NOT NULL,
+                hostname TEXT NOT NULL,
+                start_time INTEGER NOT NULL,
+                end_time INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS history_start_time ON history (start_time);
+            CREATE INDEX IF NOT EXISTS history_command ON history (command);
+            CREATE INDEX IF NOT EXISTS history_cwd ON history (cwd);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Store an entry, returning its id
+    pub fn record(&self, entry: &HistoryEntry) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO history
+                (command, cwd, exit_code, duration_ms, session_id, hostname, start_time, end_time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                entry.command,
+                entry.cwd,
+                entry.exit_code,
+                entry.duration_ms as i64,
+                entry.session_id,
+                entry.hostname,
+                entry.start_time as i64,
+                entry.end_time as i64,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn search(&self, query: &HistoryQuery, limit: usize) -> Result<Vec<HistoryEntry>> {
+        match query {
+            HistoryQuery::Prefix { text } => self.latest_per_command(
+                "substr(command, 1, length(?1)) = ?1",
+                params![text, limit as i64],
+            ),
+            HistoryQuery::Directory { cwd } => {
+                self.latest_per_command("cwd = ?1", params![cwd, limit as i64])
+            }
+            HistoryQuery::Fuzzy { text } => self.fuzzy(text, limit),
+            HistoryQuery::Failed { since } => {
+                let since = since.unwrap_or_else(|| {
+                    now_millis().saturating_sub(FAILED_WINDOW.as_millis() as u64)
+                });
+                let sql = format!(
+                    "SELECT {} FROM history
+                     WHERE exit_code IS NOT NULL AND exit_code != 0 AND start_time >= ?1
+                     ORDER BY start_time DESC, id DESC LIMIT ?2",
+                    COLUMNS
+                );
+                self.query(&sql, params![since as i64, limit as i64])
+            }
+        }
+    }
+
+    /// The latest run of each distinct command matching `filter`, newest
+    /// first; the limit is the last parameter
+    fn latest_per_command(
+        &self,
+        filter: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<HistoryEntry>> {
+        let sql = format!(
+            "SELECT {} FROM history
+             WHERE id IN (SELECT MAX(id) FROM history WHERE {} GROUP BY command)
+             ORDER BY start_time DESC, id DESC LIMIT ?{}",
+            COLUMNS,
+            filter,
+            params.len()
+        );
+        self.query(&sql, params)
+    }
+
+    fn fuzzy(&self, text: &str, limit: usize) -> Result<Vec<HistoryEntry>> {
+        // Narrow the candidates in SQL, then rank them here. LIKE only folds
+        // ASCII case, which matches the scorer for everything but exotic input.
+        let mut pattern = String::from("%");
+        for c in text.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+            pattern.push('%');
+        }
+        let candidates = self.latest_per_command(
+            "command LIKE ?1 ESCAPE '\\'",
+            params![pattern, i64::MAX],
+        )?;
+
+        // Candidates are newest first and the sort is stable, so ties go to
+        // the most recent command
+        let mut scored: Vec<(i64, HistoryEntry)> = candidates
+            .into_iter()
+            .filter_map(|entry| fuzzy_score(text, &entry.command).map(|score| (score, entry)))
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        Ok(scored.into_iter().take(limit).map(|(_, entry)| entry).collect())
+    }
+
+    fn query(&self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<HistoryEntry>> {
+        let mut statement = self.conn.prepare(sql)?;
+        let rows = statement.query_map(params, HistoryEntry::from_row)?;
+        rows.collect()
+    }
+}
+
+/// How well `query` matches `candidate` as a case-insensitive subsequence,
+/// or `None` if it does not. Consecutive characters and characters at the
+/// start of a word score higher; gaps between matches cost.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let query: Vec<char> = query.chars().map(fold).collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut first_match = None;
+    let mut last_match = None;
+    let mut previous = None;
+
+    for (i, c) in candidate.chars().enumerate() {
+        if matched < query.len() && fold(c) == query[matched] {
+            score += 1;
+            if last_match.is_some_and(|last| last + 1 == i) {
+                score += 5;
+            }
+            if previous.is_none_or(|p: char| p.is_whitespace() || "/-_.".contains(p)) {
+                score += 3;
+            }
+            first_match.get_or_insert(i);
+            last_match = Some(i);
+            matched += 1;
+        }
+        previous = Some(c);
+    }
+
+    if matched < query.len() {
+        return None;
+    }
+    let span = last_match? - first_match? + 1;
+    Some(score - (span - query.len()) as i64)
+}
+
+/// This machine's name, as recorded with each entry
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, cwd: &str, exit_code: i32, start_time: u64) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            command: command.to_string(),
+            cwd: cwd.to_string(),
+            exit_code: Some(exit_code),
+            duration_ms: 10,
+            session_id: "session".to_string(),
+            hostname: "host".to_string(),
+            start_time,
+            end_time: start_time + 10,
+        }
+    }
+
+    fn commands(entries: Vec<HistoryEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.command).collect()
+    }
+
+    fn sample_db() -> HistoryDb {
+        let db = HistoryDb::open_in_memory().unwrap();
+        db.record(&entry("cargo build", "/src/app", 0, 1_000)).unwrap();
+        db.record(&entry("cargo test", "/src/app", 101, 2_000)).unwrap();
+        db.record(&entry("git status", "/src/lib", 0, 3_000)).unwrap();
+        db.record(&entry("cargo build", "/src/lib", 0, 4_000)).unwrap();
+        db
+    }
+
+    #[test]
+    fn test_prefix_search_lists_latest_runs() {
+        let db = sample_db();
+        let found = db
+            .search(&HistoryQuery::Prefix { text: "cargo".to_string() }, DEFAULT_LIMIT)
+            .unwrap();
+
+        // "cargo build" appears once, at its latest run
+        assert_eq!(commands(found.clone()), vec!["cargo build", "cargo test"]);
+        assert_eq!(found[0].cwd, "/src/lib");
+
+        let none = db
+            .search(&HistoryQuery::Prefix { text: "Cargo".to_string() }, DEFAULT_LIMIT)
+            .unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_directory_and_failed_searches() {
+        let db = sample_db();
+        let in_app = db
+            .search(&HistoryQuery::Directory { cwd: "/src/app".to_string() }, DEFAULT_LIMIT)
+            .unwrap();
+        assert_eq!(commands(in_app), vec!["cargo test", "cargo build"]);
+
+        let failed = db
+            .search(&HistoryQuery::Failed { since: Some(1_500) }, DEFAULT_LIMIT)
+            .unwrap();
+        assert_eq!(commands(failed), vec!["cargo test"]);
+
+        // All of the sample happened long before the last day
+        let recent = db
+            .search(&HistoryQuery::Failed { since: None }, DEFAULT_LIMIT)
+            .unwrap();
+        assert!(recent.is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_search_ranks_matches() {
+        let db = sample_db();
+        db.record(&entry("scrabble", "/", 0, 5_000)).unwrap();
+
+        let found = db
+            .search(&HistoryQuery::Fuzzy { text: "cb".to_string() }, DEFAULT_LIMIT)
+            .unwrap();
+        assert_eq!(commands(found), vec!["cargo build", "scrabble"]);
+
+        let limited = db
+            .search(&HistoryQuery::Fuzzy { text: "".to_string() }, 2)
+            .unwrap();
+        assert_eq!(commands(limited), vec!["scrabble", "cargo build"]);
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("xyz", "cargo build"), None);
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert!(fuzzy_score("CB", "cargo build") > fuzzy_score("cb", "scrabble"));
+        assert!(fuzzy_score("stat", "git status") > fuzzy_score("stat", "s t a t"));
+    }
+
+    #[test]
+    fn test_history_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("zenterm-history-{}.db", std::process::id()));
+        {
+            let db = HistoryDb::open(&path).unwrap();
+            assert!(db.record(&entry("ls", "/", 0, 1)).unwrap() > 0);
+        }
+
+        let db = HistoryDb::open(&path).unwrap();
+        let found = db
+            .search(&HistoryQuery::Prefix { text: "l".to_string() }, DEFAULT_LIMIT)
+            .unwrap();
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        assert_eq!(commands(found), vec!["ls"]);
+    }
+}
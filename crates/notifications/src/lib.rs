@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a command runs before its completion is worth a notification
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Normal,
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub urgency: Urgency,
+}
+
+/// Somewhere notifications can be delivered
+pub trait NotificationBackend: Send + Sync + fmt::Debug {
+    fn notify(&self, notification: &Notification) -> io::Result<()>;
+}
+
+/// Desktop notifications through `notify-send` (libnotify)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DesktopBackend;
+
+impl NotificationBackend for DesktopBackend {
+    fn notify(&self, notification: &Notification) -> io::Result<()> {
+        let urgency = match notification.urgency {
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        };
+        let mut child = Command::new("notify-send")
+            .args(["--app-name=ZenTerm", "--urgency", urgency])
+            .arg(&notification.title)
+            .arg(&notification.body)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Reap it in the background so it does not linger as a zombie
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// Keeps notifications in memory instead of showing them, for tests.
+/// Clones share the same list.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    sent: Arc<Mutex<Vec<Notification>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything delivered so far, oldest first
+    pub fn sent(&self) -> Vec<Notification> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+impl NotificationBackend for MemorySink {
+    fn notify(&self, notification: &Notification) -> io::Result<()> {
+        self.sent.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
+
+/// A command that just finished in some tab
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinishedCommand {
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+    /// Whether the tab it ran in has focus
+    pub focused: bool,
+}
+
+/// Notifies about long-running commands that finish while their tab is in
+/// the background
+#[derive(Debug)]
+pub struct CommandNotifier {
+    threshold: Duration,
+    backend: Box<dyn NotificationBackend>,
+}
+
+impl CommandNotifier {
+    pub fn new(threshold: Duration, backend: Box<dyn NotificationBackend>) -> Self {
+        Self { threshold, backend }
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
+    }
+
+    /// The notification a finished command warrants, if any
+    pub fn notification(&self, command: &FinishedCommand) -> Option<Notification> {
+        if command.focused || command.duration < self.threshold {
+            return None;
+        }
+
+        let failed = command.exit_code.is_some_and(|code| code != 0);
+        let status = match command.exit_code {
+            Some(0) => "succeeded".to_string(),
+            Some(code) => format!("failed with exit code {}", code),
+            None => "finished".to_string(),
+        };
+        Some(Notification {
+            title: if failed {
+                "Command failed".to_string()
+            } else {
+                "Command finished".to_string()
+            },
+            body: format!(
+                "{} {} after {}",
+                command.command.trim(),
+                status,
+                format_duration(command.duration)
+            ),
+            urgency: if failed {
+                Urgency::Critical
+            } else {
+                Urgency::Normal
+            },
+        })
+    }
+
+    /// Deliver a notification to the backend
+    pub fn send(&self, notification: &Notification) -> io::Result<()> {
+        self.backend.notify(notification)
+    }
+}
+
+/// Human-readable duration, e.g. `42s`, `3m 05s` or `1h 02m`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(
+        command: &str,
+        exit_code: Option<i32>,
+        secs: u64,
+        focused: bool,
+    ) -> FinishedCommand {
+        FinishedCommand {
+            command: command.to_string(),
+            exit_code,
+            duration: Duration::from_secs(secs),
+            focused,
+        }
+    }
+
+    #[test]
+    fn test_only_long_background_commands_notify() {
+        let notifier = CommandNotifier::new(DEFAULT_THRESHOLD, Box::new(MemorySink::new()));
+
+        assert!(notifier
+            .notification(&finished("ls", Some(0), 1, false))
+            .is_none());
+        assert!(notifier
+            .notification(&finished("cargo build", Some(0), 95, true))
+            .is_none());
+
+        let notification = notifier
+            .notification(&finished("cargo build", Some(0), 95, false))
+            .unwrap();
+        assert_eq!(notification.title, "Command finished");
+        assert_eq!(notification.body, "cargo build succeeded after 1m 35s");
+        assert_eq!(notification.urgency, Urgency::Normal);
+
+        let notification = notifier
+            .notification(&finished("make test", Some(2), 10, false))
+            .unwrap();
+        assert_eq!(notification.title, "Command failed");
+        assert_eq!(
+            notification.body,
+            "make test failed with exit code 2 after 10s"
+        );
+        assert_eq!(notification.urgency, Urgency::Critical);
+    }
+
+    #[test]
+    fn test_memory_sink_records_sent() {
+        let sink = MemorySink::new();
+        let notifier = CommandNotifier::new(Duration::from_secs(5), Box::new(sink.clone()));
+
+        let notification = notifier
+            .notification(&finished("sleep 5", None, 5, false))
+            .unwrap();
+        notifier.send(&notification).unwrap();
+        assert_eq!(sink.sent(), vec![notification]);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(900)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(185)), "3m 05s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 02m");
+    }
+}
@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// How often sessions are sampled unless configured otherwise
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Resources used by a process and all of its descendants
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time used since the previous sample, relative to one core; a
+    /// build on four cores reads up to 400. Zero on the first sample.
+    pub cpu_percent: f32,
+    /// Resident memory in bytes
+    pub rss_bytes: u64,
+    pub threads: u32,
+    pub open_files: u32,
+    /// Processes in the tree, the root included
+    pub processes: u32,
+}
+
+/// Usage of one terminal session's process tree, rooted at its shell
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub session_id: String,
+    pub usage: ResourceUsage,
+}
+
+/// The session using the most CPU, if any uses CPU at all
+pub fn busiest(sessions: &[SessionUsage]) -> Option<&SessionUsage> {
+    sessions
+        .iter()
+        .filter(|s| s.usage.cpu_percent > 0.0)
+        .max_by(|a, b| a.usage.cpu_percent.total_cmp(&b.usage.cpu_percent))
+}
+
+/// The fields of `/proc/<pid>/stat` the sampler needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcStat {
+    pub pid: u32,
+    pub ppid: u32,
+    /// CPU time of the process and its reaped children (utime + stime +
+    /// cutime + cstime), in clock ticks
+    pub ticks: u64,
+    pub threads: u32,
+    pub rss_pages: u64,
+}
+
+impl ProcStat {
+    pub fn read(pid: u32) -> Option<Self> {
+        parse_stat(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+    }
+}
+
+/// Parse `/proc/<pid>/stat`. The command name is in parentheses and may
+/// itself contain spaces and parentheses, so fields are counted from the
+/// last `)`.
+pub fn parse_stat(stat: &str) -> Option<ProcStat> {
+    let (pid, rest) = stat.split_once(" (")?;
+    let (_, rest) = rest.rsplit_once(") ")?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |index: usize| -> Option<u64> { fields.get(index)?.parse().ok() };
+
+    // Numbered from `state` (field 3 in proc(5))
+    Some(ProcStat {
+        pid: pid.trim().parse().ok()?,
+        ppid: field(1)? as u32,
+        ticks: field(11)? + field(12)? + field(13)? + field(14)?,
+        threads: field(17)? as u32,
+        rss_pages: field(21)?,
+    })
+}
+
+/// Every process currently listed in `/proc`
+pub fn read_processes() -> Vec<ProcStat> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(ProcStat::read)
+        .collect()
+}
+
+/// Number of open file descriptors, 0 if they cannot be listed
+pub fn count_open_files(pid: u32) -> u32 {
+    fs::read_dir(format!("/proc/{}/fd", pid))
+        .map(|entries| entries.count() as u32)
+        .unwrap_or(0)
+}
+
+/// `root` and all of its descendants that are in `processes`
+pub fn process_tree(processes: &[ProcStat], root: u32) -> Vec<&ProcStat> {
+    let mut children: HashMap<u32, Vec<&ProcStat>> = HashMap::new();
+    for process in processes {
+        children.entry(process.ppid).or_default().push(process);
+    }
+
+    let mut tree: Vec<&ProcStat> = processes.iter().filter(|p| p.pid == root).collect();
+    let mut next = 0;
+    while next < tree.len() {
+        if let Some(kids) = children.get(&tree[next].pid) {
+            tree.extend(kids.iter().filter(|kid| kid.pid != root));
+        }
+        next += 1;
+    }
+    tree
+}
+
+/// CPU ticks of a tree's processes at one sample
+#[derive(Debug)]
+struct Baseline {
+    at: Instant,
+    ticks: HashMap<u32, u64>,
+}
+
+/// Samples process trees, remembering each tree's previous sample to turn
+/// cumulative CPU time into a percentage
+#[derive(Debug)]
+pub struct ResourceSampler {
+    ticks_per_sec: f64,
+    page_size: u64,
+    previous: HashMap<u32, Baseline>,
+}
+
+impl ResourceSampler {
+    pub fn new() -> Self {
+        let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        Self {
+            ticks_per_sec: if ticks_per_sec > 0 {
+                ticks_per_sec as f64
+            } else {
+                100.0
+            },
+            page_size: if page_size > 0 {
+                page_size as u64
+            } else {
+                4096
+            },
+            previous: HashMap::new(),
+        }
+    }
+
+    /// Measure the trees rooted at `roots` in one pass over `/proc`. Roots
+    /// that no longer exist are left out.
+    pub fn sample(&mut self, roots: &[u32]) -> HashMap<u32, ResourceUsage> {
+        let processes = read_processes();
+        self.measure(&processes, roots, Instant::now(), count_open_files)
+    }
+
+    fn measure(
+        &mut self,
+        processes: &[ProcStat],
+        roots: &[u32],
+        now: Instant,
+        open_files: impl Fn(u32) -> u32,
+    ) -> HashMap<u32, ResourceUsage> {
+        let roots: HashSet<u32> = roots.iter().copied().collect();
+        self.previous.retain(|root, _| roots.contains(root));
+
+        let mut usages = HashMap::new();
+        for &root in &roots {
+            let tree = process_tree(processes, root);
+            if tree.is_empty() {
+                self.previous.remove(&root);
+                continue;
+            }
+
+            let ticks: HashMap<u32, u64> = tree.iter().map(|p| (p.pid, p.ticks)).collect();
+            let cpu_percent = match self.previous.get(&root) {
+                Some(baseline) => self.cpu_percent(baseline, &ticks, now),
+                None => 0.0,
+            };
+            usages.insert(
+                root,
+                ResourceUsage {
+                    cpu_percent,
+                    rss_bytes: tree.iter().map(|p| p.rss_pages * self.page_size).sum(),
+                    threads: tree.iter().map(|p| p.threads).sum(),
+                    open_files: tree.iter().map(|p| open_files(p.pid)).sum(),
+                    processes: tree.len() as u32,
+                },
+            );
+            self.previous.insert(root, Baseline { at: now, ticks });
+        }
+        usages
+    }
+
+    /// Processes that started since the baseline count from zero. A process
+    /// that exited was reaped by its parent, whose cumulative ticks now
+    /// include all of its time, so the part already counted is taken off.
+    fn cpu_percent(&self, baseline: &Baseline, ticks: &HashMap<u32, u64>, now: Instant) -> f32 {
+        let elapsed = now.saturating_duration_since(baseline.at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        let mut used: i64 = 0;
+        for (pid, &current) in ticks {
+            let before = baseline.ticks.get(pid).copied().unwrap_or(0);
+            used += current.saturating_sub(before) as i64;
+        }
+        for (pid, &before) in &baseline.ticks {
+            if !ticks.contains_key(pid) {
+                used -= before as i64;
+            }
+        }
+
+        (used.max(0) as f64 / self.ticks_per_sec / elapsed * 100.0) as f32
+    }
+}
+
+impl Default for ResourceSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, ppid: u32, ticks: u64) -> ProcStat {
+        ProcStat {
+            pid,
+            ppid,
+            ticks,
+            threads: 1,
+            rss_pages: 10,
+        }
+    }
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = "4242 (tmux: server (1)) S 1 4242 4242 0 -1 4194560 1 0 0 0 \
+                    150 50 7 3 20 0 4 0 123 456789 321 18446744073709551615";
+        let parsed = parse_stat(stat).unwrap();
+        assert_eq!(parsed.pid, 4242);
+        assert_eq!(parsed.ppid, 1);
+        assert_eq!(parsed.ticks, 210);
+        assert_eq!(parsed.threads, 4);
+        assert_eq!(parsed.rss_pages, 321);
+
+        assert!(parse_stat("garbage").is_none());
+    }
+
+    #[test]
+    fn test_tree_usage_and_cpu() {
+        let mut sampler = ResourceSampler::new();
+        sampler.ticks_per_sec = 100.0;
+        sampler.page_size = 4096;
+        let start = Instant::now();
+
+        // Shell 10 runs make 11, which runs cc 12; 20 is another session
+        let processes = vec![
+            process(10, 1, 5),
+            process(11, 10, 100),
+            process(12, 11, 50),
+            process(20, 1, 0),
+        ];
+        let usages = sampler.measure(&processes, &[10, 20, 99], start, |_| 3);
+        let shell = usages[&10];
+        assert_eq!(shell.processes, 3);
+        assert_eq!(shell.threads, 3);
+        assert_eq!(shell.open_files, 9);
+        assert_eq!(shell.rss_bytes, 3 * 10 * 4096);
+        assert_eq!(shell.cpu_percent, 0.0);
+        assert_eq!(usages[&20].processes, 1);
+        assert!(!usages.contains_key(&99));
+
+        // One second later cc 12 has exited and been reaped into make's
+        // cutime, and a new cc 13 started
+        let processes = vec![
+            process(10, 1, 5),
+            process(11, 10, 100 + 50 + 30),
+            process(13, 11, 40),
+            process(20, 1, 0),
+        ];
+        let usages = sampler.measure(&processes, &[10, 20], start + Duration::from_secs(1), |_| 0);
+        // 30 ticks of cc 12 plus 40 of cc 13 at 100 ticks per second
+        assert!((usages[&10].cpu_percent - 70.0).abs() < 0.01);
+        assert_eq!(usages[&20].cpu_percent, 0.0);
+
+        let sessions = vec![
+            SessionUsage {
+                session_id: "a".to_string(),
+                usage: usages[&20],
+            },
+            SessionUsage {
+                session_id: "b".to_string(),
+                usage: usages[&10],
+            },
+        ];
+        assert_eq!(busiest(&sessions).unwrap().session_id, "b");
+        assert!(busiest(&sessions[..1]).is_none());
+    }
+
+    #[test]
+    fn test_sample_own_process() {
+        let mut sampler = ResourceSampler::new();
+        let pid = std::process::id();
+        let usage = sampler.sample(&[pid])[&pid];
+        assert!(usage.processes >= 1);
+        assert!(usage.threads >= 1);
+        assert!(usage.rss_bytes > 0);
+        assert!(usage.open_files > 0);
+    }
+}
@@ -0,0 +1,56 @@
+# ZenTerm shell integration for bash.
+#
+# Emits OSC 133 semantic prompt markers so ZenTerm can split the output
+# stream into command blocks:
+#   A       prompt start
+#   B       command input start (end of prompt)
+#   C       command executed, output follows
+#   D;<n>   command finished with exit status <n>
+# and reports the working directory with OSC 7 before every prompt.
+
+if [[ -n "${__zenterm_integration_loaded:-}" || $- != *i* ]]; then
+    return
+fi
+__zenterm_integration_loaded=1
+__zenterm_state=idle
+
+__zenterm_urlencode() {
+    local LC_ALL=C str="$1" out="" c i
+    for (( i = 0; i < ${#str}; i++ )); do
+        c="${str:i:1}"
+        case "$c" in
+            [a-zA-Z0-9/._~-]) out+="$c" ;;
+            *) printf -v c '%%%02X' "'$c"; out+="$c" ;;
+        esac
+    done
+    printf '%s' "$out"
+}
+
+__zenterm_precmd() {
+    local ret=$?
+    if [[ "$__zenterm_state" == running ]]; then
+        printf '\e]133;D;%s\a' "$ret"
+    fi
+    __zenterm_state=idle
+    printf '\e]7;file://%s%s\a' "$HOSTNAME" "$(__zenterm_urlencode "$PWD")"
+    printf '\e]133;A\a'
+    return $ret
+}
+
+__zenterm_prompt_ready() {
+    __zenterm_state=prompt
+}
+
+__zenterm_preexec() {
+    # The DEBUG trap also fires for PROMPT_COMMAND and completion functions
+    [[ "$__zenterm_state" == prompt ]] || return
+    [[ -n "${COMP_LINE:-}" ]] && return
+    [[ "$BASH_COMMAND" == __zenterm_precmd* ]] && return
+    __zenterm_state=running
+    printf '\e]133;C\a'
+}
+
+trap '__zenterm_preexec' DEBUG
+# Newline separated so a user PROMPT_COMMAND ending in ';' stays valid
+PROMPT_COMMAND=$'__zenterm_precmd\n'"${PROMPT_COMMAND:-}"$'\n__zenterm_prompt_ready'
+PS1="${PS1}\[\e]133;B\a\]"
@@ -0,0 +1,32 @@
+# ZenTerm shell integration for fish.
+#
+# Emits OSC 133 semantic prompt markers so ZenTerm can split the output
+# stream into command blocks:
+#   A       prompt start
+#   B       command input start (end of prompt)
+#   C       command executed, output follows
+#   D;<n>   command finished with exit status <n>
+# and reports the working directory with OSC 7 before every prompt.
+
+status is-interactive; or return
+set -q __zenterm_integration_loaded; and return
+set -g __zenterm_integration_loaded 1
+
+function __zenterm_prompt_start --on-event fish_prompt
+    printf '\e]7;file://%s%s\a' (hostname) (string escape --style=url -- $PWD)
+    printf '\e]133;A\a'
+end
+
+function __zenterm_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+
+function __zenterm_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
+
+functions --copy fish_prompt __zenterm_user_prompt
+function fish_prompt
+    __zenterm_user_prompt
+    printf '\e]133;B\a'
+end
@@ -0,0 +1,18 @@
+//! Daemon that owns the PTYs of all sessions, see DEVELOPMENT.md.
+//!
+//! Usage: `zenterm-server [SOCKET_PATH]`
+
+use std::path::PathBuf;
+use zenterm_lib::terminal::{default_socket_path, server};
+
+fn main() {
+    let path = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(default_socket_path);
+
+    if let Err(e) = server::run(&path) {
+        eprintln!("zenterm-server: {}", e);
+        std::process::exit(1);
+    }
+}
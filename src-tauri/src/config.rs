@@ -0,0 +1,220 @@
+use crate::terminal::{RegisteredCommand, SessionSpec, Trigger, TriggerSet};
+use anyhow::{anyhow, Result};
+use redaction::RedactionConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Terminal settings for the Tauri frontend, stored alongside the engine
+/// config as `terminal.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+    /// Profile used when a session is created without one
+    pub default_profile: Option<String>,
+    /// Named shell profiles, e.g. "zsh" or "nix develop"
+    pub profiles: BTreeMap<String, SessionSpec>,
+    /// Regex watchers over session output
+    pub triggers: Vec<Trigger>,
+    /// Commands triggers can run, by name
+    pub commands: BTreeMap<String, RegisteredCommand>,
+    /// Commands running at least this many seconds raise a desktop
+    /// notification when they finish in a background tab; 0 turns this off.
+    /// Defaults to 10.
+    pub long_command_secs: Option<u64>,
+    /// How often the process trees of sessions are sampled for CPU, memory,
+    /// thread and file usage, in milliseconds; 0 turns this off. Defaults
+    /// to 2000.
+    pub resource_interval_ms: Option<u64>,
+    /// Secrets masked in output, history and recordings: the built-in
+    /// detectors plus any extra patterns
+    pub redaction: RedactionConfig,
+}
+
+impl TerminalConfig {
+    /// Load the configuration file, falling back to defaults if it is
+    /// missing or unreadable
+    pub fn load_or_default() -> Self {
+        let config_path = Self::config_path();
+
+        match fs::read_to_string(&config_path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("Failed to parse {:?}: {}. Using defaults.", config_path, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Get the path to the terminal configuration file
+    pub fn config_path() -> PathBuf {
+        match dirs::config_dir() {
+            Some(config_dir) => config_dir.join("zenterm").join("terminal.json"),
+            None => PathBuf::from("zenterm_terminal.json"),
+        }
+    }
+
+    /// Write the configuration to disk
+    pub fn save(&self) -> Result<()> {
+        let config_path = Self::config_path();
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&config_path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Pick the spec for a new session: an explicit spec wins, then the
+    /// named profile, then the default profile, then plain defaults
+    pub fn resolve_spec(
+        &self,
+        profile: Option<&str>,
+        spec: Option<SessionSpec>,
+    ) -> Result<SessionSpec> {
+        if let Some(spec) = spec {
+            return Ok(spec);
+        }
+
+        match profile.or(self.default_profile.as_deref()) {
+            Some(name) => {
+                let mut spec = self
+                    .profiles
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Profile '{}' not found", name))?;
+                spec.profile = Some(name.to_string());
+                Ok(spec)
+            }
+            None => Ok(SessionSpec::default()),
+        }
+    }
+
+    /// Threshold for long-running command notifications, if enabled
+    pub fn long_command_threshold(&self) -> Option<Duration> {
+        match self.long_command_secs {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(notifications::DEFAULT_THRESHOLD),
+        }
+    }
+
+    /// Interval of the resource sampler, if enabled
+    pub fn resource_interval(&self) -> Option<Duration> {
+        match self.resource_interval_ms {
+            Some(0) => None,
+            Some(ms) => Some(Duration::from_millis(ms)),
+            None => Some(resources::DEFAULT_INTERVAL),
+        }
+    }
+
+    /// Compile the configured triggers
+    pub fn trigger_set(&self) -> Result<TriggerSet> {
+        TriggerSet::compile(&self.triggers, &self.commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::TriggerScope;
+
+    fn config_with_profiles() -> TerminalConfig {
+        let mut config = TerminalConfig::default();
+        config.profiles.insert(
+            "fish".to_string(),
+            SessionSpec {
+                shell: Some("/usr/bin/fish".to_string()),
+                ..Default::default()
+            },
+        );
+        config.profiles.insert(
+            "nix".to_string(),
+            SessionSpec {
+                shell: Some("nix".to_string()),
+                args: vec!["develop".to_string()],
+                ..Default::default()
+            },
+        );
+        config
+    }
+
+    #[test]
+    fn test_resolve_spec_precedence() {
+        let mut config = config_with_profiles();
+
+        // No profile and no default gives plain defaults
+        assert_eq!(config.resolve_spec(None, None).unwrap(), SessionSpec::default());
+
+        // The default profile applies when none is named
+        config.default_profile = Some("fish".to_string());
+        let spec = config.resolve_spec(None, None).unwrap();
+        assert_eq!(spec.shell.as_deref(), Some("/usr/bin/fish"));
+
+        // A named profile overrides the default
+        let spec = config.resolve_spec(Some("nix"), None).unwrap();
+        assert_eq!(spec.args, vec!["develop"]);
+        assert_eq!(spec.profile.as_deref(), Some("nix"));
+
+        // An explicit spec overrides everything
+        let explicit = SessionSpec {
+            login: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.resolve_spec(Some("nix"), Some(explicit.clone())).unwrap(),
+            explicit
+        );
+    }
+
+    #[test]
+    fn test_unknown_profile_is_an_error() {
+        let config = config_with_profiles();
+        assert!(config.resolve_spec(Some("tcsh"), None).is_err());
+    }
+
+    #[test]
+    fn test_config_serialization() {
+        let config = config_with_profiles();
+        let json = serde_json::to_string(&config).unwrap();
+        let deserialized: TerminalConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.profiles, config.profiles);
+
+        // Missing fields fall back to defaults
+        let empty: TerminalConfig = serde_json::from_str("{}").unwrap();
+        assert!(empty.profiles.is_empty());
+        assert!(empty.default_profile.is_none());
+        assert!(empty.triggers.is_empty());
+        assert_eq!(
+            empty.long_command_threshold(),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(empty.resource_interval(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_trigger_config() {
+        let config: TerminalConfig = serde_json::from_str(
+            r#"{
+                "triggers": [
+                    { "name": "cargo", "pattern": "error\\[E", "action": { "type": "notify" } },
+                    {
+                        "name": "panic",
+                        "pattern": "panicked at",
+                        "scope": { "profile": "service" },
+                        "action": { "type": "run_command", "command": "page" }
+                    }
+                ],
+                "commands": { "page": { "program": "notify-send", "args": ["panic"] } }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.triggers[0].scope, TriggerScope::All);
+        assert_eq!(
+            config.triggers[1].scope,
+            TriggerScope::Profile("service".to_string())
+        );
+        assert!(config.trigger_set().is_ok());
+    }
+}
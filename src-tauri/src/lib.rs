@@ -1,12 +1,33 @@
-use std::sync::Mutex;
-use tauri::State;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::broadcast::error::RecvError;
+use history::{HistoryDb, HistoryEntry, HistoryQuery};
+use notifications::{CommandNotifier, DesktopBackend};
+use resources::{ResourceSampler, SessionUsage};
+use uuid::Uuid;
 
-mod terminal;
-use terminal::{SessionManager, TerminalSession};
+mod config;
+pub mod terminal;
+use config::TerminalConfig;
+use terminal::pty::now_millis;
+use terminal::recording;
+use terminal::{
+    BroadcastGroup, BufferSlice, ForegroundProcess, Forwarded, PaneLayout, PlaybackControl,
+    PlaybackEvent, Recording, RecordingState, ScreenSnapshot, ServerClient, SessionManager, SessionSignal,
+    SessionSnapshot, SessionEvent, SessionSpec, SplitDirection, TerminalOutput, TerminalSession,
+};
 
 // Global state for the session manager
 struct AppState {
     session_manager: Mutex<SessionManager>,
+    config: Mutex<TerminalConfig>,
+    /// Running recording playbacks by playback id
+    playbacks: Mutex<HashMap<String, Arc<PlaybackControl>>>,
+    /// Latest resource usage of every session's process tree
+    resource_usage: Mutex<Vec<SessionUsage>>,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -18,35 +39,277 @@ fn greet(name: &str) -> String {
 #[tauri::command]
 fn create_terminal_session(
     name: String,
+    profile: Option<String>,
+    spec: Option<SessionSpec>,
     state: State<AppState>,
 ) -> Result<String, String> {
+    let spec = state
+        .config
+        .lock()
+        .unwrap()
+        .resolve_spec(profile.as_deref(), spec)
+        .map_err(|e| e.to_string())?;
+
     state
         .session_manager
         .lock()
         .unwrap()
-        .create_session(name)
+        .create_session(name, spec)
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_profiles(state: State<AppState>) -> BTreeMap<String, SessionSpec> {
+    state.config.lock().unwrap().profiles.clone()
+}
+
+#[tauri::command]
+fn save_profile(
+    name: String,
+    spec: SessionSpec,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap();
+    config.profiles.insert(name, spec);
+    config.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_profile(name: String, state: State<AppState>) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap();
+    config.profiles.remove(&name);
+    if config.default_profile.as_deref() == Some(name.as_str()) {
+        config.default_profile = None;
+    }
+    config.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_default_profile(name: Option<String>, state: State<AppState>) -> Result<(), String> {
+    let mut config = state.config.lock().unwrap();
+    if let Some(name) = &name {
+        if !config.profiles.contains_key(name) {
+            return Err(format!("Profile '{}' not found", name));
+        }
+    }
+    config.default_profile = name;
+    config.save().map_err(|e| e.to_string())
+}
+
+/// Run a command line as a command block. In a broadcast group it runs in
+/// every member; a command of several lines then needs `confirmed`.
 #[tauri::command]
 fn execute_command(
     session_id: String,
     command: String,
+    confirmed: Option<bool>,
     state: State<AppState>,
 ) -> Result<String, String> {
     state
         .session_manager
         .lock()
         .unwrap()
-        .execute_command(&session_id, &command)
+        .execute_command(&session_id, &command, confirmed.unwrap_or(false))
         .map_err(|e| e.to_string())
 }
 
+/// Send keystrokes to a session as-is, e.g. Ctrl-C, arrow keys or input
+/// for a full-screen application. In a broadcast group the input goes to
+/// every member; a multi-line paste then needs `confirmed`.
+#[tauri::command]
+fn write_input(
+    session_id: String,
+    data: Vec<u8>,
+    confirmed: Option<bool>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .write_input(&session_id, &data, confirmed.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_broadcast_groups(state: State<AppState>) -> Vec<BroadcastGroup> {
+    state.session_manager.lock().unwrap().list_broadcast_groups()
+}
+
+/// Add a session to a broadcast group so it shares input with the others
+#[tauri::command]
+fn join_broadcast_group(
+    name: String,
+    session_id: String,
+    state: State<AppState>,
+) -> Result<BroadcastGroup, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .join_broadcast_group(&name, &session_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn leave_broadcast_group(session_id: String, state: State<AppState>) -> Result<(), String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .leave_broadcast_group(&session_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Exclude a session from its group's broadcasts for now, or include it again
+#[tauri::command]
+fn set_broadcast_opt_out(
+    session_id: String,
+    opted_out: bool,
+    state: State<AppState>,
+) -> Result<BroadcastGroup, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .set_broadcast_opt_out(&session_id, opted_out)
+        .map_err(|e| e.to_string())
+}
+
+/// CPU, memory, thread and open file usage of each session's process tree
+/// from the latest sample
+#[tauri::command]
+fn get_resource_usage(state: State<AppState>) -> Vec<SessionUsage> {
+    state.resource_usage.lock().unwrap().clone()
+}
+
+#[tauri::command]
+fn send_signal(
+    session_id: String,
+    signal: SessionSignal,
+    state: State<AppState>,
+) -> Result<(), String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .send_signal(&session_id, signal)
+        .map_err(|e| e.to_string())
+}
+
+/// What a session is running right now; `is_shell` means it is idle
+#[tauri::command]
+fn get_foreground_process(
+    session_id: String,
+    state: State<AppState>,
+) -> Result<Option<ForegroundProcess>, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .foreground_process(&session_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Start or stop recording a session as an asciicast v2 file
+#[tauri::command]
+fn toggle_recording(
+    session_id: String,
+    path: Option<PathBuf>,
+    state: State<AppState>,
+) -> Result<RecordingState, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .toggle_recording(&session_id, path)
+        .map_err(|e| e.to_string())
+}
+
+/// Replay a recording into a view. Output arrives as
+/// `terminal-output:<playback id>` like a live session, resizes and the end
+/// of playback as `playback:<playback id>`.
+#[tauri::command]
+fn play_recording(
+    path: PathBuf,
+    speed: Option<f64>,
+    max_idle: Option<f64>,
+    app: AppHandle,
+    state: State<AppState>,
+) -> Result<String, String> {
+    let recording = Recording::load(&path).map_err(|e| e.to_string())?;
+    let playback_id = Uuid::new_v4().to_string();
+    let control = Arc::new(PlaybackControl::new(speed.unwrap_or(1.0)));
+    state
+        .playbacks
+        .lock()
+        .unwrap()
+        .insert(playback_id.clone(), Arc::clone(&control));
+
+    let id = playback_id.clone();
+    std::thread::spawn(move || {
+        let max_idle = max_idle.filter(|secs| *secs > 0.0).map(Duration::from_secs_f64);
+        recording::play(&recording, &control, max_idle, |event| {
+            let result = match event {
+                PlaybackEvent::Output(content) => app.emit(
+                    &format!("terminal-output:{}", id),
+                    TerminalOutput {
+                        session_id: id.clone(),
+                        content,
+                        is_error: false,
+                        timestamp: now_millis(),
+                        offset: None,
+                        highlighted: None,
+                    },
+                ),
+                event => app.emit(&format!("playback:{}", id), event),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to emit playback event: {}", e);
+            }
+        });
+        app.state::<AppState>().playbacks.lock().unwrap().remove(&id);
+    });
+
+    Ok(playback_id)
+}
+
+#[tauri::command]
+fn set_playback_speed(
+    playback_id: String,
+    speed: f64,
+    state: State<AppState>,
+) -> Result<(), String> {
+    match state.playbacks.lock().unwrap().get(&playback_id) {
+        Some(control) => {
+            control.set_speed(speed);
+            Ok(())
+        }
+        None => Err("Playback not found".to_string()),
+    }
+}
+
+#[tauri::command]
+fn stop_playback(playback_id: String, state: State<AppState>) -> Result<(), String> {
+    match state.playbacks.lock().unwrap().remove(&playback_id) {
+        Some(control) => {
+            control.stop();
+            Ok(())
+        }
+        None => Err("Playback not found".to_string()),
+    }
+}
+
 #[tauri::command]
 fn get_sessions(state: State<AppState>) -> Vec<TerminalSession> {
     state.session_manager.lock().unwrap().list_sessions()
 }
 
+#[tauri::command]
+fn get_active_session(state: State<AppState>) -> Option<String> {
+    state.session_manager.lock().unwrap().get_active_session()
+}
+
 #[tauri::command]
 fn set_active_session(
     session_id: String,
@@ -60,6 +323,79 @@ fn set_active_session(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn search_history(
+    query: HistoryQuery,
+    limit: Option<usize>,
+    state: State<AppState>,
+) -> Result<Vec<HistoryEntry>, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .search_history(&query, limit.unwrap_or(history::DEFAULT_LIMIT))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_layouts(state: State<AppState>) -> Vec<PaneLayout> {
+    state.session_manager.lock().unwrap().list_layouts()
+}
+
+#[tauri::command]
+fn split_pane(
+    session_id: String,
+    direction: SplitDirection,
+    name: Option<String>,
+    state: State<AppState>,
+) -> Result<String, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .split_pane(&session_id, direction, name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn resize_pane(
+    session_id: String,
+    ratio: f32,
+    state: State<AppState>,
+) -> Result<PaneLayout, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .resize_pane(&session_id, ratio)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn focus_pane(session_id: String, state: State<AppState>) -> Result<PaneLayout, String> {
+    let session_manager = state.session_manager.lock().unwrap();
+    session_manager
+        .set_active_session(session_id.clone())
+        .map_err(|e| e.to_string())?;
+    session_manager
+        .get_layout(&session_id)
+        .ok_or_else(|| "Session not found".to_string())
+}
+
+#[tauri::command]
+fn swap_panes(
+    session_id: String,
+    other_session_id: String,
+    state: State<AppState>,
+) -> Result<PaneLayout, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .swap_panes(&session_id, &other_session_id)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn resize_terminal(
     session_id: String,
@@ -75,6 +411,32 @@ fn resize_terminal(
         .map_err(|e| e.to_string())
 }
 
+/// Replay a session's output from `from_offset` (the start by default), e.g.
+/// after the webview reloads
+#[tauri::command]
+fn get_session_buffer(
+    session_id: String,
+    from_offset: Option<u64>,
+    state: State<AppState>,
+) -> Result<BufferSlice, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .get_buffer(&session_id, from_offset.unwrap_or(0))
+        .ok_or_else(|| "Session not found".to_string())
+}
+
+#[tauri::command]
+fn get_screen(session_id: String, state: State<AppState>) -> Result<ScreenSnapshot, String> {
+    state
+        .session_manager
+        .lock()
+        .unwrap()
+        .get_screen(&session_id)
+        .ok_or_else(|| "Session not found".to_string())
+}
+
 #[tauri::command]
 fn close_session(
     session_id: String,
@@ -88,22 +450,243 @@ fn close_session(
         .map_err(|e| e.to_string())
 }
 
+/// Deliver PTY output to the webview as `terminal-output:<session id>`
+/// events, and full redraws as `terminal-resync:<session id>` after lag
+fn spawn_output_forwarder(app: &tauri::App) {
+    let forwarder = app
+        .state::<AppState>()
+        .session_manager
+        .lock()
+        .unwrap()
+        .output_forwarder();
+    let handle = app.handle().clone();
+
+    tauri::async_runtime::spawn(forwarder.run(move |forwarded| {
+        let result = match forwarded {
+            Forwarded::Output(output) => {
+                handle.emit(&format!("terminal-output:{}", output.session_id), output)
+            }
+            Forwarded::Resync(resync) => {
+                handle.emit(&format!("terminal-resync:{}", resync.session_id), resync)
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to emit terminal output: {}", e);
+        }
+    }));
+}
+
+/// Sample the process tree of every session at the configured interval,
+/// publishing the readings as `resource-usage` events
+fn spawn_resource_sampler(app: &tauri::App) {
+    let Some(interval) = app.state::<AppState>().config.lock().unwrap().resource_interval() else {
+        return;
+    };
+    let handle = app.handle().clone();
+
+    std::thread::spawn(move || {
+        let mut sampler = ResourceSampler::new();
+        loop {
+            std::thread::sleep(interval);
+            let state = handle.state::<AppState>();
+            let pids = match state.session_manager.lock().unwrap().shell_pids() {
+                Ok(pids) => pids,
+                Err(e) => {
+                    eprintln!("Failed to list session processes: {}", e);
+                    continue;
+                }
+            };
+
+            let roots: Vec<u32> = pids.iter().map(|(_, pid)| *pid).collect();
+            let mut usages = sampler.sample(&roots);
+            let readings: Vec<SessionUsage> = pids
+                .into_iter()
+                .filter_map(|(session_id, pid)| {
+                    Some(SessionUsage {
+                        session_id,
+                        usage: usages.remove(&pid)?,
+                    })
+                })
+                .collect();
+
+            *state.resource_usage.lock().unwrap() = readings.clone();
+            if let Err(e) = handle.emit("resource-usage", readings) {
+                eprintln!("Failed to emit resource usage: {}", e);
+            }
+        }
+    });
+}
+
+/// Watch session output with the triggers from the config
+fn load_triggers(app: &tauri::App) {
+    let state = app.state::<AppState>();
+    let triggers = state.config.lock().unwrap().trigger_set();
+    match triggers {
+        Ok(triggers) => {
+            if let Err(e) = state.session_manager.lock().unwrap().set_triggers(triggers) {
+                eprintln!("Failed to set triggers: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Failed to load triggers: {}", e),
+    }
+}
+
+/// Mask secrets in session output with the detectors from the config.
+/// Runs before any session is restored or created.
+fn load_redaction(app: &tauri::App) {
+    let state = app.state::<AppState>();
+    let config = state.config.lock().unwrap().redaction.clone();
+    let result = state.session_manager.lock().unwrap().set_redaction(&config);
+    if let Err(e) = result {
+        eprintln!("Failed to load redaction patterns, using the built-in detectors: {}", e);
+    }
+}
+
+/// Run the sessions in `zenterm-server` so they outlive the window,
+/// starting the server if needed. Falls back to owning the PTYs in this
+/// process when no server can be reached.
+fn connect_session_manager() -> SessionManager {
+    match ServerClient::connect_or_spawn(&terminal::default_socket_path()) {
+        Ok(client) => SessionManager::with_host(Arc::new(client)),
+        Err(e) => {
+            eprintln!("Failed to reach zenterm-server, running sessions in-process: {}", e);
+            SessionManager::new()
+        }
+    }
+}
+
+/// Raise desktop notifications for long commands that finish in background
+/// tabs
+fn notify_long_commands(app: &tauri::App) {
+    let state = app.state::<AppState>();
+    let threshold = state.config.lock().unwrap().long_command_threshold();
+    if let Some(threshold) = threshold {
+        let notifier = CommandNotifier::new(threshold, Box::new(DesktopBackend));
+        state.session_manager.lock().unwrap().notify_long_commands(notifier);
+    }
+}
+
+/// Deliver fired triggers to the webview as `trigger-fired` events, for
+/// notifications in any tab
+fn spawn_trigger_forwarder(app: &tauri::App) {
+    let mut events = app
+        .state::<AppState>()
+        .session_manager
+        .lock()
+        .unwrap()
+        .get_event_receiver();
+    let handle = app.handle().clone();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(SessionEvent::TriggerFired(fired)) => {
+                    if let Err(e) = handle.emit("trigger-fired", fired) {
+                        eprintln!("Failed to emit trigger: {}", e);
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Bring back the sessions of the previous run, then keep the snapshot
+/// current as sessions change
+fn restore_sessions(app: &tauri::App) {
+    let state = app.state::<AppState>();
+    let session_manager = state.session_manager.lock().unwrap();
+    let path = SessionSnapshot::default_path();
+
+    match SessionSnapshot::load(&path) {
+        Ok(Some(snapshot)) => {
+            session_manager.restore(snapshot);
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to load session snapshot: {}", e),
+    }
+    session_manager.persist_to(path);
+}
+
+/// Record finished commands in the history database shared with the other
+/// frontends
+fn open_history(app: &tauri::App) {
+    match HistoryDb::open(&HistoryDb::default_path()) {
+        Ok(db) => app
+            .state::<AppState>()
+            .session_manager
+            .lock()
+            .unwrap()
+            .record_history_to(db),
+        Err(e) => eprintln!("Failed to open command history: {}", e),
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .manage(AppState {
-            session_manager: Mutex::new(SessionManager::new()),
+            session_manager: Mutex::new(connect_session_manager()),
+            config: Mutex::new(TerminalConfig::load_or_default()),
+            playbacks: Mutex::new(HashMap::new()),
+            resource_usage: Mutex::new(Vec::new()),
+        })
+        .setup(|app| {
+            spawn_output_forwarder(app);
+            spawn_trigger_forwarder(app);
+            load_triggers(app);
+            load_redaction(app);
+            notify_long_commands(app);
+            open_history(app);
+            restore_sessions(app);
+            spawn_resource_sampler(app);
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet,
             create_terminal_session,
+            get_profiles,
+            save_profile,
+            delete_profile,
+            set_default_profile,
             execute_command,
+            write_input,
+            get_broadcast_groups,
+            join_broadcast_group,
+            leave_broadcast_group,
+            set_broadcast_opt_out,
+            send_signal,
+            get_foreground_process,
+            get_resource_usage,
+            toggle_recording,
+            play_recording,
+            set_playback_speed,
+            stop_playback,
             get_sessions,
+            get_active_session,
             set_active_session,
+            search_history,
+            get_layouts,
+            split_pane,
+            resize_pane,
+            focus_pane,
+            swap_panes,
             resize_terminal,
+            get_session_buffer,
+            get_screen,
             close_session
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let state = app.state::<AppState>();
+                let session_manager = state.session_manager.lock().unwrap();
+                if let Err(e) = session_manager.save_snapshot(&SessionSnapshot::default_path()) {
+                    eprintln!("Failed to save session snapshot: {}", e);
+                }
+            }
+        });
 }
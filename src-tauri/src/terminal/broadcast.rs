@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A named set of sessions that receive the same input ("synchronized
+/// input"). Typing into any member that has not opted out types into every
+/// member that has not opted out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BroadcastGroup {
+    pub name: String,
+    /// Members in the order they joined
+    pub sessions: Vec<String>,
+    /// Members that stay in the group but neither send nor receive
+    /// broadcast input for now
+    pub opted_out: BTreeSet<String>,
+}
+
+impl BroadcastGroup {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            sessions: Vec::new(),
+            opted_out: BTreeSet::new(),
+        }
+    }
+
+    /// Members that currently receive broadcast input
+    pub fn targets(&self) -> Vec<String> {
+        self.sessions
+            .iter()
+            .filter(|id| !self.opted_out.contains(*id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Every broadcast group by name. A session is in at most one group.
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastGroups {
+    groups: BTreeMap<String, BroadcastGroup>,
+}
+
+impl BroadcastGroups {
+    /// Add a session to a group, creating the group if needed and taking
+    /// the session out of any other group
+    pub fn join(&mut self, name: &str, session_id: &str) -> Result<BroadcastGroup> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("Broadcast group name must not be empty"));
+        }
+        if self.group_of(session_id).map(|g| g.name.as_str()) != Some(name) {
+            self.leave(session_id);
+        }
+
+        let group = self
+            .groups
+            .entry(name.to_string())
+            .or_insert_with(|| BroadcastGroup::new(name));
+        if !group.sessions.iter().any(|id| id == session_id) {
+            group.sessions.push(session_id.to_string());
+        }
+        Ok(group.clone())
+    }
+
+    /// Take a session out of its group; a group without members is removed
+    pub fn leave(&mut self, session_id: &str) -> bool {
+        let Some(name) = self.group_of(session_id).map(|g| g.name.clone()) else {
+            return false;
+        };
+        let group = self.groups.get_mut(&name).unwrap();
+        group.sessions.retain(|id| id != session_id);
+        group.opted_out.remove(session_id);
+        if group.sessions.is_empty() {
+            self.groups.remove(&name);
+        }
+        true
+    }
+
+    /// Pause or resume broadcasting for one member without leaving the group
+    pub fn set_opted_out(&mut self, session_id: &str, opted_out: bool) -> Result<BroadcastGroup> {
+        let group = self
+            .groups
+            .values_mut()
+            .find(|g| g.sessions.iter().any(|id| id == session_id))
+            .ok_or_else(|| anyhow!("Session is not in a broadcast group"))?;
+        if opted_out {
+            group.opted_out.insert(session_id.to_string());
+        } else {
+            group.opted_out.remove(session_id);
+        }
+        Ok(group.clone())
+    }
+
+    pub fn group_of(&self, session_id: &str) -> Option<&BroadcastGroup> {
+        self.groups
+            .values()
+            .find(|g| g.sessions.iter().any(|id| id == session_id))
+    }
+
+    /// Sessions that input typed into `session_id` goes to: its group's
+    /// targets, or just the session itself if it is not broadcasting
+    pub fn targets(&self, session_id: &str) -> Vec<String> {
+        match self.group_of(session_id) {
+            Some(group) if !group.opted_out.contains(session_id) => group.targets(),
+            _ => vec![session_id.to_string()],
+        }
+    }
+
+    pub fn list(&self) -> Vec<BroadcastGroup> {
+        self.groups.values().cloned().collect()
+    }
+}
+
+/// Whether input spans more than one line, like a multi-line paste. A
+/// single trailing line break (Enter, or a pasted line) does not count.
+pub fn is_multiline(data: &[u8]) -> bool {
+    let end = data
+        .iter()
+        .rposition(|&b| b != b'\r' && b != b'\n')
+        .map_or(0, |i| i + 1);
+    data[..end].iter().any(|&b| b == b'\r' || b == b'\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_leave_and_opt_out() {
+        let mut groups = BroadcastGroups::default();
+        groups.join("web", "a").unwrap();
+        groups.join("web", "b").unwrap();
+        let group = groups.join("web", "c").unwrap();
+        assert_eq!(group.sessions, vec!["a", "b", "c"]);
+        assert!(groups.join(" ", "d").is_err());
+
+        // Outside a group, input only goes to the session itself
+        assert_eq!(groups.targets("a"), vec!["a", "b", "c"]);
+        assert_eq!(groups.targets("d"), vec!["d"]);
+
+        // An opted-out member neither receives nor sends broadcast input
+        let group = groups.set_opted_out("b", true).unwrap();
+        assert_eq!(group.targets(), vec!["a", "c"]);
+        assert_eq!(groups.targets("a"), vec!["a", "c"]);
+        assert_eq!(groups.targets("b"), vec!["b"]);
+        assert!(groups.set_opted_out("d", true).is_err());
+
+        // Joining another group moves the session
+        groups.join("db", "c").unwrap();
+        assert_eq!(groups.group_of("c").unwrap().name, "db");
+        assert_eq!(groups.targets("a"), vec!["a"]);
+
+        assert!(groups.leave("c"));
+        assert!(!groups.leave("c"));
+        assert_eq!(groups.list().len(), 1);
+        assert!(groups.group_of("b").unwrap().opted_out.contains("b"));
+    }
+
+    #[test]
+    fn test_is_multiline() {
+        assert!(!is_multiline(b"l"));
+        assert!(!is_multiline(b"\r"));
+        assert!(!is_multiline(b"uptime\n"));
+        assert!(!is_multiline(b""));
+        assert!(is_multiline(b"apt update\napt upgrade"));
+        assert!(is_multiline(b"a\r\nb\r\n"));
+    }
+}
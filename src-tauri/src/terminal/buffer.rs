@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Bytes of output kept per session for replay
+pub const DEFAULT_BUFFER_LIMIT: usize = 4 * 1024 * 1024;
+
+/// A range of a session's output stream, as returned by `get_session_buffer`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BufferSlice {
+    /// Offset of the first byte of `content` in the session's output stream
+    pub start_offset: u64,
+    /// Offset just past `content`; pass it back to continue from here
+    pub end_offset: u64,
+    pub content: String,
+    /// Part of the requested range was already evicted from the buffer
+    pub truncated: bool,
+}
+
+/// Byte-bounded ring of a session's decoded output. Offsets count bytes
+/// since the session started and keep growing as old output is evicted.
+#[derive(Debug)]
+pub struct OutputBuffer {
+    data: VecDeque<u8>,
+    /// Stream offset of `data[0]`
+    start_offset: u64,
+    limit: usize,
+}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_LIMIT)
+    }
+}
+
+impl OutputBuffer {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            start_offset: 0,
+            limit,
+        }
+    }
+
+    /// Offset the next appended byte will get
+    pub fn end_offset(&self) -> u64 {
+        self.start_offset + self.data.len() as u64
+    }
+
+    pub fn start_offset(&self) -> u64 {
+        self.start_offset
+    }
+
+    /// Append output, returning the offset it starts at
+    pub fn push(&mut self, content: &str) -> u64 {
+        let offset = self.end_offset();
+        self.data.extend(content.as_bytes());
+
+        if self.data.len() > self.limit {
+            let mut excess = self.data.len() - self.limit;
+            // Never split a character: evict its continuation bytes with it
+            while self.data.get(excess).is_some_and(|b| b & 0xC0 == 0x80) {
+                excess += 1;
+            }
+            self.data.drain(..excess);
+            self.start_offset += excess as u64;
+        }
+
+        offset
+    }
+
+    /// Everything from `from_offset` to the end of the stream; offsets that
+    /// were evicted start at the oldest retained byte instead
+    pub fn read_from(&self, from_offset: u64) -> BufferSlice {
+        let end_offset = self.end_offset();
+        let start_offset = from_offset.clamp(self.start_offset, end_offset);
+        let skip = (start_offset - self.start_offset) as usize;
+
+        let bytes: Vec<u8> = self.data.range(skip..).copied().collect();
+        BufferSlice {
+            start_offset,
+            end_offset,
+            content: String::from_utf8_lossy(&bytes).into_owned(),
+            truncated: from_offset < self.start_offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_from_offsets() {
+        let mut buffer = OutputBuffer::new(1024);
+        assert_eq!(buffer.push("hello "), 0);
+        assert_eq!(buffer.push("world"), 6);
+
+        let slice = buffer.read_from(0);
+        assert_eq!(slice.content, "hello world");
+        assert_eq!((slice.start_offset, slice.end_offset), (0, 11));
+        assert!(!slice.truncated);
+
+        let slice = buffer.read_from(6);
+        assert_eq!(slice.content, "world");
+
+        // Reading from the end (or beyond) yields nothing new
+        let slice = buffer.read_from(50);
+        assert_eq!(slice.content, "");
+        assert_eq!(slice.start_offset, 11);
+    }
+
+    #[test]
+    fn test_eviction_keeps_offsets_and_characters() {
+        let mut buffer = OutputBuffer::new(8);
+        buffer.push("abcdef");
+        buffer.push("ghé");
+        // 10 bytes pushed, limit 8: "ab" is evicted
+        assert_eq!(buffer.start_offset(), 2);
+        assert_eq!(buffer.end_offset(), 10);
+
+        let slice = buffer.read_from(0);
+        assert!(slice.truncated);
+        assert_eq!(slice.start_offset, 2);
+        assert_eq!(slice.content, "cdefghé");
+
+        // Evicting the first byte of "é" takes its continuation byte too
+        buffer.push("ijklmno");
+        let slice = buffer.read_from(0);
+        assert_eq!(slice.content, "ijklmno");
+        assert_eq!(slice.start_offset, 10);
+    }
+}
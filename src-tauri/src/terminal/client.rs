@@ -0,0 +1,619 @@
+use crate::terminal::protocol::{ClientMessage, Request, Response, ServerMessage};
+use crate::terminal::pty::now_millis;
+use crate::terminal::runtime_dir::{check_private_dir, check_socket};
+use crate::terminal::{
+    BufferSlice, ForegroundProcess, HostedSession, PtyHost, RecordingState, ScreenSnapshot,
+    SessionEvent, SessionSignal, SessionSpec, TerminalOutput, TriggerSet, PROTOCOL_VERSION,
+};
+use anyhow::{anyhow, Result};
+use redaction::RedactionConfig;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
+
+/// Longest a request waits for the server to answer
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a freshly spawned server gets to start listening
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Attempts to reconnect after losing the server, and the pause before
+/// each; a new server is started if none is listening
+const RECONNECT_ATTEMPTS: u32 = 20;
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(250);
+
+type PendingReplies = Arc<Mutex<HashMap<u64, mpsc::Sender<Response>>>>;
+
+/// A `PtyHost` backed by a `zenterm-server` daemon. Sessions created
+/// through it keep running when the client goes away. If the connection
+/// drops, the client reconnects and attaches to its sessions again.
+pub struct ServerClient {
+    state: Arc<ClientState>,
+}
+
+struct ClientState {
+    path: PathBuf,
+    /// Replaced when the client reconnects
+    writer: Mutex<UnixStream>,
+    /// Bumped with every new connection, so the reader of an old one does
+    /// not act on its end
+    generation: AtomicU64,
+    next_id: AtomicU64,
+    pending: PendingReplies,
+    /// Sessions created or attached through this client, attached again
+    /// after reconnecting
+    attached: Arc<Mutex<HashSet<String>>>,
+    output_sender: broadcast::Sender<TerminalOutput>,
+    event_sender: broadcast::Sender<SessionEvent>,
+}
+
+impl ServerClient {
+    /// Connect to a running server, if its socket and the directory it is
+    /// in belong to the user and nobody else can get at them
+    pub fn connect(path: &Path) -> Result<Self> {
+        let stream = open(path)?;
+        let (output_sender, _) = broadcast::channel(1000);
+        let (event_sender, _) = broadcast::channel(1000);
+        let state = Arc::new(ClientState {
+            path: path.to_path_buf(),
+            writer: Mutex::new(stream.try_clone()?),
+            generation: AtomicU64::new(0),
+            next_id: AtomicU64::new(1),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            attached: Arc::new(Mutex::new(HashSet::new())),
+            output_sender,
+            event_sender,
+        });
+        spawn_reader(&state, stream);
+        state.hello()?;
+        Ok(Self { state })
+    }
+
+    /// Connect to the server at `path`, starting one in the background if
+    /// none is running
+    pub fn connect_or_spawn(path: &Path) -> Result<Self> {
+        if let Ok(client) = Self::connect(path) {
+            return Ok(client);
+        }
+
+        spawn_server(path)?;
+        let deadline = std::time::Instant::now() + SPAWN_TIMEOUT;
+        loop {
+            match Self::connect(path) {
+                Ok(client) => return Ok(client),
+                Err(e) if std::time::Instant::now() >= deadline => return Err(e),
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }
+
+    fn request(&self, request: Request) -> Result<Response> {
+        self.state.request(request)
+    }
+
+    fn request_ok(&self, request: Request) -> Result<()> {
+        self.state.request_ok(request)
+    }
+
+    fn track(&self, session_id: &str, attached: bool) {
+        let mut sessions = self.state.attached.lock().unwrap();
+        if attached {
+            sessions.insert(session_id.to_string());
+        } else {
+            sessions.remove(session_id);
+        }
+    }
+}
+
+/// Connect to the socket at `path` after checking that it and its
+/// directory belong to the user
+fn open(path: &Path) -> Result<UnixStream> {
+    if let Some(parent) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        check_private_dir(parent)?;
+    }
+    check_socket(path)?;
+    Ok(UnixStream::connect(path)?)
+}
+
+/// Read the server's messages from `stream` until it goes away, then
+/// reconnect unless the client was dropped or is on a newer connection
+fn spawn_reader(state: &Arc<ClientState>, stream: UnixStream) {
+    let weak = Arc::downgrade(state);
+    let generation = state.generation.load(Ordering::SeqCst);
+    let pending = Arc::clone(&state.pending);
+    let output_sender = state.output_sender.clone();
+    let event_sender = state.event_sender.clone();
+    let attached = Arc::clone(&state.attached);
+
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            let message = match serde_json::from_str::<ServerMessage>(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    eprintln!("Ignoring malformed message from server: {}", e);
+                    continue;
+                }
+            };
+
+            match message {
+                ServerMessage::Reply { id, result } => {
+                    if let Some(reply) = pending.lock().unwrap().remove(&id) {
+                        let _ = reply.send(result);
+                    }
+                }
+                ServerMessage::Output(output) => {
+                    let _ = output_sender.send(output);
+                }
+                ServerMessage::Event(event) => {
+                    if let SessionEvent::SessionEnded { session_id, .. } = &event {
+                        attached.lock().unwrap().remove(session_id);
+                    }
+                    let _ = event_sender.send(event);
+                }
+                // Clear the terminal and redraw; the chunk has no offset
+                // because it is not part of the output stream
+                ServerMessage::Resync(resync) => {
+                    let _ = output_sender.send(redraw(resync.session_id, &resync.content));
+                }
+            }
+        }
+
+        let Some(state) = weak.upgrade() else {
+            return;
+        };
+        if state.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        eprintln!("Lost connection to zenterm-server");
+        // Fail the requests still waiting for a reply
+        pending.lock().unwrap().clear();
+        state.reconnect();
+    });
+}
+
+/// Output that clears a session's terminal and draws `content`
+fn redraw(session_id: String, content: &str) -> TerminalOutput {
+    TerminalOutput {
+        session_id,
+        content: format!("\x1bc{}", content),
+        is_error: false,
+        timestamp: now_millis(),
+        offset: None,
+        highlighted: None,
+    }
+}
+
+impl ClientState {
+    fn hello(&self) -> Result<()> {
+        match self.request(Request::Hello)? {
+            Response::Hello { version } if version == PROTOCOL_VERSION => Ok(()),
+            Response::Hello { version } => Err(anyhow!(
+                "Server speaks protocol version {}, expected {}",
+                version,
+                PROTOCOL_VERSION
+            )),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn request(&self, request: Request) -> Result<Response> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply_sender, reply_receiver) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id, reply_sender);
+
+        let mut line = serde_json::to_vec(&ClientMessage { id, request })?;
+        line.push(b'\n');
+        let written = {
+            let mut writer = self.writer.lock().unwrap();
+            writer.write_all(&line).and_then(|_| writer.flush())
+        };
+        if let Err(e) = written {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e.into());
+        }
+
+        let result = reply_receiver.recv_timeout(REPLY_TIMEOUT);
+        self.pending.lock().unwrap().remove(&id);
+        match result {
+            Ok(Response::Error { message }) => Err(anyhow!(message)),
+            Ok(response) => Ok(response),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(anyhow!("zenterm-server did not reply")),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(anyhow!("Lost connection to zenterm-server"))
+            }
+        }
+    }
+
+    fn request_ok(&self, request: Request) -> Result<()> {
+        match self.request(request)? {
+            Response::Ok => Ok(()),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Show `notice` in every attached session, outside the output stream
+    fn notify_sessions(&self, notice: &str) {
+        for session_id in self.attached.lock().unwrap().iter() {
+            let _ = self.output_sender.send(TerminalOutput {
+                session_id: session_id.clone(),
+                content: format!("\r\n\x1b[2m[{}]\x1b[0m\r\n", notice),
+                is_error: true,
+                timestamp: now_millis(),
+                offset: None,
+                highlighted: None,
+            });
+        }
+    }
+
+    /// Connect again after losing the server, starting a new one if none
+    /// is listening, and pick up the attached sessions where they are
+    fn reconnect(self: &Arc<Self>) {
+        self.notify_sessions("Lost connection to zenterm-server, reconnecting");
+        let mut spawned = false;
+
+        for _ in 0..RECONNECT_ATTEMPTS {
+            thread::sleep(RECONNECT_INTERVAL);
+            let stream = match open(&self.path) {
+                Ok(stream) => stream,
+                Err(_) => {
+                    if !spawned {
+                        spawned = spawn_server(&self.path).is_ok();
+                    }
+                    continue;
+                }
+            };
+
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            let resumed = stream
+                .try_clone()
+                .map_err(anyhow::Error::from)
+                .and_then(|writer| {
+                    *self.writer.lock().unwrap() = writer;
+                    spawn_reader(self, stream);
+                    self.resume()
+                });
+            match resumed {
+                Ok(()) => return,
+                Err(e) => {
+                    eprintln!("Failed to reconnect to zenterm-server: {}", e);
+                    // Retire the half-made connection before its reader
+                    // notices it is gone
+                    self.generation.fetch_add(1, Ordering::SeqCst);
+                    let _ = self.writer.lock().unwrap().shutdown(Shutdown::Both);
+                }
+            }
+        }
+        self.notify_sessions("Lost connection to zenterm-server");
+    }
+
+    /// Attach to the sessions again on a new connection and redraw their
+    /// screens, since their output while disconnected was lost. Sessions
+    /// the server no longer has are reported as ended.
+    fn resume(&self) -> Result<()> {
+        self.hello()?;
+
+        let sessions: Vec<String> = self.attached.lock().unwrap().iter().cloned().collect();
+        for session_id in sessions {
+            let attached = self.request_ok(Request::Attach {
+                session_id: session_id.clone(),
+            });
+            if let Err(e) = attached {
+                self.attached.lock().unwrap().remove(&session_id);
+                let _ = self.event_sender.send(SessionEvent::SessionEnded {
+                    session_id,
+                    exit_code: 1,
+                    description: format!("Lost when zenterm-server went away: {}", e),
+                });
+            }
+        }
+
+        match self.request(Request::Resync)? {
+            Response::Screens { screens } => {
+                for screen in screens {
+                    let content = screen.screen.to_ansi();
+                    let _ = self.output_sender.send(redraw(screen.session_id, &content));
+                }
+                Ok(())
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+impl Drop for ClientState {
+    /// Hang up, which also ends the reader
+    fn drop(&mut self) {
+        let _ = self.writer.lock().unwrap().shutdown(Shutdown::Both);
+    }
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    anyhow!("Unexpected reply from zenterm-server: {:?}", response)
+}
+
+/// Start `zenterm-server` in its own session so it survives this process.
+/// The binary is looked up next to the current executable, then on `PATH`.
+fn spawn_server(path: &Path) -> Result<()> {
+    let program = std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name("zenterm-server"))
+        .filter(|candidate| candidate.exists())
+        .unwrap_or_else(|| PathBuf::from("zenterm-server"));
+
+    let mut command = Command::new(program);
+    command
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Leave our session so closing the window's terminal does not hang it up
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+
+    // Reap it if it exits while we are still running
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+impl PtyHost for ServerClient {
+    fn create_session_with_history(&self, spec: &SessionSpec, history: &str) -> Result<String> {
+        match self.request(Request::Create {
+            spec: spec.clone(),
+            history: history.to_string(),
+        })? {
+            Response::Created { session_id } => {
+                self.track(&session_id, true);
+                Ok(session_id)
+            }
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn list_sessions(&self) -> Result<Vec<HostedSession>> {
+        match self.request(Request::List)? {
+            Response::Sessions { sessions } => Ok(sessions),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn attach(&self, session_id: &str) -> Result<()> {
+        self.request_ok(Request::Attach {
+            session_id: session_id.to_string(),
+        })?;
+        self.track(session_id, true);
+        Ok(())
+    }
+
+    fn detach(&self, session_id: &str) -> Result<()> {
+        self.track(session_id, false);
+        self.request_ok(Request::Detach {
+            session_id: session_id.to_string(),
+        })
+    }
+
+    fn write_command(&self, session_id: &str, command: &str) -> Result<String> {
+        match self.request(Request::Command {
+            session_id: session_id.to_string(),
+            command: command.to_string(),
+        })? {
+            Response::CommandStarted { command_id } => Ok(command_id),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn write_input(&self, session_id: &str, data: &[u8]) -> Result<()> {
+        self.request_ok(Request::Input {
+            session_id: session_id.to_string(),
+            data: data.to_vec(),
+        })
+    }
+
+    fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<()> {
+        self.request_ok(Request::Signal {
+            session_id: session_id.to_string(),
+            signal,
+        })
+    }
+
+    fn foreground_process(&self, session_id: &str) -> Result<Option<ForegroundProcess>> {
+        match self.request(Request::Foreground {
+            session_id: session_id.to_string(),
+        })? {
+            Response::Foreground { process } => Ok(process),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn toggle_recording(
+        &self,
+        session_id: &str,
+        path: Option<PathBuf>,
+        title: Option<String>,
+    ) -> Result<RecordingState> {
+        match self.request(Request::ToggleRecording {
+            session_id: session_id.to_string(),
+            path,
+            title,
+        })? {
+            Response::Recording { state } => Ok(state),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    fn resize_session(&self, session_id: &str, rows: u16, cols: u16) -> Result<()> {
+        self.request_ok(Request::Resize {
+            session_id: session_id.to_string(),
+            rows,
+            cols,
+        })
+    }
+
+    fn get_buffer(&self, session_id: &str, from_offset: u64) -> Option<BufferSlice> {
+        match self.request(Request::Buffer {
+            session_id: session_id.to_string(),
+            from_offset,
+        }) {
+            Ok(Response::Buffer { slice }) => slice,
+            _ => None,
+        }
+    }
+
+    fn get_screen(&self, session_id: &str) -> Option<ScreenSnapshot> {
+        match self.request(Request::Screen {
+            session_id: session_id.to_string(),
+        }) {
+            Ok(Response::Screen { screen }) => screen,
+            _ => None,
+        }
+    }
+
+    fn close_session(&self, session_id: &str) -> Result<()> {
+        self.track(session_id, false);
+        self.request_ok(Request::Close {
+            session_id: session_id.to_string(),
+        })
+    }
+
+    // The server compiles the triggers again; they apply to every client
+    fn set_triggers(&self, triggers: TriggerSet) -> Result<()> {
+        self.request_ok(Request::SetTriggers {
+            triggers: triggers.triggers(),
+            commands: triggers.commands().clone(),
+        })
+    }
+
+    fn set_redaction(&self, config: &RedactionConfig) -> Result<()> {
+        self.request_ok(Request::SetRedaction {
+            config: config.clone(),
+        })
+    }
+
+    fn get_output_receiver(&self) -> broadcast::Receiver<TerminalOutput> {
+        self.state.output_sender.subscribe()
+    }
+
+    fn get_event_receiver(&self) -> broadcast::Receiver<SessionEvent> {
+        self.state.event_sender.subscribe()
+    }
+
+    /// Drop what is queued locally, then fetch the screens from the server.
+    /// Output queued after the drain repeats part of the screens; its
+    /// offsets let the frontend skip it.
+    fn resync(
+        &self,
+        receiver: &mut broadcast::Receiver<TerminalOutput>,
+    ) -> Vec<(String, ScreenSnapshot, u64)> {
+        while let Ok(_) | Err(TryRecvError::Lagged(_)) = receiver.try_recv() {}
+
+        match self.request(Request::Resync) {
+            Ok(Response::Screens { screens }) => screens
+                .into_iter()
+                .map(|screen| (screen.session_id, screen.screen, screen.offset))
+                .collect(),
+            Ok(other) => {
+                eprintln!("{}", unexpected(other));
+                Vec::new()
+            }
+            Err(e) => {
+                eprintln!("Failed to resync with zenterm-server: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::runtime_dir::create_private_dir;
+    use std::os::unix::net::UnixListener;
+
+    /// Answer like a server with one session, hanging up after listing it
+    fn serve(stream: UnixStream, seen: mpsc::Sender<String>) {
+        let mut writer = stream.try_clone().unwrap();
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            let message: ClientMessage = serde_json::from_str(&line).unwrap();
+            let kind = serde_json::to_value(&message.request).unwrap()["type"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            let result = match message.request {
+                Request::Hello => Response::Hello {
+                    version: PROTOCOL_VERSION,
+                },
+                Request::Create { .. } => Response::Created {
+                    session_id: "s1".to_string(),
+                },
+                Request::Resync => Response::Screens {
+                    screens: Vec::new(),
+                },
+                _ => Response::Ok,
+            };
+            let reply = serde_json::to_string(&ServerMessage::Reply {
+                id: message.id,
+                result,
+            })
+            .unwrap();
+            writeln!(writer, "{}", reply).unwrap();
+            seen.send(kind.clone()).unwrap();
+            if kind == "list" {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_reattaches_after_reconnecting() {
+        let dir = std::env::temp_dir().join(format!("zenterm-client-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_private_dir(&dir).unwrap();
+        let path = dir.join("server.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let (seen_sender, seen) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                serve(stream.unwrap(), seen_sender.clone());
+            }
+        });
+
+        let client = ServerClient::connect(&path).unwrap();
+        let mut output = client.get_output_receiver();
+        let session_id = client
+            .create_session_with_history(&SessionSpec::default(), "")
+            .unwrap();
+        client.list_sessions().unwrap_err();
+
+        let requests: Vec<String> = (0..6)
+            .map(|_| seen.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        assert_eq!(
+            requests,
+            ["hello", "create", "list", "hello", "attach", "resync"]
+        );
+
+        // The session was told the connection dropped
+        let notice = output.try_recv().unwrap();
+        assert_eq!(notice.session_id, session_id);
+        assert!(notice.content.contains("reconnecting"));
+        assert_eq!(notice.offset, None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::terminal::screen::{Color, Screen, ScreenSnapshot};
+use vte::{Params, Parser, Perform};
+
+/// Headless VT/xterm emulator: parses PTY output and keeps a `Screen` model
+/// of what the terminal would display. Replies to queries (DSR, DA) are left
+/// to the frontend terminal, which sees the same byte stream.
+pub struct Emulator {
+    parser: Parser,
+    screen: Screen,
+}
+
+impl Emulator {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            parser: Parser::new(),
+            screen: Screen::new(rows, cols),
+        }
+    }
+
+    /// Feed raw PTY output; partial escape sequences carry over between calls
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.advance(&mut self.screen, bytes);
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.screen.resize(rows, cols);
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        self.screen.snapshot()
+    }
+}
+
+/// First parameter of a CSI sequence, with 0 treated as the default
+fn param(params: &Params, index: usize, default: u16) -> u16 {
+    match params.iter().nth(index).and_then(|p| p.first().copied()) {
+        Some(0) | None => default,
+        Some(value) => value,
+    }
+}
+
+/// Count-style parameter as usize (0 and missing both mean 1)
+fn count(params: &Params) -> usize {
+    param(params, 0, 1) as usize
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        Screen::print(self, c);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x08 => self.backspace(),
+            0x09 => self.tab(1),
+            0x0A..=0x0C => self.linefeed(),
+            0x0D => self.carriage_return(),
+            // BEL and the charset shifts have no effect on the model
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        // OSC 0 / OSC 2: window title
+        if let [b"0" | b"2", title, ..] = params {
+            self.title = String::from_utf8_lossy(title).into_owned();
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        // Charset designations (ESC ( B and friends) carry intermediates
+        if !intermediates.is_empty() {
+            return;
+        }
+        match byte {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], _ignore: bool, action: char) {
+        let private = intermediates.first() == Some(&b'?');
+        if !intermediates.is_empty() && !private {
+            // DECSTR (CSI ! p), DECSCUSR (CSI SP q) and similar
+            return;
+        }
+
+        match (private, action) {
+            (false, '@') => self.insert_chars(count(params)),
+            (false, 'A') => self.move_up(count(params)),
+            (false, 'B' | 'e') => self.move_down(count(params)),
+            (false, 'C' | 'a') => self.move_forward(count(params)),
+            (false, 'D') => self.move_backward(count(params)),
+            (false, 'E') => {
+                self.move_down(count(params));
+                self.carriage_return();
+            }
+            (false, 'F') => {
+                self.move_up(count(params));
+                self.carriage_return();
+            }
+            (false, 'G' | '`') => self.goto_col(count(params) - 1),
+            (false, 'H' | 'f') => {
+                let row = param(params, 0, 1) as usize - 1;
+                let col = param(params, 1, 1) as usize - 1;
+                self.goto(row, col);
+            }
+            (false, 'I') => self.tab(count(params)),
+            (false, 'Z') => self.back_tab(count(params)),
+            (_, 'J') => self.erase_in_display(param(params, 0, 0)),
+            (_, 'K') => self.erase_in_line(param(params, 0, 0)),
+            (false, 'L') => self.insert_lines(count(params)),
+            (false, 'M') => self.delete_lines(count(params)),
+            (false, 'P') => self.delete_chars(count(params)),
+            (false, 'S') => self.scroll_up(count(params)),
+            (false, 'T') => self.scroll_down(count(params)),
+            (false, 'X') => self.erase_chars(count(params)),
+            (false, 'd') => self.goto_row(count(params) - 1),
+            (false, 'm') => self.select_graphic_rendition(params),
+            (false, 'r') => {
+                let top = param(params, 0, 1) as usize - 1;
+                let bottom = param(params, 1, self.rows() as u16) as usize - 1;
+                self.set_scroll_region(top, bottom);
+            }
+            (false, 's') => self.save_cursor(),
+            (false, 'u') => self.restore_cursor(),
+            (_, 'h') => self.set_modes(params, private, true),
+            (_, 'l') => self.set_modes(params, private, false),
+            _ => {}
+        }
+    }
+}
+
+impl Screen {
+    fn set_modes(&mut self, params: &Params, private: bool, enable: bool) {
+        for mode in params.iter().filter_map(|p| p.first().copied()) {
+            match (private, mode) {
+                (false, 4) => self.modes.insert = enable,
+                (true, 1) => self.modes.application_cursor = enable,
+                (true, 6) => {
+                    self.modes.origin = enable;
+                    self.goto(0, 0);
+                }
+                (true, 7) => self.modes.auto_wrap = enable,
+                (true, 25) => self.modes.cursor_visible = enable,
+                (true, 47 | 1047) => {
+                    if enable {
+                        self.enter_alternate_screen(false);
+                    } else {
+                        self.leave_alternate_screen(false);
+                    }
+                }
+                (true, 1048) => {
+                    if enable {
+                        self.save_cursor();
+                    } else {
+                        self.restore_cursor();
+                    }
+                }
+                (true, 1049) => {
+                    if enable {
+                        self.enter_alternate_screen(true);
+                    } else {
+                        self.leave_alternate_screen(true);
+                    }
+                }
+                (true, 2004) => self.modes.bracketed_paste = enable,
+                _ => {}
+            }
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let mut iter = params.iter();
+        if params.is_empty() {
+            *self.pen_mut() = Default::default();
+            return;
+        }
+
+        while let Some(param) = iter.next() {
+            let pen = self.pen_mut();
+            match param[0] {
+                0 => *pen = Default::default(),
+                1 => pen.bold = true,
+                2 => pen.dim = true,
+                3 => pen.italic = true,
+                4 => pen.underline = param.get(1) != Some(&0),
+                7 => pen.inverse = true,
+                8 => pen.hidden = true,
+                9 => pen.strikethrough = true,
+                21 => pen.underline = true,
+                22 => {
+                    pen.bold = false;
+                    pen.dim = false;
+                }
+                23 => pen.italic = false,
+                24 => pen.underline = false,
+                27 => pen.inverse = false,
+                28 => pen.hidden = false,
+                29 => pen.strikethrough = false,
+                n @ 30..=37 => pen.fg = Color::Indexed((n - 30) as u8),
+                38 => {
+                    if let Some(color) = extended_color(param, &mut iter) {
+                        pen.fg = color;
+                    }
+                }
+                39 => pen.fg = Color::Default,
+                n @ 40..=47 => pen.bg = Color::Indexed((n - 40) as u8),
+                48 => {
+                    if let Some(color) = extended_color(param, &mut iter) {
+                        pen.bg = color;
+                    }
+                }
+                49 => pen.bg = Color::Default,
+                n @ 90..=97 => pen.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => pen.bg = Color::Indexed((n - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parse the color following SGR 38/48, in either the colon form
+/// (`38:5:n`, `38:2::r:g:b`) or the legacy semicolon form (`38;5;n`)
+fn extended_color<'a>(
+    param: &[u16],
+    rest: &mut impl Iterator<Item = &'a [u16]>,
+) -> Option<Color> {
+    let values: Vec<u16> = if param.len() > 1 {
+        param[1..].to_vec()
+    } else {
+        let kind = rest.next()?.first().copied()?;
+        let needed = match kind {
+            5 => 1,
+            2 => 3,
+            _ => return None,
+        };
+        let mut values = vec![kind];
+        for _ in 0..needed {
+            values.push(rest.next()?.first().copied()?);
+        }
+        values
+    };
+
+    match values.as_slice() {
+        [5, index, ..] => Some(Color::Indexed(*index as u8)),
+        // The colon form may carry a color space id before the components
+        [2, _, r, g, b] | [2, r, g, b] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::screen::CellAttrs;
+
+    fn emulate(rows: usize, cols: usize, input: &str) -> Emulator {
+        let mut emulator = Emulator::new(rows, cols);
+        emulator.process(input.as_bytes());
+        emulator
+    }
+
+    #[test]
+    fn test_plain_text_and_newlines() {
+        let emulator = emulate(4, 20, "hello\r\nworld");
+        assert_eq!(emulator.screen().text(), "hello\nworld");
+        let cursor = emulator.screen().cursor();
+        assert_eq!((cursor.row, cursor.col), (1, 5));
+    }
+
+    #[test]
+    fn test_autowrap_marks_wrapped_rows() {
+        let emulator = emulate(3, 5, "abcdefg");
+        assert_eq!(emulator.screen().text(), "abcde\nfg");
+        let snapshot = emulator.snapshot();
+        assert!(snapshot.lines[0].wrapped);
+        assert!(!snapshot.lines[1].wrapped);
+
+        // Filling the last column defers the wrap until the next character
+        let emulator = emulate(3, 5, "abcde\r\n");
+        assert_eq!(emulator.screen().text(), "abcde");
+        assert!(!emulator.snapshot().lines[0].wrapped);
+    }
+
+    #[test]
+    fn test_cursor_movement_and_erase() {
+        let emulator = emulate(4, 10, "0123456789\x1b[2;3HX\x1b[1;5H\x1b[K");
+        assert_eq!(emulator.screen().text(), "0123\n  X");
+
+        let emulator = emulate(3, 10, "aaa\r\nbbb\r\nccc\x1b[2;1H\x1b[J");
+        assert_eq!(emulator.screen().text(), "aaa");
+
+        let emulator = emulate(2, 10, "abcdef\x1b[3D\x1b[2P");
+        assert_eq!(emulator.screen().text(), "abcf");
+    }
+
+    #[test]
+    fn test_sgr_attributes() {
+        let emulator = emulate(2, 20, "\x1b[1;31mred\x1b[0m \x1b[38;2;1;2;3mrgb\x1b[48:5:200mx");
+        let snapshot = emulator.snapshot();
+        let runs = &snapshot.lines[0].runs;
+        assert_eq!(runs[0].text, "red");
+        assert_eq!(
+            runs[0].attrs,
+            CellAttrs {
+                fg: Color::Indexed(1),
+                bold: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(runs[1].text, " ");
+        assert_eq!(runs[1].attrs, CellAttrs::default());
+        assert_eq!(runs[2].text, "rgb");
+        assert_eq!(runs[2].attrs.fg, Color::Rgb(1, 2, 3));
+        assert_eq!(runs[3].text, "x");
+        assert_eq!(runs[3].attrs.bg, Color::Indexed(200));
+    }
+
+    #[test]
+    fn test_scrollback_and_scroll_region() {
+        let emulator = emulate(2, 10, "one\r\ntwo\r\nthree\r\nfour");
+        assert_eq!(emulator.screen().text(), "three\nfour");
+        assert_eq!(emulator.screen().scrollback_text(), "one\ntwo");
+        // Rows keep only their text and coloured cells once scrolled off
+        let emulator = emulate(2, 10, "one\r\n\x1b[41m  \x1b[0m\r\n\r\nx\r\ny");
+        let widths: Vec<usize> = emulator
+            .screen()
+            .scrollback()
+            .map(|row| row.cells.len())
+            .collect();
+        assert_eq!(widths, [3, 2, 0]);
+
+        // Scrolling inside a region leaves the lines outside it alone and
+        // does not feed scrollback
+        let emulator = emulate(4, 10, "top\r\na\r\nb\r\nbottom\x1b[2;3r\x1b[3;1H\n\nc");
+        assert_eq!(emulator.screen().text(), "top\n\nc\nbottom");
+        assert_eq!(emulator.screen().scrollback_text(), "");
+    }
+
+    #[test]
+    fn test_alternate_screen_restores_primary() {
+        let mut emulator = emulate(3, 10, "shell$ vim");
+        emulator.process(b"\x1b[?1049h\x1b[Hediting");
+        assert!(emulator.screen().is_alternate_screen());
+        assert_eq!(emulator.screen().text(), "editing");
+
+        emulator.process(b"\x1b[?1049l");
+        assert!(!emulator.screen().is_alternate_screen());
+        assert_eq!(emulator.screen().text(), "shell$ vim");
+        assert_eq!(emulator.screen().cursor().col, 10 - 1);
+    }
+
+    #[test]
+    fn test_wide_characters() {
+        let emulator = emulate(2, 5, "a日本");
+        assert_eq!(emulator.screen().text(), "a日本");
+        assert!(emulator.screen().cell(0, 1).unwrap().wide);
+        assert!(emulator.screen().cell(0, 2).unwrap().spacer);
+
+        // A wide character that does not fit wraps to the next row
+        let emulator = emulate(2, 4, "abc日");
+        assert_eq!(emulator.screen().text(), "abc\n日");
+
+        // Nor does it fit a one-column screen at all, so it is dropped
+        let emulator = emulate(2, 1, "日a");
+        assert_eq!(emulator.screen().text(), "a");
+    }
+
+    #[test]
+    fn test_title_and_split_sequences() {
+        let mut emulator = Emulator::new(2, 10);
+        emulator.process(b"\x1b]0;my ti");
+        emulator.process(b"tle\x07\x1b[3");
+        emulator.process(b"1mx");
+        let snapshot = emulator.snapshot();
+        assert_eq!(snapshot.title, "my title");
+        assert_eq!(snapshot.lines[0].runs[0].attrs.fg, Color::Indexed(1));
+    }
+
+    #[test]
+    fn test_snapshot_ansi_round_trip() {
+        let mut original = emulate(4, 12, "\x1b[1;33mwarn\x1b[0m: x\r\n\x1b[48;5;200m  \x1b[0m日本\x1b[2;2H");
+        original.process(b"\x1b[?25l");
+        let snapshot = original.snapshot();
+
+        let redrawn = emulate(4, 12, &snapshot.to_ansi());
+        assert_eq!(redrawn.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_resize_keeps_cursor_line() {
+        let mut emulator = emulate(4, 10, "1\r\n2\r\n3\r\n4");
+        emulator.resize(2, 5);
+        assert_eq!(emulator.screen().text(), "3\n4");
+        assert_eq!(emulator.screen().scrollback_text(), "1\n2");
+        assert_eq!(emulator.screen().cursor().row, 1);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::terminal::{PtyHost, TerminalOutput};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{timeout_at, Instant};
+
+/// Longest a chunk waits before it is delivered (one frame at ~60 fps)
+pub const FRAME_BUDGET: Duration = Duration::from_millis(16);
+
+/// Deliver a batch early once this much output is pending
+pub const MAX_BATCH_BYTES: usize = 256 * 1024;
+
+/// Sent instead of output when the forwarder fell behind the PTY readers
+/// and had to drop chunks. `content` redraws the session's current screen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalResync {
+    pub session_id: String,
+    /// Chunks dropped across all sessions
+    pub skipped: u64,
+    pub content: String,
+    /// Stream offset the redraw is current up to; later output continues here
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum Forwarded {
+    Output(TerminalOutput),
+    Resync(TerminalResync),
+}
+
+/// Output collected during one frame, merged per session in arrival order
+#[derive(Debug, Default)]
+pub struct OutputBatch {
+    outputs: Vec<TerminalOutput>,
+    bytes: usize,
+}
+
+impl OutputBatch {
+    pub fn push(&mut self, output: TerminalOutput) {
+        self.bytes += output.content.len();
+
+        let last_for_session = self
+            .outputs
+            .iter_mut()
+            .rev()
+            .find(|pending| pending.session_id == output.session_id);
+        match last_for_session {
+            // Chunks of a session are contiguous, so the merged chunk keeps
+            // the offset of its first part. Chunks without an offset (e.g. a
+            // redraw from the server) are written as they are.
+            Some(pending)
+                if pending.is_error == output.is_error
+                    && pending.offset.is_some() == output.offset.is_some() =>
+            {
+                // Once either part is highlighted, the display copy covers
+                // the whole merged chunk
+                if pending.highlighted.is_some() || output.highlighted.is_some() {
+                    let highlighted = pending
+                        .highlighted
+                        .get_or_insert_with(|| pending.content.clone());
+                    highlighted.push_str(output.highlighted.as_deref().unwrap_or(&output.content));
+                }
+                pending.content.push_str(&output.content);
+                pending.timestamp = output.timestamp;
+            }
+            _ => self.outputs.push(output),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.bytes >= MAX_BATCH_BYTES
+    }
+
+    pub fn take(&mut self) -> Vec<TerminalOutput> {
+        self.bytes = 0;
+        std::mem::take(&mut self.outputs)
+    }
+}
+
+/// Moves PTY output from the broadcast channel to a frontend, one batch per
+/// frame, resynchronising sessions from their emulated screens on lag
+pub struct OutputForwarder {
+    pty_manager: Arc<dyn PtyHost>,
+    receiver: broadcast::Receiver<TerminalOutput>,
+}
+
+impl OutputForwarder {
+    pub fn new(pty_manager: Arc<dyn PtyHost>) -> Self {
+        let receiver = pty_manager.get_output_receiver();
+        Self {
+            pty_manager,
+            receiver,
+        }
+    }
+
+    /// Forward until every sender is gone
+    pub async fn run<F>(mut self, mut emit: F)
+    where
+        F: FnMut(Forwarded),
+    {
+        let mut batch = OutputBatch::default();
+
+        loop {
+            // Sleep until the first chunk of the next frame arrives
+            match self.receiver.recv().await {
+                Ok(output) => batch.push(output),
+                Err(RecvError::Lagged(skipped)) => {
+                    self.resync(skipped, &mut emit);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            }
+
+            let deadline = Instant::now() + FRAME_BUDGET;
+            let mut closed = false;
+            while !batch.is_full() {
+                match timeout_at(deadline, self.receiver.recv()).await {
+                    Err(_) => break,
+                    Ok(Ok(output)) => batch.push(output),
+                    Ok(Err(RecvError::Lagged(skipped))) => {
+                        // The pending chunks are covered by the resync too
+                        batch.take();
+                        self.resync(skipped, &mut emit);
+                        break;
+                    }
+                    Ok(Err(RecvError::Closed)) => {
+                        closed = true;
+                        break;
+                    }
+                }
+            }
+
+            for output in batch.take() {
+                emit(Forwarded::Output(output));
+            }
+            if closed {
+                return;
+            }
+        }
+    }
+
+    fn resync<F>(&mut self, skipped: u64, emit: &mut F)
+    where
+        F: FnMut(Forwarded),
+    {
+        for (session_id, screen, offset) in self.pty_manager.resync(&mut self.receiver) {
+            emit(Forwarded::Resync(TerminalResync {
+                session_id,
+                skipped,
+                content: screen.to_ansi(),
+                offset,
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(session_id: &str, content: &str) -> TerminalOutput {
+        TerminalOutput {
+            session_id: session_id.to_string(),
+            content: content.to_string(),
+            is_error: false,
+            timestamp: 0,
+            offset: None,
+            highlighted: None,
+        }
+    }
+
+    #[test]
+    fn test_batch_merges_chunks_per_session() {
+        let mut batch = OutputBatch::default();
+        batch.push(output("a", "one "));
+        batch.push(output("b", "other"));
+        batch.push(output("a", "two"));
+
+        let outputs = batch.take();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].content, "one two");
+        assert_eq!(outputs[1].content, "other");
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_batch_merges_highlighted_chunks() {
+        let mut batch = OutputBatch::default();
+        let mut first = output("a", "error ");
+        first.highlighted = Some("\x1b[31merror\x1b[0m ".to_string());
+        batch.push(first);
+        batch.push(output("a", "plain "));
+        let mut last = output("a", "error");
+        last.highlighted = Some("\x1b[31merror\x1b[0m".to_string());
+        batch.push(last);
+
+        let outputs = batch.take();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].content, "error plain error");
+        assert_eq!(
+            outputs[0].highlighted.as_deref(),
+            Some("\x1b[31merror\x1b[0m plain \x1b[31merror\x1b[0m")
+        );
+
+        // A plain chunk followed by a highlighted one keeps the plain part
+        batch.push(output("a", "plain "));
+        let mut second = output("a", "error");
+        second.highlighted = Some("\x1b[31merror\x1b[0m".to_string());
+        batch.push(second);
+        let outputs = batch.take();
+        assert_eq!(
+            outputs[0].highlighted.as_deref(),
+            Some("plain \x1b[31merror\x1b[0m")
+        );
+    }
+
+    #[test]
+    fn test_batch_fills_up() {
+        let mut batch = OutputBatch::default();
+        batch.push(output("a", &"x".repeat(MAX_BATCH_BYTES - 1)));
+        assert!(!batch.is_full());
+        batch.push(output("a", "x"));
+        assert!(batch.is_full());
+        batch.take();
+        assert!(!batch.is_full());
+    }
+}
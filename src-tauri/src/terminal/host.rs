@@ -0,0 +1,157 @@
+use crate::terminal::{
+    BufferSlice, ForegroundProcess, PtyManager, RecordingState, ScreenSnapshot, SessionEvent,
+    SessionSignal, SessionSpec, TerminalOutput, TriggerSet,
+};
+use anyhow::Result;
+use redaction::RedactionConfig;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+
+/// A session running in a PTY host, as listed by `PtyHost::list_sessions`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostedSession {
+    pub id: String,
+    pub spec: SessionSpec,
+    /// Last known working directory
+    pub cwd: String,
+    /// Process id of the shell
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+/// Owner of the PTYs behind the sessions: either this process
+/// (`PtyManager`) or a `zenterm-server` daemon reached through
+/// `ServerClient`. Output and events only arrive for sessions created or
+/// attached through this host.
+pub trait PtyHost: Send + Sync {
+    /// Start a session whose screen and buffer begin with `history`
+    fn create_session_with_history(&self, spec: &SessionSpec, history: &str) -> Result<String>;
+
+    fn create_session(&self, spec: &SessionSpec) -> Result<String> {
+        self.create_session_with_history(spec, "")
+    }
+
+    /// Sessions the host is running, including ones started by other clients
+    fn list_sessions(&self) -> Result<Vec<HostedSession>>;
+
+    /// Receive the output and events of a session started elsewhere
+    fn attach(&self, session_id: &str) -> Result<()>;
+
+    /// Stop receiving a session's output and events, leaving it running
+    fn detach(&self, session_id: &str) -> Result<()>;
+
+    fn write_command(&self, session_id: &str, command: &str) -> Result<String>;
+    fn write_input(&self, session_id: &str, data: &[u8]) -> Result<()>;
+    fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<()>;
+    fn foreground_process(&self, session_id: &str) -> Result<Option<ForegroundProcess>>;
+    fn toggle_recording(
+        &self,
+        session_id: &str,
+        path: Option<PathBuf>,
+        title: Option<String>,
+    ) -> Result<RecordingState>;
+    fn resize_session(&self, session_id: &str, rows: u16, cols: u16) -> Result<()>;
+    fn get_buffer(&self, session_id: &str, from_offset: u64) -> Option<BufferSlice>;
+    fn get_screen(&self, session_id: &str) -> Option<ScreenSnapshot>;
+    fn close_session(&self, session_id: &str) -> Result<()>;
+    fn set_triggers(&self, triggers: TriggerSet) -> Result<()>;
+    fn set_redaction(&self, config: &RedactionConfig) -> Result<()>;
+
+    fn get_output_receiver(&self) -> broadcast::Receiver<TerminalOutput>;
+    fn get_event_receiver(&self) -> broadcast::Receiver<SessionEvent>;
+
+    /// Recover a receiver that lagged behind the output channel, see
+    /// `PtyManager::resync`
+    fn resync(
+        &self,
+        receiver: &mut broadcast::Receiver<TerminalOutput>,
+    ) -> Vec<(String, ScreenSnapshot, u64)>;
+}
+
+impl PtyHost for PtyManager {
+    fn create_session_with_history(&self, spec: &SessionSpec, history: &str) -> Result<String> {
+        PtyManager::create_session_with_history(self, spec, history)
+    }
+
+    fn list_sessions(&self) -> Result<Vec<HostedSession>> {
+        Ok(PtyManager::list_sessions(self))
+    }
+
+    // Every session of an in-process host is already attached
+    fn attach(&self, session_id: &str) -> Result<()> {
+        if self.get_screen(session_id).is_none() {
+            return Err(anyhow::anyhow!("Session not found"));
+        }
+        Ok(())
+    }
+
+    fn detach(&self, _session_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn write_command(&self, session_id: &str, command: &str) -> Result<String> {
+        PtyManager::write_command(self, session_id, command)
+    }
+
+    fn write_input(&self, session_id: &str, data: &[u8]) -> Result<()> {
+        PtyManager::write_input(self, session_id, data)
+    }
+
+    fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<()> {
+        PtyManager::send_signal(self, session_id, signal)
+    }
+
+    fn foreground_process(&self, session_id: &str) -> Result<Option<ForegroundProcess>> {
+        PtyManager::foreground_process(self, session_id)
+    }
+
+    fn toggle_recording(
+        &self,
+        session_id: &str,
+        path: Option<PathBuf>,
+        title: Option<String>,
+    ) -> Result<RecordingState> {
+        PtyManager::toggle_recording(self, session_id, path, title)
+    }
+
+    fn resize_session(&self, session_id: &str, rows: u16, cols: u16) -> Result<()> {
+        PtyManager::resize_session(self, session_id, rows, cols)
+    }
+
+    fn get_buffer(&self, session_id: &str, from_offset: u64) -> Option<BufferSlice> {
+        PtyManager::get_buffer(self, session_id, from_offset)
+    }
+
+    fn get_screen(&self, session_id: &str) -> Option<ScreenSnapshot> {
+        PtyManager::get_screen(self, session_id)
+    }
+
+    fn close_session(&self, session_id: &str) -> Result<()> {
+        PtyManager::close_session(self, session_id)
+    }
+
+    fn set_triggers(&self, triggers: TriggerSet) -> Result<()> {
+        PtyManager::set_triggers(self, triggers);
+        Ok(())
+    }
+
+    fn set_redaction(&self, config: &RedactionConfig) -> Result<()> {
+        PtyManager::set_redaction(self, config)
+    }
+
+    fn get_output_receiver(&self) -> broadcast::Receiver<TerminalOutput> {
+        PtyManager::get_output_receiver(self)
+    }
+
+    fn get_event_receiver(&self) -> broadcast::Receiver<SessionEvent> {
+        PtyManager::get_event_receiver(self)
+    }
+
+    fn resync(
+        &self,
+        receiver: &mut broadcast::Receiver<TerminalOutput>,
+    ) -> Vec<(String, ScreenSnapshot, u64)> {
+        PtyManager::resync(self, receiver)
+    }
+}
@@ -1,5 +1,36 @@
+pub mod broadcast;
+pub mod buffer;
+pub mod client;
+pub mod emulator;
+pub mod forward;
+pub mod host;
+pub mod process;
+pub mod protocol;
 pub mod pty;
+pub mod recording;
+pub mod runtime_dir;
+pub mod screen;
+pub mod server;
 pub mod session;
+pub mod shell_integration;
+pub mod snapshot;
+pub mod spec;
+pub mod trigger;
 
+pub use broadcast::BroadcastGroup;
+pub use buffer::BufferSlice;
+pub use client::ServerClient;
+pub use emulator::Emulator;
+pub use forward::{Forwarded, OutputForwarder, TerminalResync};
+pub use host::{HostedSession, PtyHost};
+pub use process::{ForegroundProcess, SessionSignal};
+pub use protocol::{default_socket_path, PROTOCOL_VERSION};
 pub use pty::*;
-pub use session::*;
\ No newline at end of file
+pub use recording::{PlaybackControl, PlaybackEvent, Recording, RecordingState};
+pub use screen::ScreenSnapshot;
+pub use session::*;
+pub use snapshot::{SavedSession, SessionSnapshot, SNAPSHOT_VERSION};
+pub use spec::SessionSpec;
+pub use trigger::{
+    RegisteredCommand, Trigger, TriggerAction, TriggerFired, TriggerScope, TriggerSet,
+};
\ No newline at end of file
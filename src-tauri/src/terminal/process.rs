@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Signals a frontend may send to a session's foreground process group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SessionSignal {
+    #[serde(rename = "SIGINT")]
+    Interrupt,
+    #[serde(rename = "SIGTERM")]
+    Terminate,
+    #[serde(rename = "SIGKILL")]
+    Kill,
+    #[serde(rename = "SIGTSTP")]
+    Stop,
+    /// Resumes the group last stopped with `Stop`. The shell keeps the
+    /// terminal, so the job continues in the background until `fg`.
+    #[serde(rename = "SIGCONT")]
+    Continue,
+}
+
+impl SessionSignal {
+    pub fn as_raw(self) -> libc::c_int {
+        match self {
+            SessionSignal::Interrupt => libc::SIGINT,
+            SessionSignal::Terminate => libc::SIGTERM,
+            SessionSignal::Kill => libc::SIGKILL,
+            SessionSignal::Stop => libc::SIGTSTP,
+            SessionSignal::Continue => libc::SIGCONT,
+        }
+    }
+}
+
+/// The process group currently in the foreground of a session's terminal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForegroundProcess {
+    /// Process group leader, as reported by `tcgetpgrp`
+    pub pid: u32,
+    /// Executable name from `/proc/<pid>/comm`, e.g. "cargo"
+    pub name: String,
+    /// Full command line, e.g. "cargo build --release"
+    pub command: String,
+    /// The shell itself is in the foreground, i.e. the session is idle
+    pub is_shell: bool,
+}
+
+impl ForegroundProcess {
+    /// Describe a running process from `/proc`
+    pub fn read(pid: u32, shell_pid: Option<u32>) -> Option<Self> {
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        let name = name.trim_end().to_string();
+        let command = fs::read(format!("/proc/{}/cmdline", pid))
+            .map(|raw| parse_cmdline(&raw).join(" "))
+            .unwrap_or_default();
+
+        Some(Self {
+            pid,
+            command: if command.is_empty() { name.clone() } else { command },
+            name,
+            is_shell: shell_pid == Some(pid),
+        })
+    }
+}
+
+/// Split the NUL-separated contents of `/proc/<pid>/cmdline`
+fn parse_cmdline(raw: &[u8]) -> Vec<String> {
+    raw.split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+/// Send a signal to every process in a group
+pub fn signal_group(pgid: libc::pid_t, signal: SessionSignal) -> std::io::Result<()> {
+    if unsafe { libc::killpg(pgid, signal.as_raw()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmdline() {
+        assert_eq!(
+            parse_cmdline(b"cargo\0build\0--release\0"),
+            vec!["cargo", "build", "--release"]
+        );
+        assert!(parse_cmdline(b"").is_empty());
+    }
+
+    #[test]
+    fn test_read_own_process() {
+        let pid = std::process::id();
+        let process = ForegroundProcess::read(pid, Some(pid)).unwrap();
+        assert_eq!(process.pid, pid);
+        assert!(!process.name.is_empty());
+        assert!(!process.command.is_empty());
+        assert!(process.is_shell);
+
+        assert!(!ForegroundProcess::read(pid, None).unwrap().is_shell);
+    }
+
+    #[test]
+    fn test_signal_names() {
+        let signal: SessionSignal = serde_json::from_str(r#""SIGTSTP""#).unwrap();
+        assert_eq!(signal, SessionSignal::Stop);
+        assert_eq!(signal.as_raw(), libc::SIGTSTP);
+        assert_eq!(serde_json::to_string(&SessionSignal::Interrupt).unwrap(), r#""SIGINT""#);
+    }
+}
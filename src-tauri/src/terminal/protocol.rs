@@ -0,0 +1,156 @@
+//! Messages between `zenterm-server` and its clients. Each message is one
+//! line of JSON on the server's Unix socket; see DEVELOPMENT.md for the
+//! protocol.
+
+use crate::terminal::runtime_dir::runtime_dir;
+use crate::terminal::{
+    BufferSlice, ForegroundProcess, HostedSession, RecordingState, RegisteredCommand,
+    ScreenSnapshot, SessionEvent, SessionSignal, SessionSpec, TerminalOutput, TerminalResync,
+    Trigger,
+};
+use redaction::RedactionConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Bumped whenever messages change incompatibly; exchanged in `hello`
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Where the server listens unless told otherwise:
+/// `$XDG_RUNTIME_DIR/zenterm/server.sock`, or a per-user directory under
+/// the temp dir
+pub fn default_socket_path() -> PathBuf {
+    runtime_dir().join("server.sock")
+}
+
+/// A request from a client; the reply carries the same `id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientMessage {
+    pub id: u64,
+    pub request: Request,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Request {
+    /// Check the protocol version; answered with `Response::Hello`
+    Hello,
+    /// Start a session and attach to it
+    Create {
+        spec: SessionSpec,
+        /// Scrollback shown ahead of the shell's output
+        #[serde(default)]
+        history: String,
+    },
+    /// List every running session
+    List,
+    /// Start receiving a session's output and events
+    Attach { session_id: String },
+    /// Stop receiving a session's output and events; it keeps running
+    Detach { session_id: String },
+    Input { session_id: String, data: Vec<u8> },
+    /// Run a command line as a command block
+    Command { session_id: String, command: String },
+    Resize { session_id: String, rows: u16, cols: u16 },
+    Signal { session_id: String, signal: SessionSignal },
+    Foreground { session_id: String },
+    ToggleRecording {
+        session_id: String,
+        #[serde(default)]
+        path: Option<PathBuf>,
+        #[serde(default)]
+        title: Option<String>,
+    },
+    Buffer { session_id: String, from_offset: u64 },
+    Screen { session_id: String },
+    /// Screens of all attached sessions, for a client that fell behind
+    Resync,
+    /// End a session for every client
+    Close { session_id: String },
+    /// Replace the output triggers of all sessions
+    SetTriggers {
+        triggers: Vec<Trigger>,
+        commands: BTreeMap<String, RegisteredCommand>,
+    },
+    /// Replace the secret detectors of all sessions
+    SetRedaction { config: RedactionConfig },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Reply { id: u64, result: Response },
+    /// Output of an attached session
+    Output(TerminalOutput),
+    /// Lifecycle event of an attached session
+    Event(SessionEvent),
+    /// The server fell behind and dropped output of an attached session;
+    /// `content` redraws its screen
+    Resync(TerminalResync),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Error { message: String },
+    Hello { version: u32 },
+    Created { session_id: String },
+    Sessions { sessions: Vec<HostedSession> },
+    CommandStarted { command_id: String },
+    Foreground { process: Option<ForegroundProcess> },
+    Recording { state: RecordingState },
+    Buffer { slice: Option<BufferSlice> },
+    Screen { screen: Option<ScreenSnapshot> },
+    Screens { screens: Vec<SessionScreen> },
+}
+
+/// A session's screen and the stream offset it is current up to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionScreen {
+    pub session_id: String,
+    pub screen: ScreenSnapshot,
+    pub offset: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_format() {
+        let message = ClientMessage {
+            id: 7,
+            request: Request::Input {
+                session_id: "s".to_string(),
+                data: b"ls\r".to_vec(),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::json!({
+                "id": 7,
+                "request": { "type": "input", "data": { "session_id": "s", "data": [108, 115, 13] } },
+            })
+        );
+
+        // Variants without fields need no `data`
+        let hello: ClientMessage =
+            serde_json::from_str(r#"{"id":1,"request":{"type":"hello"}}"#).unwrap();
+        assert!(matches!(hello.request, Request::Hello));
+
+        let reply = ServerMessage::Reply {
+            id: 7,
+            result: Response::Ok,
+        };
+        let json = serde_json::to_string(&reply).unwrap();
+        assert_eq!(json, r#"{"type":"reply","data":{"id":7,"result":{"type":"ok"}}}"#);
+        assert!(matches!(
+            serde_json::from_str(&json).unwrap(),
+            ServerMessage::Reply {
+                id: 7,
+                result: Response::Ok
+            }
+        ));
+    }
+}
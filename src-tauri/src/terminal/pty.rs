@@ -1,11 +1,23 @@
+use crate::terminal::buffer::{BufferSlice, OutputBuffer};
+use crate::terminal::emulator::Emulator;
+use crate::terminal::process::{signal_group, ForegroundProcess, SessionSignal};
+use crate::terminal::recording::{default_recording_path, Recorder, RecordingState};
+use crate::terminal::screen::ScreenSnapshot;
+use crate::terminal::shell_integration::{BlockUpdate, CommandTracker, OscParser, ShellEvent};
+use crate::terminal::trigger::{TriggerAction, TriggerFired, TriggerScanner, TriggerSet};
+use crate::terminal::{HostedSession, SessionSpec};
 use anyhow::Result;
-use portable_pty::{CommandBuilder, PtySize};
+use portable_pty::{Child, ExitStatus, PtySize};
+use redaction::{RedactionConfig, Redactor, StreamRedactor};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +26,14 @@ pub struct TerminalOutput {
     pub content: String,
     pub is_error: bool,
     pub timestamp: u64,
+    /// Byte offset of `content` in the session's output stream; `None` for
+    /// output captured into command blocks
+    #[serde(default)]
+    pub offset: Option<u64>,
+    /// `content` with trigger highlights, for display only; the screen,
+    /// buffer, recordings and `offset` all refer to `content`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlighted: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +46,9 @@ pub struct CommandBlock {
     pub start_time: u64,
     pub end_time: Option<u64>,
     pub status: CommandStatus,
+    /// Directory the session was in when the command started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,137 +58,747 @@ pub enum CommandStatus {
     Failed,
 }
 
+/// Lifecycle notifications emitted by the PTY reader threads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum SessionEvent {
+    CommandStarted(CommandBlock),
+    CommandFinished(CommandBlock),
+    /// The working directory of the session changed
+    DirectoryChanged { session_id: String, path: String },
+    /// The shell exited on its own (e.g. `exit` or Ctrl-D)
+    SessionEnded {
+        session_id: String,
+        exit_code: u32,
+        description: String,
+    },
+    /// Output matched a configured trigger
+    TriggerFired(TriggerFired),
+}
+
+impl SessionEvent {
+    pub fn session_id(&self) -> &str {
+        match self {
+            SessionEvent::CommandStarted(block) | SessionEvent::CommandFinished(block) => {
+                &block.session_id
+            }
+            SessionEvent::DirectoryChanged { session_id, .. }
+            | SessionEvent::SessionEnded { session_id, .. } => session_id,
+            SessionEvent::TriggerFired(fired) => &fired.session_id,
+        }
+    }
+}
+
+/// Size of a new PTY until the frontend sends its first resize
+const DEFAULT_ROWS: u16 = 24;
+const DEFAULT_COLS: u16 = 80;
+
+/// How often the watcher polls a session's shell for exit
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Time a shell gets to exit after SIGHUP before it is killed
+const CLOSE_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Incremental UTF-8 decoder for raw PTY reads.
+///
+/// A read can end in the middle of a multi-byte character; the incomplete
+/// tail is held back and completed by the next chunk. Invalid bytes are
+/// replaced with U+FFFD instead of dropping the whole chunk.
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Decode a chunk, returning every complete character seen so far
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+
+        let mut out = String::with_capacity(self.pending.len());
+        let mut pos = 0;
+
+        while pos < self.pending.len() {
+            match std::str::from_utf8(&self.pending[pos..]) {
+                Ok(valid) => {
+                    out.push_str(valid);
+                    pos = self.pending.len();
+                }
+                Err(e) => {
+                    let valid_end = pos + e.valid_up_to();
+                    // Safe: `valid_up_to` marks the end of a valid UTF-8 prefix
+                    out.push_str(std::str::from_utf8(&self.pending[pos..valid_end]).unwrap());
+                    match e.error_len() {
+                        Some(len) => {
+                            out.push(char::REPLACEMENT_CHARACTER);
+                            pos = valid_end + len;
+                        }
+                        None => {
+                            // Incomplete sequence at the end, wait for more bytes
+                            pos = valid_end;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending.drain(..pos);
+        out
+    }
+
+    /// Flush any bytes still held back, replacing them lossily
+    pub fn finish(&mut self) -> String {
+        let out = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        out
+    }
+}
+
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
 pub struct PtyManager {
     sessions: Arc<Mutex<HashMap<String, PtySession>>>,
     output_sender: broadcast::Sender<TerminalOutput>,
+    event_sender: broadcast::Sender<SessionEvent>,
+    /// Output triggers, swapped as a whole when the config changes
+    triggers: Arc<Mutex<Arc<TriggerSet>>>,
+    /// Masks secrets in output before anything else sees it
+    redactor: Arc<Mutex<Arc<Redactor>>>,
 }
 
+/// Input side of a PTY. It has its own lock so a program that stops
+/// reading its input blocks only writes to its own session.
+pub type PtyWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
 pub struct PtySession {
     pub id: String,
     pub pty: Box<dyn portable_pty::MasterPty + Send>,
-    pub writer: Box<dyn Write + Send>,
+    pub writer: PtyWriter,
+    pub child: Box<dyn Child + Send + Sync>,
+    /// What the session was started with
+    pub spec: SessionSpec,
     pub current_command: Option<CommandBlock>,
+    /// Last known working directory of the session, from OSC 7 reports
+    /// and `/proc/<pid>/cwd`
+    pub cwd: String,
+    /// Headless model of the session's screen, fed with the raw output
+    pub emulator: Emulator,
+    /// Recent output, replayed to frontends that attach late
+    pub buffer: OutputBuffer,
+    /// Process group last suspended with `SessionSignal::Stop`
+    pub stopped_group: Option<libc::pid_t>,
+    /// Active asciicast recording of the session
+    pub recorder: Option<Recorder>,
+}
+
+impl PtySession {
+    /// Record a new working directory, returning whether it changed
+    fn update_cwd(&mut self, path: String) -> bool {
+        if self.cwd == path {
+            return false;
+        }
+        self.cwd = path;
+        true
+    }
+
+    /// Write to the active recording, if any; a failed write ends it
+    fn record(&mut self, write: impl FnOnce(&mut Recorder) -> Result<()>) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = write(recorder) {
+                eprintln!("Stopped recording to {:?}: {}", recorder.path(), e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Foreground process group of the terminal (`tcgetpgrp` on the master),
+    /// falling back to the shell
+    fn foreground_group(&self) -> Option<libc::pid_t> {
+        self.pty
+            .process_group_leader()
+            .or_else(|| self.child.process_id().map(|pid| pid as libc::pid_t))
+    }
+
+}
+
+/// Working directory of process `pid`, read from `/proc`
+fn process_cwd(pid: libc::pid_t) -> Option<String> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid))
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
 }
 
 impl PtyManager {
     pub fn new() -> Self {
         let (output_sender, _) = broadcast::channel(1000);
+        let (event_sender, _) = broadcast::channel(1000);
         
         PtyManager {
             sessions: Arc::new(Mutex::new(HashMap::new())),
             output_sender,
+            event_sender,
+            triggers: Arc::new(Mutex::new(Arc::new(TriggerSet::default()))),
+            redactor: Arc::new(Mutex::new(Arc::new(Redactor::new()))),
         }
     }
 
-    pub fn create_session(&self) -> Result<String> {
+    /// Replace the secret detectors applied to the output, input recordings
+    /// and commands of every session
+    pub fn set_redaction(&self, config: &RedactionConfig) -> Result<()> {
+        *self.redactor.lock().unwrap() = Arc::new(Redactor::from_config(config)?);
+        Ok(())
+    }
+
+    fn redactor(&self) -> Arc<Redactor> {
+        Arc::clone(&self.redactor.lock().unwrap())
+    }
+
+    /// Replace the triggers that watch the output of every session
+    pub fn set_triggers(&self, triggers: TriggerSet) {
+        *self.triggers.lock().unwrap() = Arc::new(triggers);
+    }
+
+    pub fn create_session(&self, spec: &SessionSpec) -> Result<String> {
+        self.create_session_with_history(spec, "")
+    }
+
+    /// Create a session whose screen and buffer start with `history`, e.g.
+    /// scrollback restored from a previous run, ahead of any shell output
+    pub fn create_session_with_history(&self, spec: &SessionSpec, history: &str) -> Result<String> {
         let session_id = Uuid::new_v4().to_string();
         
         let pty_system = portable_pty::native_pty_system();
         let pty_pair = pty_system.openpty(PtySize {
-            rows: 24,
-            cols: 80,
+            rows: DEFAULT_ROWS,
+            cols: DEFAULT_COLS,
             pixel_width: 0,
             pixel_height: 0,
         })?;
 
-        let mut cmd = CommandBuilder::new("/bin/bash");
-        cmd.env("TERM", "xterm-256color");
+        let cmd = spec.build_command()?;
         
         let child = pty_pair.slave.spawn_command(cmd)?;
-        std::mem::drop(child); // The child process will continue running
 
         let reader = pty_pair.master.try_clone_reader()?;
-        let writer = pty_pair.master.take_writer()?;
+        let writer: PtyWriter = Arc::new(Mutex::new(pty_pair.master.take_writer()?));
 
-        let session = PtySession {
+        let mut session = PtySession {
             id: session_id.clone(),
             pty: pty_pair.master,
-            writer,
+            writer: Arc::clone(&writer),
+            child,
+            spec: spec.clone(),
             current_command: None,
+            cwd: spec.resolve_cwd(),
+            emulator: Emulator::new(DEFAULT_ROWS as usize, DEFAULT_COLS as usize),
+            buffer: OutputBuffer::default(),
+            stopped_group: None,
+            recorder: None,
         };
+        if !history.is_empty() {
+            let history = self.redactor().redact(history);
+            session.emulator.process(history.as_bytes());
+            session.buffer.push(&history);
+        }
+
+        // Register the session before the reader starts so no markers are missed
+        self.sessions.lock().unwrap().insert(session_id.clone(), session);
 
         // Start reading output in a separate thread
         let sessions_arc = Arc::clone(&self.sessions);
         let output_sender = self.output_sender.clone();
+        let event_sender = self.event_sender.clone();
+        let triggers_arc = Arc::clone(&self.triggers);
+        let redactor_arc = Arc::clone(&self.redactor);
         let session_id_clone = session_id.clone();
+        let mut scanner = TriggerScanner::new(session_id.clone(), spec.profile.clone());
 
+        // Trigger replies are written from their own thread, so a program
+        // that is not reading its input cannot stall the output
+        let (reply_sender, reply_receiver) = mpsc::channel::<(String, String)>();
         thread::spawn(move || {
-            let buf_reader = BufReader::new(reader);
-            for line in buf_reader.lines() {
-                if let Ok(content) = line {
-                    let output = TerminalOutput {
-                        session_id: session_id_clone.clone(),
-                        content,
-                        is_error: false,
-                        timestamp: std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64,
+            for (trigger, text) in reply_receiver {
+                let mut writer = writer.lock().unwrap();
+                if let Err(e) = writer
+                    .write_all(text.as_bytes())
+                    .and_then(|_| writer.flush())
+                {
+                    eprintln!("Trigger '{}' failed to reply: {}", trigger, e);
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            let mut reader = reader;
+            let mut decoder = Utf8Decoder::default();
+            let mut parser = OscParser::default();
+            let mut tracker = CommandTracker::new(session_id_clone.clone());
+            let mut redaction = StreamRedactor::default();
+            let mut buf = [0u8; 4096];
+            // Lines each reply trigger answered during the current command
+            let mut replied: HashSet<(String, String)> = HashSet::new();
+
+            let mut send = |content: String| {
+                let events = parser.feed(&content);
+                let triggers = Arc::clone(&triggers_arc.lock().unwrap());
+                let fired = scanner.scan(&triggers, &content);
+
+                let mut sessions = sessions_arc.lock().unwrap();
+                let Some(session) = sessions.get_mut(&session_id_clone) else {
+                    return;
+                };
+
+                // Feed the shell integration markers into the session's command blocks
+                for event in events {
+                    if let ShellEvent::WorkingDirectory(path) = &event {
+                        if session.update_cwd(path.clone()) {
+                            let _ = event_sender.send(SessionEvent::DirectoryChanged {
+                                session_id: session_id_clone.clone(),
+                                path: path.clone(),
+                            });
+                        }
+                    }
+
+                    let update = match tracker.handle(event, &mut session.current_command) {
+                        Some(BlockUpdate::Started(block)) => {
+                            replied.clear();
+                            SessionEvent::CommandStarted(block)
+                        }
+                        Some(BlockUpdate::Finished(block)) => SessionEvent::CommandFinished(block),
+                        None => continue,
                     };
-                    
-                    let _ = output_sender.send(output);
+                    let _ = event_sender.send(update);
+                }
+
+                // Carry out what the triggers matched in this chunk asked for
+                let mut replies = Vec::new();
+                for fired in fired {
+                    match &fired.action {
+                        // Only answer programs run as a command, not text the
+                        // shell prints (e.g. `cat` of a log), and each line
+                        // once, so a reply echoing its own match cannot loop
+                        TriggerAction::Reply { text } => {
+                            if session.current_command.is_some()
+                                && replied.insert((fired.trigger.clone(), fired.line.clone()))
+                            {
+                                session.record(|r| r.input(text.as_bytes()));
+                                replies.push((fired.trigger.clone(), text.clone()));
+                            }
+                        }
+                        TriggerAction::RunCommand { command } => {
+                            triggers.run_command(command, &fired)
+                        }
+                        TriggerAction::Highlight { .. } | TriggerAction::Notify { .. } => {}
+                    }
+                    let _ = event_sender.send(SessionEvent::TriggerFired(fired));
+                }
+
+                // Update the screen and buffer and publish the output under the
+                // same lock, so snapshots and offsets match what has been sent
+                let pen = session.emulator.screen().cursor().attrs;
+                session.emulator.process(content.as_bytes());
+                let offset = session.buffer.push(&content);
+                session.record(|recorder| recorder.output(&content));
+
+                let output = TerminalOutput {
+                    session_id: session_id_clone.clone(),
+                    highlighted: triggers.highlight(&content, scanner.profile(), pen),
+                    content,
+                    is_error: false,
+                    timestamp: now_millis(),
+                    offset: Some(offset),
+                };
+
+                let _ = output_sender.send(output);
+                drop(sessions);
+
+                for reply in replies {
+                    let _ = reply_sender.send(reply);
+                }
+            };
+
+            // Secrets are masked before the output reaches the screen, buffer,
+            // command blocks, recording or any client
+            let mut redact = |content: &str, last: bool| {
+                let redactor = Arc::clone(&redactor_arc.lock().unwrap());
+                let mut masked = redaction.feed(&redactor, content);
+                if last {
+                    masked.push_str(&redaction.finish(&redactor));
+                }
+                masked
+            };
+
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        let content = redact(&decoder.decode(&buf[..n]), false);
+                        if !content.is_empty() {
+                            send(content);
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break,
                 }
             }
+
+            // Flush a truncated multi-byte sequence and any output held back
+            // as a possible secret
+            let tail = redact(&decoder.finish(), true);
+            if !tail.is_empty() {
+                send(tail);
+            }
         });
 
-        self.sessions.lock().unwrap().insert(session_id.clone(), session);
+        self.spawn_exit_watcher(session_id.clone());
+
         Ok(session_id)
     }
 
+    /// Watch for the shell exiting on its own and report it once.
+    /// Stops quietly when the session is closed, since `close_session`
+    /// takes over reaping the child. Also polls the working directory, for
+    /// shells without OSC 7 support and programs that change it without a
+    /// new prompt, such as a nested shell.
+    fn spawn_exit_watcher(&self, session_id: String) {
+        let sessions_arc = Arc::clone(&self.sessions);
+        let event_sender = self.event_sender.clone();
+
+        thread::spawn(move || loop {
+            thread::sleep(CHILD_POLL_INTERVAL);
+
+            let (group, known) = match sessions_arc.lock().unwrap().get(&session_id) {
+                Some(session) => (session.foreground_group(), session.cwd.clone()),
+                None => break,
+            };
+
+            // The directory may be on a slow or stale mount, so it is looked
+            // up without holding the sessions. OSC 7 reports the path as
+            // typed, which may lead through a symlink to the same directory.
+            let changed = group.and_then(process_cwd).filter(|path| {
+                !std::fs::canonicalize(&known).is_ok_and(|cwd| cwd.as_os_str() == path.as_str())
+            });
+
+            let status = match sessions_arc.lock().unwrap().get_mut(&session_id) {
+                Some(session) => {
+                    // Skip it if OSC 7 reported another directory meanwhile
+                    if let Some(path) = changed.filter(|_| session.cwd == known) {
+                        if session.update_cwd(path.clone()) {
+                            let _ = event_sender.send(SessionEvent::DirectoryChanged {
+                                session_id: session_id.clone(),
+                                path,
+                            });
+                        }
+                    }
+                    session.child.try_wait()
+                }
+                None => break,
+            };
+
+            match status {
+                Ok(Some(status)) => {
+                    let _ = event_sender.send(SessionEvent::SessionEnded {
+                        session_id: session_id.clone(),
+                        exit_code: status.exit_code(),
+                        description: status.to_string(),
+                    });
+                    break;
+                }
+                Ok(None) => continue,
+                Err(_) => break,
+            }
+        });
+    }
+
     pub fn write_command(&self, session_id: &str, command: &str) -> Result<String> {
+        let redactor = self.redactor();
         let mut sessions = self.sessions.lock().unwrap();
         
         if let Some(session) = sessions.get_mut(session_id) {
             let command_id = Uuid::new_v4().to_string();
-            let timestamp = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64;
+            let timestamp = now_millis();
 
             let command_block = CommandBlock {
                 id: command_id.clone(),
                 session_id: session_id.to_string(),
-                command: command.to_string(),
+                command: redactor.redact(command).into_owned(),
                 output: Vec::new(),
                 exit_code: None,
                 start_time: timestamp,
                 end_time: None,
                 status: CommandStatus::Running,
+                cwd: None,
             };
 
             session.current_command = Some(command_block);
-            
-            // Write command to PTY
-            writeln!(session.writer, "{}", command)?;
-            
+            let recorded = format!("{}\n", redactor.redact(command));
+            session.record(|recorder| recorder.input(recorded.as_bytes()));
+
+            // Write command to PTY, without holding up the other sessions
+            let writer = Arc::clone(&session.writer);
+            drop(sessions);
+            let mut writer = writer.lock().unwrap();
+            writeln!(writer, "{}", command)?;
+            writer.flush()?;
+
             Ok(command_id)
         } else {
             Err(anyhow::anyhow!("Session not found"))
         }
     }
 
+    /// Write raw input (keystrokes, control characters, pasted text) to the
+    /// session verbatim. Unlike `write_command` this appends no newline and
+    /// starts no command block.
+    pub fn write_input(&self, session_id: &str, data: &[u8]) -> Result<()> {
+        let redactor = self.redactor();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        if let Some(session) = sessions.get_mut(session_id) {
+            // Keystrokes arrive one at a time; this catches pasted secrets
+            match std::str::from_utf8(data) {
+                Ok(text) => {
+                    let text = redactor.redact(text);
+                    session.record(|recorder| recorder.input(text.as_bytes()))
+                }
+                Err(_) => session.record(|recorder| recorder.input(data)),
+            }
+
+            let writer = Arc::clone(&session.writer);
+            drop(sessions);
+            let mut writer = writer.lock().unwrap();
+            writer.write_all(data)?;
+            writer.flush()?;
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Session not found"))
+        }
+    }
+
+    /// Signal the session's foreground process group, e.g. to interrupt or
+    /// suspend the running command
+    pub fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+        // Once stopped, the job is no longer in the foreground
+        let group = match signal {
+            SessionSignal::Continue => session
+                .stopped_group
+                .take()
+                .or_else(|| session.foreground_group()),
+            _ => session.foreground_group(),
+        }
+        .ok_or_else(|| anyhow::anyhow!("Session has no foreground process"))?;
+
+        signal_group(group, signal)?;
+        if signal == SessionSignal::Stop {
+            session.stopped_group = Some(group);
+        }
+        Ok(())
+    }
+
+    /// The process currently in the foreground of a session
+    pub fn foreground_process(&self, session_id: &str) -> Result<Option<ForegroundProcess>> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+        let shell_pid = session.child.process_id();
+        Ok(session
+            .foreground_group()
+            .and_then(|pid| ForegroundProcess::read(pid as u32, shell_pid)))
+    }
+
     pub fn get_output_receiver(&self) -> broadcast::Receiver<TerminalOutput> {
         self.output_sender.subscribe()
     }
 
+    pub fn get_event_receiver(&self) -> broadcast::Receiver<SessionEvent> {
+        self.event_sender.subscribe()
+    }
+
     pub fn resize_session(&self, session_id: &str, rows: u16, cols: u16) -> Result<()> {
-        let sessions = self.sessions.lock().unwrap();
+        let mut sessions = self.sessions.lock().unwrap();
         
-        if let Some(session) = sessions.get(session_id) {
+        if let Some(session) = sessions.get_mut(session_id) {
             session.pty.resize(PtySize {
                 rows,
                 cols,
                 pixel_width: 0,
                 pixel_height: 0,
             })?;
+            session.emulator.resize(rows as usize, cols as usize);
+            session.record(|recorder| recorder.resize(cols as usize, rows as usize));
         }
         
         Ok(())
     }
 
-    pub fn close_session(&self, session_id: &str) -> Result<()> {
+    /// Start recording a session to an asciicast file, or stop the running
+    /// recording. Without a path, recordings go to the data directory.
+    pub fn toggle_recording(
+        &self,
+        session_id: &str,
+        path: Option<PathBuf>,
+        title: Option<String>,
+    ) -> Result<RecordingState> {
         let mut sessions = self.sessions.lock().unwrap();
-        sessions.remove(session_id);
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+        if let Some(recorder) = session.recorder.take() {
+            return Ok(RecordingState {
+                recording: false,
+                path: recorder.finish()?,
+            });
+        }
+
+        let path = path.unwrap_or_else(|| default_recording_path(session_id));
+        let screen = session.emulator.screen();
+        let recorder = Recorder::create(&path, screen.cols(), screen.rows(), title)?;
+        session.recorder = Some(recorder);
+        Ok(RecordingState {
+            recording: true,
+            path,
+        })
+    }
+
+    /// Recover a receiver that lagged behind the output channel: drop the
+    /// chunks still queued and return a snapshot of every live session with
+    /// the stream offset it is current up to. Output is only sent while the
+    /// sessions lock is held, so the snapshots include exactly the dropped
+    /// chunks and nothing received afterwards.
+    pub fn resync(
+        &self,
+        receiver: &mut broadcast::Receiver<TerminalOutput>,
+    ) -> Vec<(String, ScreenSnapshot, u64)> {
+        let sessions = self.sessions.lock().unwrap();
+        while let Ok(_) | Err(TryRecvError::Lagged(_)) = receiver.try_recv() {}
+
+        sessions
+            .iter()
+            .map(|(id, session)| {
+                let offset = session.buffer.end_offset();
+                (id.clone(), session.emulator.snapshot(), offset)
+            })
+            .collect()
+    }
+
+    /// Every running session, in no particular order
+    pub fn list_sessions(&self) -> Vec<HostedSession> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .values()
+            .map(|session| HostedSession {
+                id: session.id.clone(),
+                spec: session.spec.clone(),
+                cwd: session.cwd.clone(),
+                pid: session.child.process_id(),
+            })
+            .collect()
+    }
+
+    /// Buffered output of a session from `from_offset` onwards
+    pub fn get_buffer(&self, session_id: &str, from_offset: u64) -> Option<BufferSlice> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(session_id)
+            .map(|session| session.buffer.read_from(from_offset))
+    }
+
+    /// Current contents of a session's screen as the emulator sees it
+    pub fn get_screen(&self, session_id: &str) -> Option<ScreenSnapshot> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(session_id).map(|session| session.emulator.snapshot())
+    }
+
+    pub fn close_session(&self, session_id: &str) -> Result<()> {
+        let session = self.sessions.lock().unwrap().remove(session_id);
+
+        if let Some(mut session) = session {
+            if let Some(recorder) = session.recorder.take() {
+                let _ = recorder.finish();
+            }
+
+            // Reap in the background so closing a tab never waits on a stubborn shell
+            thread::spawn(move || {
+                let _ = terminate_child(session.child, CLOSE_GRACE_PERIOD);
+            });
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Hang up the child, then kill it if it is still alive after `grace`
+fn terminate_child(
+    mut child: Box<dyn Child + Send + Sync>,
+    grace: Duration,
+) -> Option<ExitStatus> {
+    if let Ok(Some(status)) = child.try_wait() {
+        return Some(status);
+    }
+
+    let pid = child.process_id()? as libc::pid_t;
+    unsafe {
+        libc::kill(pid, libc::SIGHUP);
+    }
+
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if let Ok(Some(status)) = child.try_wait() {
+            return Some(status);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    unsafe {
+        libc::kill(pid, libc::SIGKILL);
+    }
+    child.wait().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_passes_through_ascii_and_control_bytes() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"$ "), "$ ");
+        assert_eq!(decoder.decode(b"50%\r75%\r\x1b[2K"), "50%\r75%\r\x1b[2K");
+    }
+
+    #[test]
+    fn test_decoder_joins_split_multibyte_characters() {
+        let mut decoder = Utf8Decoder::default();
+        let bytes = "héllo ✓".as_bytes();
+
+        // Split inside 'é' (2 bytes) and inside '✓' (3 bytes)
+        assert_eq!(decoder.decode(&bytes[..2]), "h");
+        assert_eq!(decoder.decode(&bytes[2..8]), "éllo ");
+        assert_eq!(decoder.decode(&bytes[8..9]), "");
+        assert_eq!(decoder.decode(&bytes[9..]), "✓");
+        assert!(decoder.finish().is_empty());
+    }
+
+    #[test]
+    fn test_decoder_replaces_invalid_bytes() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"a\xffb"), "a\u{FFFD}b");
+
+        // A truncated sequence at EOF is flushed lossily
+        assert_eq!(decoder.decode(b"c\xe2\x9c"), "c");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+    }
+}
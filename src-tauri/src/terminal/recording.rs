@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Longest sleep between checks for stop and speed changes during playback
+const PLAYBACK_TICK: Duration = Duration::from_millis(50);
+
+/// First line of an asciicast v2 file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AsciicastHeader {
+    pub version: u8,
+    pub width: usize,
+    pub height: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventCode {
+    #[serde(rename = "o")]
+    Output,
+    #[serde(rename = "i")]
+    Input,
+    /// Data is `"<cols>x<rows>"`
+    #[serde(rename = "r")]
+    Resize,
+    #[serde(rename = "m")]
+    Marker,
+}
+
+/// One event line: `[seconds since start, code, data]`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AsciicastEvent(pub f64, pub EventCode, pub String);
+
+/// Result of toggling a session's recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingState {
+    /// Whether the session is now being recorded
+    pub recording: bool,
+    /// File that was started or finished
+    pub path: PathBuf,
+}
+
+/// Writes a session's output, input and resizes to an asciicast v2 file
+pub struct Recorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, cols: usize, rows: usize, title: Option<String>) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording {:?}", path))?;
+
+        let mut env = BTreeMap::new();
+        env.insert("TERM".to_string(), "xterm-256color".to_string());
+        if let Ok(shell) = std::env::var("SHELL") {
+            env.insert("SHELL".to_string(), shell);
+        }
+        let header = AsciicastHeader {
+            version: 2,
+            width: cols,
+            height: rows,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+            title,
+            env,
+        };
+
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+        writer.flush()?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn output(&mut self, content: &str) -> Result<()> {
+        self.write_event(EventCode::Output, content.to_string())
+    }
+
+    pub fn input(&mut self, data: &[u8]) -> Result<()> {
+        self.write_event(EventCode::Input, String::from_utf8_lossy(data).into_owned())
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) -> Result<()> {
+        self.write_event(EventCode::Resize, format!("{}x{}", cols, rows))
+    }
+
+    fn write_event(&mut self, code: EventCode, data: String) -> Result<()> {
+        let time = self.started.elapsed().as_secs_f64();
+        let event = AsciicastEvent((time * 1e6).round() / 1e6, code, data);
+        writeln!(self.writer, "{}", serde_json::to_string(&event)?)?;
+        Ok(())
+    }
+
+    /// Flush and close the file, returning its path
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.writer.flush()?;
+        Ok(self.path)
+    }
+}
+
+/// Where a recording goes when no path is given
+pub fn default_recording_path(session_id: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let short_id: String = session_id.chars().take(8).collect();
+    let file_name = format!("zenterm-{}-{}.cast", timestamp, short_id);
+
+    match dirs::data_dir() {
+        Some(data_dir) => data_dir.join("zenterm").join("recordings").join(file_name),
+        None => PathBuf::from(file_name),
+    }
+}
+
+/// A parsed asciicast v2 file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: AsciicastHeader,
+    pub events: Vec<AsciicastEvent>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines.next().ok_or_else(|| anyhow!("Recording is empty"))??;
+        let header: AsciicastHeader = serde_json::from_str(&header_line)?;
+        if header.version != 2 {
+            return Err(anyhow!("Unsupported asciicast version {}", header.version));
+        }
+
+        let mut events = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(serde_json::from_str(&line)?);
+        }
+
+        Ok(Self { header, events })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum PlaybackEvent {
+    Output(String),
+    Resize { cols: usize, rows: usize },
+    Finished,
+}
+
+/// Shared between a running playback and whoever controls it
+#[derive(Debug)]
+pub struct PlaybackControl {
+    speed: Mutex<f64>,
+    stopped: AtomicBool,
+}
+
+impl PlaybackControl {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            speed: Mutex::new(if speed > 0.0 { speed } else { 1.0 }),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Change the speed of a running playback; 2.0 plays twice as fast
+    pub fn set_speed(&self, speed: f64) {
+        if speed > 0.0 {
+            *self.speed.lock().unwrap() = speed;
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Sleep for `delay` of recording time, tracking speed changes; returns
+    /// false if playback was stopped meanwhile
+    fn wait(&self, mut delay: Duration) -> bool {
+        while !delay.is_zero() {
+            if self.is_stopped() {
+                return false;
+            }
+            let speed = *self.speed.lock().unwrap();
+            let tick = delay.min(PLAYBACK_TICK.mul_f64(speed));
+            thread::sleep(tick.div_f64(speed));
+            delay -= tick;
+        }
+        !self.is_stopped()
+    }
+}
+
+/// Replay a recording's output and resizes with their original timing,
+/// scaled by the control's speed. Pauses longer than `max_idle` are cut
+/// short, as `asciinema play -i` does.
+pub fn play<F>(
+    recording: &Recording,
+    control: &PlaybackControl,
+    max_idle: Option<Duration>,
+    mut emit: F,
+) where
+    F: FnMut(PlaybackEvent),
+{
+    let mut previous = 0.0;
+
+    for AsciicastEvent(time, code, data) in &recording.events {
+        let mut delay = Duration::from_secs_f64((time - previous).max(0.0));
+        if let Some(max_idle) = max_idle {
+            delay = delay.min(max_idle);
+        }
+        previous = *time;
+        if !control.wait(delay) {
+            return;
+        }
+
+        match code {
+            EventCode::Output => emit(PlaybackEvent::Output(data.clone())),
+            EventCode::Resize => {
+                if let Some((cols, rows)) = data.split_once('x') {
+                    if let (Ok(cols), Ok(rows)) = (cols.parse(), rows.parse()) {
+                        emit(PlaybackEvent::Resize { cols, rows });
+                    }
+                }
+            }
+            EventCode::Input | EventCode::Marker => {}
+        }
+    }
+
+    emit(PlaybackEvent::Finished);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("zenterm-test-{}-{}.cast", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_recording_round_trip() {
+        let path = temp_path("round-trip");
+        let mut recorder = Recorder::create(&path, 80, 24, Some("demo".to_string())).unwrap();
+        recorder.output("$ ").unwrap();
+        recorder.input(b"ls\r").unwrap();
+        recorder.resize(100, 30).unwrap();
+        recorder.output("\u{1b}[1mfile\u{1b}[0m\r\n").unwrap();
+        assert_eq!(recorder.finish().unwrap(), path);
+
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(recording.header.version, 2);
+        assert_eq!((recording.header.width, recording.header.height), (80, 24));
+        assert_eq!(recording.header.title.as_deref(), Some("demo"));
+
+        let codes: Vec<EventCode> = recording.events.iter().map(|e| e.1).collect();
+        assert_eq!(
+            codes,
+            vec![EventCode::Output, EventCode::Input, EventCode::Resize, EventCode::Output]
+        );
+        assert_eq!(recording.events[2].2, "100x30");
+        assert_eq!(recording.events[3].2, "\u{1b}[1mfile\u{1b}[0m\r\n");
+        assert!(recording.events.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    #[test]
+    fn test_event_line_format() {
+        let event: AsciicastEvent = serde_json::from_str(r#"[1.5, "o", "hi"]"#).unwrap();
+        assert_eq!(event, AsciicastEvent(1.5, EventCode::Output, "hi".to_string()));
+        assert_eq!(serde_json::to_string(&event).unwrap(), r#"[1.5,"o","hi"]"#);
+    }
+
+    #[test]
+    fn test_playback_order_and_idle_limit() {
+        let recording = Recording {
+            header: AsciicastHeader {
+                version: 2,
+                width: 80,
+                height: 24,
+                timestamp: None,
+                title: None,
+                env: BTreeMap::new(),
+            },
+            events: vec![
+                AsciicastEvent(0.0, EventCode::Output, "a".to_string()),
+                AsciicastEvent(0.1, EventCode::Input, "x".to_string()),
+                AsciicastEvent(3600.0, EventCode::Resize, "120x40".to_string()),
+                AsciicastEvent(3600.1, EventCode::Output, "b".to_string()),
+            ],
+        };
+
+        let started = Instant::now();
+        let mut events = Vec::new();
+        let control = PlaybackControl::new(10.0);
+        play(&recording, &control, Some(Duration::from_millis(100)), |e| events.push(e));
+
+        // The hour-long pause is capped, and input is not replayed
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(
+            events,
+            vec![
+                PlaybackEvent::Output("a".to_string()),
+                PlaybackEvent::Resize { cols: 120, rows: 40 },
+                PlaybackEvent::Output("b".to_string()),
+                PlaybackEvent::Finished,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stopped_playback_ends_early() {
+        let recording = Recording {
+            header: AsciicastHeader {
+                version: 2,
+                width: 80,
+                height: 24,
+                timestamp: None,
+                title: None,
+                env: BTreeMap::new(),
+            },
+            events: vec![AsciicastEvent(60.0, EventCode::Output, "late".to_string())],
+        };
+
+        let control = PlaybackControl::new(1.0);
+        control.stop();
+        let mut events = Vec::new();
+        play(&recording, &control, None, |e| events.push(e));
+        assert!(events.is_empty());
+    }
+}
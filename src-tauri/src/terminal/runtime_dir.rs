@@ -0,0 +1,114 @@
+//! The per-user directory holding the server socket and the generated
+//! shell integration files. It may sit in a temp dir shared with other
+//! users, so it is created private and checked before anything in it is
+//! trusted.
+
+use anyhow::{anyhow, Result};
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// `$XDG_RUNTIME_DIR/zenterm`, or `zenterm-<uid>` under the temp dir
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("zenterm"),
+        _ => std::env::temp_dir().join(format!("zenterm-{}", current_uid())),
+    }
+}
+
+pub fn current_uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Create `dir` with mode 0700 unless it exists, then check it with
+/// `check_private_dir`
+pub fn create_private_dir(dir: &Path) -> Result<()> {
+    match DirBuilder::new().recursive(true).mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(anyhow!("Failed to create {}: {}", dir.display(), e)),
+    }
+    check_private_dir(dir)
+}
+
+/// Fail unless `dir` is a directory, not a symlink, owned by the current
+/// user and closed to everyone else, like tmux checks its socket directory
+pub fn check_private_dir(dir: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(dir)
+        .map_err(|e| anyhow!("Failed to inspect {}: {}", dir.display(), e))?;
+    if !metadata.is_dir() {
+        return Err(anyhow!("{} is not a directory", dir.display()));
+    }
+    if metadata.uid() != current_uid() {
+        return Err(anyhow!(
+            "{} is owned by uid {}, not by us",
+            dir.display(),
+            metadata.uid()
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(anyhow!(
+            "{} has mode {:o}, expected 700",
+            dir.display(),
+            metadata.mode() & 0o777
+        ));
+    }
+    Ok(())
+}
+
+/// Fail unless `path` is a socket owned by the current user, so a client
+/// never talks to a server someone else put in its place
+pub fn check_socket(path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)
+        .map_err(|e| anyhow!("Failed to inspect {}: {}", path.display(), e))?;
+    if !metadata.file_type().is_socket() {
+        return Err(anyhow!("{} is not a socket", path.display()));
+    }
+    if metadata.uid() != current_uid() {
+        return Err(anyhow!(
+            "{} is owned by uid {}, not by us",
+            path.display(),
+            metadata.uid()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_private_dir_is_checked() {
+        let base =
+            std::env::temp_dir().join(format!("zenterm_test_runtime_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+
+        let dir = base.join("zenterm");
+        create_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        // Creating it again reuses it
+        create_private_dir(&dir).unwrap();
+
+        // Others may not look into it
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(create_private_dir(&dir).is_err());
+
+        // Nor can a symlink stand in for it
+        let link = base.join("link");
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(check_private_dir(&link).is_err());
+
+        // Only a socket passes for the server's
+        let socket = dir.join("server.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        check_socket(&socket).unwrap();
+        fs::write(dir.join("file"), "").unwrap();
+        assert!(check_socket(&dir.join("file")).is_err());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,810 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use unicode_width::UnicodeWidthChar;
+
+/// Lines kept above the primary screen once they scroll off the top
+pub const DEFAULT_SCROLLBACK_LINES: usize = 10_000;
+
+const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Color {
+    #[default]
+    Default,
+    /// One of the 256 palette entries (0-15 are the ANSI colors)
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// SGR rendition of a cell
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellAttrs {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+impl CellAttrs {
+    /// SGR sequence that switches a terminal to exactly these attributes
+    pub fn to_sgr(self) -> String {
+        let mut codes = vec!["0".to_string()];
+        for (enabled, code) in [
+            (self.bold, "1"),
+            (self.dim, "2"),
+            (self.italic, "3"),
+            (self.underline, "4"),
+            (self.inverse, "7"),
+            (self.hidden, "8"),
+            (self.strikethrough, "9"),
+        ] {
+            if enabled {
+                codes.push(code.to_string());
+            }
+        }
+        codes.extend(color_sgr(self.fg, 30, 90, 38));
+        codes.extend(color_sgr(self.bg, 40, 100, 48));
+        format!("\x1b[{}m", codes.join(";"))
+    }
+}
+
+fn color_sgr(color: Color, base: u8, bright_base: u8, extended: u8) -> Option<String> {
+    match color {
+        Color::Default => None,
+        Color::Indexed(n) if n < 8 => Some((base + n).to_string()),
+        Color::Indexed(n) if n < 16 => Some((bright_base + n - 8).to_string()),
+        Color::Indexed(n) => Some(format!("{};5;{}", extended, n)),
+        Color::Rgb(r, g, b) => Some(format!("{};2;{};{};{}", extended, r, g, b)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Cell {
+    pub c: char,
+    pub attrs: CellAttrs,
+    /// Left half of a double-width character
+    pub wide: bool,
+    /// Right half of a double-width character; holds no text of its own
+    pub spacer: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            attrs: CellAttrs::default(),
+            wide: false,
+            spacer: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// The line continues on the next row because of auto-wrap
+    pub wrapped: bool,
+}
+
+impl Row {
+    fn new(cols: usize, blank: Cell) -> Self {
+        Self {
+            cells: vec![blank; cols],
+            wrapped: false,
+        }
+    }
+
+    /// Text of the row without trailing blanks
+    pub fn text(&self) -> String {
+        let text: String = self
+            .cells
+            .iter()
+            .filter(|cell| !cell.spacer)
+            .map(|cell| cell.c)
+            .collect();
+        text.trim_end().to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    /// Pen used for newly printed characters
+    pub attrs: CellAttrs,
+    /// A character was printed in the last column; the next one wraps
+    pending_wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modes {
+    pub auto_wrap: bool,
+    pub cursor_visible: bool,
+    pub origin: bool,
+    pub insert: bool,
+    pub application_cursor: bool,
+    pub bracketed_paste: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            auto_wrap: true,
+            cursor_visible: true,
+            origin: false,
+            insert: false,
+            application_cursor: false,
+            bracketed_paste: false,
+        }
+    }
+}
+
+/// A run of cells sharing the same attributes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextRun {
+    pub text: String,
+    pub attrs: CellAttrs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StyledLine {
+    pub runs: Vec<TextRun>,
+    pub wrapped: bool,
+}
+
+/// Serializable view of the visible screen for frontends and snapshot tests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScreenSnapshot {
+    pub rows: usize,
+    pub cols: usize,
+    pub cursor_row: usize,
+    pub cursor_col: usize,
+    pub cursor_visible: bool,
+    pub alternate_screen: bool,
+    pub title: String,
+    pub lines: Vec<StyledLine>,
+}
+
+impl ScreenSnapshot {
+    /// Escape sequences that redraw this snapshot on a terminal of the same
+    /// size, e.g. to resynchronise a frontend that missed output
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        if self.alternate_screen {
+            out.push_str("\x1b[?1049h");
+        }
+        out.push_str("\x1b[H\x1b[2J");
+
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                out.push_str("\r\n");
+            }
+            for run in &line.runs {
+                out.push_str(&run.attrs.to_sgr());
+                out.push_str(&run.text);
+            }
+        }
+
+        out.push_str("\x1b[0m");
+        out.push_str(&format!("\x1b[{};{}H", self.cursor_row + 1, self.cursor_col + 1));
+        if !self.cursor_visible {
+            out.push_str("\x1b[?25l");
+        }
+        out
+    }
+}
+
+/// Terminal screen state: primary and alternate grids, cursor, scroll
+/// region and scrollback. Escape sequence parsing lives in `emulator.rs`;
+/// this type only implements the operations those sequences map to.
+#[derive(Debug, Clone)]
+pub struct Screen {
+    rows: usize,
+    cols: usize,
+    primary: Vec<Row>,
+    alternate: Vec<Row>,
+    alternate_active: bool,
+    scrollback: VecDeque<Row>,
+    scrollback_limit: usize,
+    cursor: Cursor,
+    saved_cursor: Option<Cursor>,
+    /// Cursor saved when entering the alternate screen with mode 1049
+    saved_primary_cursor: Option<Cursor>,
+    scroll_top: usize,
+    scroll_bottom: usize,
+    pub modes: Modes,
+    pub title: String,
+}
+
+impl Screen {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self::with_scrollback(rows, cols, DEFAULT_SCROLLBACK_LINES)
+    }
+
+    pub fn with_scrollback(rows: usize, cols: usize, scrollback_limit: usize) -> Self {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        Self {
+            rows,
+            cols,
+            primary: vec![Row::new(cols, Cell::default()); rows],
+            alternate: vec![Row::new(cols, Cell::default()); rows],
+            alternate_active: false,
+            scrollback: VecDeque::new(),
+            scrollback_limit,
+            cursor: Cursor::default(),
+            saved_cursor: None,
+            saved_primary_cursor: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            modes: Modes::default(),
+            title: String::new(),
+        }
+    }
+
+    // ---- Read access ----
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.alternate_active
+    }
+
+    /// Visible rows of the active screen
+    pub fn visible_rows(&self) -> &[Row] {
+        if self.alternate_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Option<&Cell> {
+        self.visible_rows().get(row)?.cells.get(col)
+    }
+
+    pub fn line_text(&self, row: usize) -> Option<String> {
+        self.visible_rows().get(row).map(Row::text)
+    }
+
+    /// Visible text, one line per row, trailing blank lines removed
+    pub fn text(&self) -> String {
+        join_lines(self.visible_rows())
+    }
+
+    /// Lines that scrolled off the primary screen, oldest first
+    pub fn scrollback(&self) -> impl Iterator<Item = &Row> {
+        self.scrollback.iter()
+    }
+
+    pub fn scrollback_text(&self) -> String {
+        join_lines(self.scrollback.iter())
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let lines = self
+            .visible_rows()
+            .iter()
+            .map(|row| {
+                let mut runs: Vec<TextRun> = Vec::new();
+                for cell in row.cells.iter().filter(|cell| !cell.spacer) {
+                    match runs.last_mut() {
+                        Some(run) if run.attrs == cell.attrs => run.text.push(cell.c),
+                        _ => runs.push(TextRun {
+                            text: cell.c.to_string(),
+                            attrs: cell.attrs,
+                        }),
+                    }
+                }
+                StyledLine {
+                    runs,
+                    wrapped: row.wrapped,
+                }
+            })
+            .collect();
+
+        ScreenSnapshot {
+            rows: self.rows,
+            cols: self.cols,
+            cursor_row: self.cursor.row,
+            cursor_col: self.cursor.col,
+            cursor_visible: self.modes.cursor_visible,
+            alternate_screen: self.alternate_active,
+            title: self.title.clone(),
+            lines,
+        }
+    }
+
+    // ---- Printing and cursor movement ----
+
+    pub fn print(&mut self, c: char) {
+        let width = match c.width() {
+            Some(width) if width > 0 => width.min(2),
+            // Combining marks and other zero-width characters are dropped
+            _ => return,
+        };
+        if width == 2 && self.cols < 2 {
+            // A wide character fits no row of a one-column screen
+            return;
+        }
+
+        if self.cursor.pending_wrap || (width == 2 && self.cursor.col + 1 >= self.cols) {
+            if self.modes.auto_wrap {
+                self.wrap_line();
+            } else if width == 2 {
+                // No room for a wide character at the right margin
+                return;
+            }
+        }
+
+        if self.modes.insert {
+            self.insert_chars(width);
+        }
+
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let attrs = self.cursor.attrs;
+        self.clear_wide_pair(row, col);
+        if width == 2 {
+            self.clear_wide_pair(row, col + 1);
+        }
+
+        let cells = &mut self.grid_mut()[row].cells;
+        cells[col] = Cell {
+            c,
+            attrs,
+            wide: width == 2,
+            spacer: false,
+        };
+        if width == 2 {
+            cells[col + 1] = Cell {
+                c: ' ',
+                attrs,
+                wide: false,
+                spacer: true,
+            };
+        }
+
+        if col + width >= self.cols {
+            self.cursor.col = self.cols - 1;
+            self.cursor.pending_wrap = self.modes.auto_wrap;
+        } else {
+            self.cursor.col = col + width;
+        }
+    }
+
+    fn wrap_line(&mut self) {
+        let row = self.cursor.row;
+        self.grid_mut()[row].wrapped = true;
+        self.cursor.col = 0;
+        self.linefeed();
+    }
+
+    /// Overwriting either half of a wide character blanks the other half
+    fn clear_wide_pair(&mut self, row: usize, col: usize) {
+        let cols = self.cols;
+        let cells = &mut self.grid_mut()[row].cells;
+        if col >= cols {
+            return;
+        }
+        if cells[col].spacer && col > 0 {
+            cells[col - 1].c = ' ';
+            cells[col - 1].wide = false;
+        }
+        if cells[col].wide && col + 1 < cols {
+            cells[col + 1].spacer = false;
+        }
+    }
+
+    pub fn linefeed(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row + 1 < self.rows {
+            self.cursor.row += 1;
+        }
+    }
+
+    pub fn reverse_index(&mut self) {
+        self.cursor.pending_wrap = false;
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+    }
+
+    pub fn carriage_return(&mut self) {
+        self.cursor.col = 0;
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn backspace(&mut self) {
+        self.cursor.col = self.cursor.col.saturating_sub(1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn tab(&mut self, count: usize) {
+        for _ in 0..count {
+            let next = (self.cursor.col / TAB_WIDTH + 1) * TAB_WIDTH;
+            self.cursor.col = next.min(self.cols - 1);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn back_tab(&mut self, count: usize) {
+        for _ in 0..count {
+            self.cursor.col = self.cursor.col.saturating_sub(1) / TAB_WIDTH * TAB_WIDTH;
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    /// Move to an absolute position (0-based), honouring origin mode
+    pub fn goto(&mut self, row: usize, col: usize) {
+        let (top, bottom) = if self.modes.origin {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows - 1)
+        };
+        self.cursor.row = (top + row).min(bottom);
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn goto_col(&mut self, col: usize) {
+        self.cursor.col = col.min(self.cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn goto_row(&mut self, row: usize) {
+        let col = self.cursor.col;
+        self.goto(row, col);
+    }
+
+    /// Relative vertical move that stops at the scroll region margins
+    pub fn move_up(&mut self, count: usize) {
+        let top = if self.cursor.row >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        };
+        self.cursor.row = self.cursor.row.saturating_sub(count).max(top);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn move_down(&mut self, count: usize) {
+        let bottom = if self.cursor.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.rows - 1
+        };
+        self.cursor.row = (self.cursor.row + count).min(bottom);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn move_forward(&mut self, count: usize) {
+        self.cursor.col = (self.cursor.col + count).min(self.cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn move_backward(&mut self, count: usize) {
+        self.cursor.col = self.cursor.col.saturating_sub(count);
+        self.cursor.pending_wrap = false;
+    }
+
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = Some(self.cursor);
+    }
+
+    pub fn restore_cursor(&mut self) {
+        if let Some(saved) = self.saved_cursor {
+            self.cursor = saved;
+            self.cursor.row = self.cursor.row.min(self.rows - 1);
+            self.cursor.col = self.cursor.col.min(self.cols - 1);
+        }
+    }
+
+    // ---- Scrolling ----
+
+    /// Set the scroll region (0-based, inclusive); invalid regions reset it
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows - 1);
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+        } else {
+            self.scroll_top = 0;
+            self.scroll_bottom = self.rows - 1;
+        }
+        self.goto(0, 0);
+    }
+
+    pub fn scroll_up(&mut self, count: usize) {
+        // Only scrolls off the top of the primary screen become history
+        let keep_history = self.scroll_top == 0 && !self.alternate_active;
+        self.scroll_region_up(self.scroll_top, self.scroll_bottom, count, keep_history);
+    }
+
+    fn scroll_region_up(&mut self, top: usize, bottom: usize, count: usize, keep_history: bool) {
+        let count = count.min(bottom - top + 1);
+        let blank = self.blank_row();
+
+        for _ in 0..count {
+            let row = self.grid_mut().remove(top);
+            self.grid_mut().insert(bottom, blank.clone());
+            if keep_history {
+                self.push_scrollback(row);
+            }
+        }
+    }
+
+    /// Keep a row that left the top of the screen. Its trailing blank cells
+    /// are dropped, since most lines are much shorter than the screen.
+    fn push_scrollback(&mut self, mut row: Row) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        let end = row
+            .cells
+            .iter()
+            .rposition(|cell| *cell != Cell::default())
+            .map_or(0, |i| i + 1);
+        row.cells.truncate(end);
+        row.cells.shrink_to_fit();
+
+        if self.scrollback.len() == self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(row);
+    }
+
+    pub fn scroll_down(&mut self, count: usize) {
+        self.scroll_region_down(self.scroll_top, self.scroll_bottom, count);
+    }
+
+    fn scroll_region_down(&mut self, top: usize, bottom: usize, count: usize) {
+        let count = count.min(bottom - top + 1);
+        let blank = self.blank_row();
+
+        for _ in 0..count {
+            self.grid_mut().remove(bottom);
+            self.grid_mut().insert(top, blank.clone());
+        }
+    }
+
+    pub fn insert_lines(&mut self, count: usize) {
+        if !self.cursor_in_scroll_region() {
+            return;
+        }
+        self.scroll_region_down(self.cursor.row, self.scroll_bottom, count);
+        self.carriage_return();
+    }
+
+    pub fn delete_lines(&mut self, count: usize) {
+        if !self.cursor_in_scroll_region() {
+            return;
+        }
+        // Deleted lines never go to scrollback
+        self.scroll_region_up(self.cursor.row, self.scroll_bottom, count, false);
+        self.carriage_return();
+    }
+
+    fn cursor_in_scroll_region(&self) -> bool {
+        (self.scroll_top..=self.scroll_bottom).contains(&self.cursor.row)
+    }
+
+    // ---- Erasing and editing ----
+
+    /// ED: 0 = cursor to end, 1 = start to cursor, 2 = screen, 3 = scrollback
+    pub fn erase_in_display(&mut self, mode: u16) {
+        let row = self.cursor.row;
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for r in row + 1..self.rows {
+                    self.clear_row(r);
+                }
+            }
+            1 => {
+                self.erase_in_line(1);
+                for r in 0..row {
+                    self.clear_row(r);
+                }
+            }
+            2 => {
+                for r in 0..self.rows {
+                    self.clear_row(r);
+                }
+            }
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+    }
+
+    /// EL: 0 = cursor to end, 1 = start to cursor, 2 = whole line
+    pub fn erase_in_line(&mut self, mode: u16) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let range = match mode {
+            0 => col..self.cols,
+            1 => 0..col + 1,
+            2 => 0..self.cols,
+            _ => return,
+        };
+        let blank = self.blank_cell();
+        let line = &mut self.grid_mut()[row];
+        for cell in &mut line.cells[range] {
+            *cell = blank;
+        }
+        if mode != 1 {
+            line.wrapped = false;
+        }
+    }
+
+    pub fn erase_chars(&mut self, count: usize) {
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let end = (col + count).min(self.cols);
+        let blank = self.blank_cell();
+        for cell in &mut self.grid_mut()[row].cells[col..end] {
+            *cell = blank;
+        }
+    }
+
+    pub fn insert_chars(&mut self, count: usize) {
+        let (row, col, cols) = (self.cursor.row, self.cursor.col, self.cols);
+        let count = count.min(cols - col);
+        let blank = self.blank_cell();
+        let cells = &mut self.grid_mut()[row].cells;
+        cells.truncate(cols - count);
+        for _ in 0..count {
+            cells.insert(col, blank);
+        }
+    }
+
+    pub fn delete_chars(&mut self, count: usize) {
+        let (row, col, cols) = (self.cursor.row, self.cursor.col, self.cols);
+        let count = count.min(cols - col);
+        let blank = self.blank_cell();
+        let cells = &mut self.grid_mut()[row].cells;
+        cells.drain(col..col + count);
+        cells.resize(cols, blank);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let blank = self.blank_row();
+        self.grid_mut()[row] = blank;
+    }
+
+    /// Erased cells keep the current background color (BCE)
+    fn blank_cell(&self) -> Cell {
+        Cell {
+            attrs: CellAttrs {
+                bg: self.cursor.attrs.bg,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn blank_row(&self) -> Row {
+        Row::new(self.cols, self.blank_cell())
+    }
+
+    // ---- Modes and screens ----
+
+    /// Switch to the alternate screen, optionally saving the cursor (1049)
+    pub fn enter_alternate_screen(&mut self, save_cursor: bool) {
+        if self.alternate_active {
+            return;
+        }
+        if save_cursor {
+            self.saved_primary_cursor = Some(self.cursor);
+        }
+        self.alternate_active = true;
+        for r in 0..self.rows {
+            self.clear_row(r);
+        }
+    }
+
+    pub fn leave_alternate_screen(&mut self, restore_cursor: bool) {
+        if !self.alternate_active {
+            return;
+        }
+        self.alternate_active = false;
+        if restore_cursor {
+            if let Some(saved) = self.saved_primary_cursor.take() {
+                self.cursor = saved;
+                self.cursor.row = self.cursor.row.min(self.rows - 1);
+                self.cursor.col = self.cursor.col.min(self.cols - 1);
+            }
+        }
+    }
+
+    /// RIS: back to the power-on state, keeping the size and scrollback
+    pub fn reset(&mut self) {
+        let scrollback = std::mem::take(&mut self.scrollback);
+        *self = Self::with_scrollback(self.rows, self.cols, self.scrollback_limit);
+        self.scrollback = scrollback;
+    }
+
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let rows = rows.max(1);
+        let cols = cols.max(1);
+        if rows == self.rows && cols == self.cols {
+            return;
+        }
+
+        for row in self.primary.iter_mut().chain(self.alternate.iter_mut()) {
+            row.cells.resize(cols, Cell::default());
+            if let Some(last) = row.cells.last_mut() {
+                if last.wide {
+                    *last = Cell::default();
+                }
+            }
+        }
+
+        if rows < self.rows {
+            // Drop blank rows below the cursor first, then push rows off the top
+            let excess = self.rows - rows;
+            let below_cursor = self.rows - 1 - self.cursor.row;
+            let from_bottom = excess.min(below_cursor);
+            let from_top = excess - from_bottom;
+
+            for grid in [&mut self.primary, &mut self.alternate] {
+                grid.truncate(grid.len() - from_bottom);
+            }
+            let pushed_off: Vec<Row> = self.primary.drain(..from_top).collect();
+            for row in pushed_off {
+                self.push_scrollback(row);
+            }
+            self.alternate.drain(..from_top);
+            self.cursor.row -= from_top;
+        } else {
+            for grid in [&mut self.primary, &mut self.alternate] {
+                grid.resize(rows, Row::new(cols, Cell::default()));
+            }
+        }
+
+        self.rows = rows;
+        self.cols = cols;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn grid_mut(&mut self) -> &mut Vec<Row> {
+        if self.alternate_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    pub(crate) fn pen_mut(&mut self) -> &mut CellAttrs {
+        &mut self.cursor.attrs
+    }
+}
+
+fn join_lines<'a>(rows: impl IntoIterator<Item = &'a Row>) -> String {
+    let lines: Vec<String> = rows.into_iter().map(Row::text).collect();
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(0, |i| i + 1);
+    lines[..end].join("\n")
+}
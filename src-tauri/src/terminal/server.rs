@@ -0,0 +1,365 @@
+use crate::terminal::protocol::{ClientMessage, Request, Response, ServerMessage, SessionScreen};
+use crate::terminal::runtime_dir::create_private_dir;
+use crate::terminal::{PtyManager, TerminalResync, TriggerSet, PROTOCOL_VERSION};
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Own the PTYs of every session and serve clients on `path` until the
+/// process is killed. Sessions outlive the clients that started them.
+pub fn run(path: &Path) -> Result<()> {
+    let listener = bind(path)?;
+    let pty_manager = Arc::new(PtyManager::new());
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let pty_manager = Arc::clone(&pty_manager);
+                thread::spawn(move || {
+                    if let Err(e) = serve(pty_manager, stream) {
+                        eprintln!("zenterm-server: client error: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("zenterm-server: failed to accept client: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Listen on `path`, replacing a stale socket but not a live server. The
+/// socket's directory must be private to the user, as it is created.
+fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(parent) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        create_private_dir(parent)?;
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow!("A server is already listening on {:?}", path));
+        }
+        fs::remove_file(path)?;
+    }
+
+    // Anyone who can connect can type into the sessions, so the socket is
+    // created with mode 0600 rather than changed to it afterwards
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    Ok(listener?)
+}
+
+/// One client connection. The client's requests are answered in order;
+/// output and events of the sessions it attached are pushed alongside.
+struct Connection {
+    pty_manager: Arc<PtyManager>,
+    writer: Mutex<UnixStream>,
+    attached: Mutex<HashSet<String>>,
+    open: AtomicBool,
+}
+
+impl Connection {
+    fn send(&self, message: &ServerMessage) -> Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(&line)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn is_attached(&self, session_id: &str) -> bool {
+        self.attached.lock().unwrap().contains(session_id)
+    }
+
+    fn handle(&self, request: Request) -> Result<Response> {
+        let pty = &self.pty_manager;
+        Ok(match request {
+            Request::Hello => Response::Hello {
+                version: PROTOCOL_VERSION,
+            },
+            Request::Create { spec, history } => {
+                let session_id = pty.create_session_with_history(&spec, &history)?;
+                self.attached.lock().unwrap().insert(session_id.clone());
+                Response::Created { session_id }
+            }
+            Request::List => Response::Sessions {
+                sessions: pty.list_sessions(),
+            },
+            Request::Attach { session_id } => {
+                if pty.get_screen(&session_id).is_none() {
+                    return Err(anyhow!("Session not found"));
+                }
+                self.attached.lock().unwrap().insert(session_id);
+                Response::Ok
+            }
+            Request::Detach { session_id } => {
+                self.attached.lock().unwrap().remove(&session_id);
+                Response::Ok
+            }
+            Request::Input { session_id, data } => {
+                pty.write_input(&session_id, &data)?;
+                Response::Ok
+            }
+            Request::Command {
+                session_id,
+                command,
+            } => Response::CommandStarted {
+                command_id: pty.write_command(&session_id, &command)?,
+            },
+            Request::Resize {
+                session_id,
+                rows,
+                cols,
+            } => {
+                pty.resize_session(&session_id, rows, cols)?;
+                Response::Ok
+            }
+            Request::Signal { session_id, signal } => {
+                pty.send_signal(&session_id, signal)?;
+                Response::Ok
+            }
+            Request::Foreground { session_id } => Response::Foreground {
+                process: pty.foreground_process(&session_id)?,
+            },
+            Request::ToggleRecording {
+                session_id,
+                path,
+                title,
+            } => Response::Recording {
+                state: pty.toggle_recording(&session_id, path, title)?,
+            },
+            Request::Buffer {
+                session_id,
+                from_offset,
+            } => Response::Buffer {
+                slice: pty.get_buffer(&session_id, from_offset),
+            },
+            Request::Screen { session_id } => Response::Screen {
+                screen: pty.get_screen(&session_id),
+            },
+            Request::Resync => {
+                let attached = self.attached.lock().unwrap().clone();
+                let screens = pty
+                    .resync(&mut pty.get_output_receiver())
+                    .into_iter()
+                    .filter(|(session_id, _, _)| attached.contains(session_id))
+                    .map(|(session_id, screen, offset)| SessionScreen {
+                        session_id,
+                        screen,
+                        offset,
+                    })
+                    .collect();
+                Response::Screens { screens }
+            }
+            Request::Close { session_id } => {
+                pty.close_session(&session_id)?;
+                self.attached.lock().unwrap().remove(&session_id);
+                Response::Ok
+            }
+            Request::SetTriggers { triggers, commands } => {
+                pty.set_triggers(TriggerSet::compile(&triggers, &commands)?);
+                Response::Ok
+            }
+            Request::SetRedaction { config } => {
+                pty.set_redaction(&config)?;
+                Response::Ok
+            }
+        })
+    }
+}
+
+fn serve(pty_manager: Arc<PtyManager>, stream: UnixStream) -> Result<()> {
+    let connection = Arc::new(Connection {
+        pty_manager,
+        writer: Mutex::new(stream.try_clone()?),
+        attached: Mutex::new(HashSet::new()),
+        open: AtomicBool::new(true),
+    });
+    spawn_output_pump(Arc::clone(&connection));
+    spawn_event_pump(Arc::clone(&connection));
+
+    let result = read_requests(&connection, stream);
+    connection.open.store(false, Ordering::Relaxed);
+    let _ = connection.writer.lock().unwrap().shutdown(std::net::Shutdown::Both);
+    result
+}
+
+fn read_requests(connection: &Connection, stream: UnixStream) -> Result<()> {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match serde_json::from_str::<ClientMessage>(&line) {
+            Ok(message) => ServerMessage::Reply {
+                id: message.id,
+                result: connection
+                    .handle(message.request)
+                    .unwrap_or_else(|e| Response::Error {
+                        message: e.to_string(),
+                    }),
+            },
+            Err(e) => ServerMessage::Reply {
+                id: 0,
+                result: Response::Error {
+                    message: format!("Malformed request: {}", e),
+                },
+            },
+        };
+        connection.send(&reply)?;
+    }
+    Ok(())
+}
+
+/// Push the output of attached sessions, redrawing their screens if the
+/// client reads too slowly to keep up
+fn spawn_output_pump(connection: Arc<Connection>) {
+    let mut receiver = connection.pty_manager.get_output_receiver();
+
+    thread::spawn(move || {
+        while connection.open.load(Ordering::Relaxed) {
+            let sent = match receiver.blocking_recv() {
+                Ok(output) if connection.is_attached(&output.session_id) => {
+                    connection.send(&ServerMessage::Output(output))
+                }
+                Ok(_) => Ok(()),
+                Err(RecvError::Lagged(skipped)) => connection
+                    .pty_manager
+                    .resync(&mut receiver)
+                    .into_iter()
+                    .filter(|(session_id, _, _)| connection.is_attached(session_id))
+                    .try_for_each(|(session_id, screen, offset)| {
+                        connection.send(&ServerMessage::Resync(TerminalResync {
+                            session_id,
+                            skipped,
+                            content: screen.to_ansi(),
+                            offset,
+                        }))
+                    }),
+                Err(RecvError::Closed) => break,
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_event_pump(connection: Arc<Connection>) {
+    let mut receiver = connection.pty_manager.get_event_receiver();
+
+    thread::spawn(move || {
+        while connection.open.load(Ordering::Relaxed) {
+            match receiver.blocking_recv() {
+                Ok(event) => {
+                    if !connection.is_attached(event.session_id()) {
+                        continue;
+                    }
+                    if connection.send(&ServerMessage::Event(event)).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::{PtyHost, ServerClient, SessionSpec};
+    use std::time::Duration;
+
+    #[test]
+    fn test_client_talks_to_server() {
+        let dir = std::env::temp_dir().join(format!("zenterm-server-test-{}", std::process::id()));
+        let path = dir.join("server.sock");
+        let server_path = path.clone();
+        thread::spawn(move || run(&server_path));
+
+        let client = (0..50)
+            .find_map(|_| {
+                thread::sleep(Duration::from_millis(20));
+                ServerClient::connect(&path).ok()
+            })
+            .expect("server did not start");
+
+        // Only one server per socket
+        assert!(run(&path).is_err());
+
+        assert!(client.list_sessions().unwrap().is_empty());
+        let err = client.attach("missing").unwrap_err();
+        assert_eq!(err.to_string(), "Session not found");
+        assert!(client.get_screen("missing").is_none());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_session_outlives_its_client() {
+        let dir =
+            std::env::temp_dir().join(format!("zenterm-server-reattach-{}", std::process::id()));
+        let path = dir.join("server.sock");
+        let server_path = path.clone();
+        thread::spawn(move || run(&server_path));
+
+        let client = (0..50)
+            .find_map(|_| {
+                thread::sleep(Duration::from_millis(20));
+                ServerClient::connect(&path).ok()
+            })
+            .expect("server did not start");
+        let mut output = client.get_output_receiver();
+        let spec = SessionSpec {
+            shell: Some("/bin/sh".to_string()),
+            args: vec!["-c".to_string(), "echo zenterm-alive; exec cat".to_string()],
+            ..Default::default()
+        };
+        let session_id = client.create_session_with_history(&spec, "").unwrap();
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let mut received = String::new();
+        while !received.contains("zenterm-alive") {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "no output from the session"
+            );
+            match output.try_recv() {
+                Ok(chunk) => received.push_str(&chunk.content),
+                Err(_) => thread::sleep(Duration::from_millis(20)),
+            }
+        }
+        drop(client);
+
+        // A new client finds the session still running, screen and all
+        let client = ServerClient::connect(&path).unwrap();
+        let sessions = client.list_sessions().unwrap();
+        assert!(sessions.iter().any(|session| session.id == session_id));
+        client.attach(&session_id).unwrap();
+        let screen = client.get_screen(&session_id).unwrap();
+        let text: String = screen
+            .lines
+            .iter()
+            .flat_map(|line| &line.runs)
+            .map(|run| run.text.as_str())
+            .collect();
+        assert!(text.contains("zenterm-alive"));
+
+        client.close_session(&session_id).unwrap();
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -1,9 +1,239 @@
-use crate::terminal::{CommandBlock, PtyManager, TerminalOutput};
+use crate::terminal::{
+    BufferSlice, CommandBlock, ForegroundProcess, HostedSession, OutputForwarder, PtyHost,
+    PtyManager, ScreenSnapshot,
+    RecordingState, SavedSession, SessionEvent, SessionSignal, SessionSnapshot, SessionSpec,
+    TerminalOutput, TriggerSet, SNAPSHOT_VERSION,
+};
+use crate::terminal::broadcast::{is_multiline, BroadcastGroup, BroadcastGroups};
+use crate::terminal::snapshot::render_history;
 use anyhow::Result;
+use history::{HistoryDb, HistoryEntry, HistoryQuery};
+use notifications::{CommandNotifier, FinishedCommand};
+use redaction::RedactionConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+/// How long the snapshot writer waits for further changes before saving
+const SNAPSHOT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Smallest share of a split either side can be resized to
+const MIN_SPLIT_RATIO: f32 = 0.1;
+
+/// Command blocks kept per session; older ones are dropped, they remain in
+/// the history database
+const MAX_COMMAND_BLOCKS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitDirection {
+    /// Panes side by side, like `tmux split-window -h`
+    Horizontal,
+    /// Panes stacked top to bottom
+    Vertical,
+}
+
+/// A node of a tab's layout: a single pane, or a split into two parts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PaneNode {
+    Pane {
+        session_id: String,
+    },
+    Split {
+        direction: SplitDirection,
+        /// Share of the space given to `first`, between 0 and 1
+        ratio: f32,
+        first: Box<PaneNode>,
+        second: Box<PaneNode>,
+    },
+}
+
+impl PaneNode {
+    fn pane(session_id: &str) -> Self {
+        PaneNode::Pane {
+            session_id: session_id.to_string(),
+        }
+    }
+
+    /// Session ids of all panes, left to right and top to bottom
+    pub fn session_ids(&self) -> Vec<String> {
+        match self {
+            PaneNode::Pane { session_id } => vec![session_id.clone()],
+            PaneNode::Split { first, second, .. } => {
+                let mut ids = first.session_ids();
+                ids.extend(second.session_ids());
+                ids
+            }
+        }
+    }
+
+    pub fn contains(&self, session_id: &str) -> bool {
+        match self {
+            PaneNode::Pane { session_id: id } => id == session_id,
+            PaneNode::Split { first, second, .. } => {
+                first.contains(session_id) || second.contains(session_id)
+            }
+        }
+    }
+
+    /// Split the pane of `target`, putting `new_session_id` after it
+    fn split(&mut self, target: &str, new_session_id: &str, direction: SplitDirection) -> bool {
+        match self {
+            PaneNode::Pane { session_id } if session_id == target => {
+                let existing = std::mem::replace(self, PaneNode::pane(new_session_id));
+                *self = PaneNode::Split {
+                    direction,
+                    ratio: 0.5,
+                    first: Box::new(existing),
+                    second: Box::new(PaneNode::pane(new_session_id)),
+                };
+                true
+            }
+            PaneNode::Pane { .. } => false,
+            PaneNode::Split { first, second, .. } => {
+                first.split(target, new_session_id, direction)
+                    || second.split(target, new_session_id, direction)
+            }
+        }
+    }
+
+    /// Session of the last pane, the one at the right or bottom edge
+    fn last_session_id(&self) -> &str {
+        match self {
+            PaneNode::Pane { session_id } => session_id,
+            PaneNode::Split { second, .. } => second.last_session_id(),
+        }
+    }
+
+    /// Move the divider right after the pane of `target`, giving the side
+    /// before it `ratio` of its split. Every divider follows exactly one
+    /// pane, so this reaches each split, however deeply nested.
+    fn set_ratio(&mut self, target: &str, ratio: f32) -> bool {
+        let PaneNode::Split {
+            ratio: split_ratio,
+            first,
+            second,
+            ..
+        } = self
+        else {
+            return false;
+        };
+
+        if first.last_session_id() == target {
+            *split_ratio = ratio.clamp(MIN_SPLIT_RATIO, 1.0 - MIN_SPLIT_RATIO);
+            true
+        } else {
+            first.set_ratio(target, ratio) || second.set_ratio(target, ratio)
+        }
+    }
+
+    /// Exchange the positions of two panes
+    fn swap(&mut self, a: &str, b: &str) {
+        match self {
+            PaneNode::Pane { session_id } => {
+                if session_id == a {
+                    *session_id = b.to_string();
+                } else if session_id == b {
+                    *session_id = a.to_string();
+                }
+            }
+            PaneNode::Split { first, second, .. } => {
+                first.swap(a, b);
+                second.swap(a, b);
+            }
+        }
+    }
+
+    /// Replace every session id through `rename`, dropping panes it returns
+    /// `None` for; a split that loses one side collapses into the other
+    pub fn map_sessions<F>(self, rename: &F) -> Option<PaneNode>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        match self {
+            PaneNode::Pane { session_id } => rename(&session_id).map(|id| PaneNode::pane(&id)),
+            PaneNode::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => match (first.map_sessions(rename), second.map_sessions(rename)) {
+                (Some(first), Some(second)) => Some(PaneNode::Split {
+                    direction,
+                    ratio,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (Some(remaining), None) | (None, Some(remaining)) => Some(remaining),
+                (None, None) => None,
+            },
+        }
+    }
+}
+
+/// The panes of one tab
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaneLayout {
+    pub id: String,
+    pub root: PaneNode,
+    /// Session of the pane that has focus within the tab
+    pub focused: String,
+}
+
+impl PaneLayout {
+    pub fn new(session_id: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            root: PaneNode::pane(session_id),
+            focused: session_id.to_string(),
+        }
+    }
+
+    pub fn split(&mut self, target: &str, new_session_id: &str, direction: SplitDirection) -> bool {
+        let split = self.root.split(target, new_session_id, direction);
+        if split {
+            self.focused = new_session_id.to_string();
+        }
+        split
+    }
+
+    /// Remove a pane, moving focus to the first remaining one if it had it;
+    /// returns false once the tab has no panes left
+    pub fn remove(&mut self, target: &str) -> bool {
+        // The sibling of the removed pane takes over its parent split
+        let root = std::mem::replace(&mut self.root, PaneNode::pane(""));
+        match root.map_sessions(&|id| (id != target).then(|| id.to_string())) {
+            Some(root) => {
+                self.root = root;
+                if self.focused == target {
+                    self.focused = self.root.session_ids().remove(0);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_ratio(&mut self, target: &str, ratio: f32) -> bool {
+        self.root.set_ratio(target, ratio)
+    }
+
+    pub fn swap(&mut self, a: &str, b: &str) -> bool {
+        if !self.root.contains(a) || !self.root.contains(b) {
+            return false;
+        }
+        self.root.swap(a, b);
+        true
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TerminalSession {
@@ -12,51 +242,526 @@ pub struct TerminalSession {
     pub active: bool,
     pub current_directory: String,
     pub commands: Vec<CommandBlock>,
+    /// Commands from before the last restart, shown read-only in scrollback
+    #[serde(default)]
+    pub restored_commands: Vec<CommandBlock>,
+    /// How the session was launched, reused for "new tab here" and restore
+    pub spec: SessionSpec,
+    /// Set once the shell has exited on its own
+    pub exit_code: Option<u32>,
 }
 
 pub struct SessionManager {
     sessions: Arc<Mutex<HashMap<String, TerminalSession>>>,
-    pty_manager: Arc<PtyManager>,
+    /// Owner of the PTYs: this process, or a `zenterm-server` daemon
+    pty_manager: Arc<dyn PtyHost>,
     active_session: Arc<Mutex<Option<String>>>,
+    /// One layout per tab, in tab order
+    layouts: Arc<Mutex<Vec<PaneLayout>>>,
+    /// Wakes the snapshot writer once `persist_to` has started it
+    snapshot_notifier: Arc<Mutex<Option<mpsc::Sender<()>>>>,
+    /// Where finished commands are recorded, once `record_history_to` is called
+    history: Arc<Mutex<Option<HistoryDb>>>,
+    /// Announces long commands finishing in background tabs, once
+    /// `notify_long_commands` is called
+    command_notifier: Arc<Mutex<Option<CommandNotifier>>>,
+    /// Sessions typing into each other, not kept across restarts
+    broadcast: Arc<Mutex<BroadcastGroups>>,
 }
 
 impl SessionManager {
+    /// Manage sessions whose PTYs live in this process
     pub fn new() -> Self {
+        Self::with_host(Arc::new(PtyManager::new()))
+    }
+
+    /// Manage sessions whose PTYs live in `pty_manager`, e.g. a
+    /// `ServerClient` connected to `zenterm-server`
+    pub fn with_host(pty_manager: Arc<dyn PtyHost>) -> Self {
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+
+        // Keep the command blocks of each session in sync with the PTY readers
+        let mut events = pty_manager.get_event_receiver();
+        let sessions_clone = Arc::clone(&sessions);
+        let snapshot_notifier: Arc<Mutex<Option<mpsc::Sender<()>>>> = Arc::new(Mutex::new(None));
+        let notifier_clone = Arc::clone(&snapshot_notifier);
+        let history: Arc<Mutex<Option<HistoryDb>>> = Arc::new(Mutex::new(None));
+        let history_clone = Arc::clone(&history);
+        let active_session: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+        let active_clone = Arc::clone(&active_session);
+        let command_notifier: Arc<Mutex<Option<CommandNotifier>>> = Arc::new(Mutex::new(None));
+        let command_notifier_clone = Arc::clone(&command_notifier);
+        thread::spawn(move || loop {
+            match events.blocking_recv() {
+                Ok(event) => {
+                    if let SessionEvent::CommandFinished(block) = &event {
+                        Self::notify_finished(&active_clone, &command_notifier_clone, block);
+                    }
+                    Self::apply_event(&sessions_clone, &history_clone, event);
+                    Self::notify(&notifier_clone);
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        });
+
         SessionManager {
-            sessions: Arc::new(Mutex::new(HashMap::new())),
-            pty_manager: Arc::new(PtyManager::new()),
-            active_session: Arc::new(Mutex::new(None)),
+            sessions,
+            pty_manager,
+            active_session,
+            layouts: Arc::new(Mutex::new(Vec::new())),
+            snapshot_notifier,
+            history,
+            command_notifier,
+            broadcast: Arc::new(Mutex::new(BroadcastGroups::default())),
+        }
+    }
+
+    fn notify(notifier: &Mutex<Option<mpsc::Sender<()>>>) {
+        if let Some(sender) = notifier.lock().unwrap().as_ref() {
+            let _ = sender.send(());
+        }
+    }
+
+    fn build_snapshot(
+        sessions: &Mutex<HashMap<String, TerminalSession>>,
+        layouts: &Mutex<Vec<PaneLayout>>,
+        active_session: &Mutex<Option<String>>,
+    ) -> SessionSnapshot {
+        let layouts = layouts.lock().unwrap().clone();
+        let order: Vec<String> = layouts.iter().flat_map(|l| l.root.session_ids()).collect();
+        let active = active_session.lock().unwrap().clone();
+        let sessions = sessions.lock().unwrap();
+
+        SessionSnapshot {
+            version: SNAPSHOT_VERSION,
+            active: active.and_then(|id| order.iter().position(|o| *o == id)),
+            sessions: order
+                .iter()
+                .filter_map(|id| sessions.get(id))
+                .map(SavedSession::from_session)
+                .collect(),
+            layouts,
+        }
+    }
+
+    fn apply_event(
+        sessions: &Mutex<HashMap<String, TerminalSession>>,
+        history: &Mutex<Option<HistoryDb>>,
+        event: SessionEvent,
+    ) {
+        let mut sessions = sessions.lock().unwrap();
+        let finished = matches!(event, SessionEvent::CommandFinished(_));
+
+        match event {
+            SessionEvent::CommandStarted(mut block) | SessionEvent::CommandFinished(mut block) => {
+                let Some(session) = sessions.get_mut(&block.session_id) else {
+                    return;
+                };
+                // The directory can change while the command runs (e.g.
+                // `make -C sub`), so keep the one it started in
+                let existing = session.commands.iter_mut().find(|c| c.id == block.id);
+                if block.cwd.is_none() {
+                    block.cwd = match &existing {
+                        Some(existing) => existing.cwd.clone(),
+                        None => Some(session.current_directory.clone()),
+                    };
+                }
+                let recorded = finished.then(|| block.clone());
+                match existing {
+                    Some(existing) => *existing = block,
+                    None => session.commands.push(block),
+                }
+                let excess = session.commands.len().saturating_sub(MAX_COMMAND_BLOCKS);
+                session.commands.drain(..excess);
+
+                // Writing the history may wait on the database; leave the
+                // sessions to everyone else meanwhile
+                drop(sessions);
+                if let Some(block) = recorded {
+                    Self::record_history(history, &block);
+                }
+            }
+            SessionEvent::DirectoryChanged { session_id, path } => {
+                if let Some(session) = sessions.get_mut(&session_id) {
+                    session.current_directory = path;
+                }
+            }
+            SessionEvent::SessionEnded {
+                session_id,
+                exit_code,
+                ..
+            } => {
+                if let Some(session) = sessions.get_mut(&session_id) {
+                    session.exit_code = Some(exit_code);
+                }
+            }
+            SessionEvent::TriggerFired(_) => {}
+        }
+    }
+
+    fn record_history(history: &Mutex<Option<HistoryDb>>, block: &CommandBlock) {
+        let history = history.lock().unwrap();
+        let Some(db) = history.as_ref() else {
+            return;
+        };
+        let command = block.command.trim();
+        if command.is_empty() {
+            return;
+        }
+
+        let end_time = block.end_time.unwrap_or(block.start_time);
+        let entry = HistoryEntry {
+            id: 0,
+            command: command.to_string(),
+            cwd: block.cwd.clone().unwrap_or_default(),
+            exit_code: block.exit_code,
+            duration_ms: end_time.saturating_sub(block.start_time),
+            session_id: block.session_id.clone(),
+            hostname: history::hostname(),
+            start_time: block.start_time,
+            end_time,
+        };
+        if let Err(e) = db.record(&entry) {
+            eprintln!("Failed to record command history: {}", e);
+        }
+    }
+
+    /// Record every command that finishes from now on in `db`
+    pub fn record_history_to(&self, db: HistoryDb) {
+        *self.history.lock().unwrap() = Some(db);
+    }
+
+    /// Notify about long commands that finish while their session is not
+    /// the active one
+    pub fn notify_long_commands(&self, notifier: CommandNotifier) {
+        *self.command_notifier.lock().unwrap() = Some(notifier);
+    }
+
+    fn notify_finished(
+        active_session: &Mutex<Option<String>>,
+        notifier: &Mutex<Option<CommandNotifier>>,
+        block: &CommandBlock,
+    ) {
+        let notifier = notifier.lock().unwrap();
+        let Some(notifier) = notifier.as_ref() else {
+            return;
+        };
+
+        let end_time = block.end_time.unwrap_or(block.start_time);
+        let command = FinishedCommand {
+            command: block.command.clone(),
+            exit_code: block.exit_code,
+            duration: Duration::from_millis(end_time.saturating_sub(block.start_time)),
+            focused: active_session.lock().unwrap().as_deref() == Some(block.session_id.as_str()),
+        };
+        if let Some(notification) = notifier.notification(&command) {
+            if let Err(e) = notifier.send(&notification) {
+                eprintln!("Failed to send notification: {}", e);
+            }
+        }
+    }
+
+    /// Look up recorded commands across all sessions, past and present
+    pub fn search_history(&self, query: &HistoryQuery, limit: usize) -> Result<Vec<HistoryEntry>> {
+        match self.history.lock().unwrap().as_ref() {
+            Some(db) => Ok(db.search(query, limit)?),
+            None => Err(anyhow::anyhow!("Command history is not available")),
         }
     }
 
-    pub fn create_session(&self, name: String) -> Result<String> {
-        let session_id = self.pty_manager.create_session()?;
+    pub fn create_session(&self, name: String, spec: SessionSpec) -> Result<String> {
+        let session_id = self.pty_manager.create_session(&spec)?;
         
         let session = TerminalSession {
             id: session_id.clone(),
             name,
             active: true,
-            current_directory: std::env::var("HOME").unwrap_or_else(|_| "/".to_string()),
+            current_directory: spec.resolve_cwd(),
             commands: Vec::new(),
+            restored_commands: Vec::new(),
+            spec,
+            exit_code: None,
         };
 
         self.sessions.lock().unwrap().insert(session_id.clone(), session);
+        self.layouts.lock().unwrap().push(PaneLayout::new(&session_id));
         *self.active_session.lock().unwrap() = Some(session_id.clone());
+        Self::notify(&self.snapshot_notifier);
 
         Ok(session_id)
     }
 
+    /// Split a session's pane in two, starting a new session in the other
+    /// half with the same spec and working directory. The new pane gets focus.
+    pub fn split_pane(
+        &self,
+        session_id: &str,
+        direction: SplitDirection,
+        name: Option<String>,
+    ) -> Result<String> {
+        let (name, spec) = {
+            let sessions = self.sessions.lock().unwrap();
+            let session = sessions
+                .get(session_id)
+                .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+            let mut spec = session.spec.clone();
+            if Path::new(&session.current_directory).is_dir() {
+                spec.cwd = Some(session.current_directory.clone());
+            }
+            (name.unwrap_or_else(|| session.name.clone()), spec)
+        };
+
+        let new_session_id = self.pty_manager.create_session(&spec)?;
+        self.sessions.lock().unwrap().insert(
+            new_session_id.clone(),
+            TerminalSession {
+                id: new_session_id.clone(),
+                name,
+                active: true,
+                current_directory: spec.resolve_cwd(),
+                commands: Vec::new(),
+                restored_commands: Vec::new(),
+                spec,
+                exit_code: None,
+            },
+        );
+
+        let split = self
+            .layouts
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .any(|layout| layout.split(session_id, &new_session_id, direction));
+        if !split {
+            // The target was closed meanwhile; the new session gets a tab
+            self.layouts.lock().unwrap().push(PaneLayout::new(&new_session_id));
+        }
+        *self.active_session.lock().unwrap() = Some(new_session_id.clone());
+        Self::notify(&self.snapshot_notifier);
+
+        Ok(new_session_id)
+    }
+
+    /// Move the divider after a session's pane; `ratio` is the share of
+    /// the split on the pane's side of it
+    pub fn resize_pane(&self, session_id: &str, ratio: f32) -> Result<PaneLayout> {
+        self.update_layout(session_id, |layout| {
+            if layout.set_ratio(session_id, ratio) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("No divider after this pane"))
+            }
+        })
+    }
+
+    /// Exchange two panes of the same tab
+    pub fn swap_panes(&self, session_id: &str, other_session_id: &str) -> Result<PaneLayout> {
+        self.update_layout(session_id, |layout| {
+            if layout.swap(session_id, other_session_id) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Panes are not in the same tab"))
+            }
+        })
+    }
+
+    fn update_layout<F>(&self, session_id: &str, update: F) -> Result<PaneLayout>
+    where
+        F: FnOnce(&mut PaneLayout) -> Result<()>,
+    {
+        let mut layouts = self.layouts.lock().unwrap();
+        let layout = layouts
+            .iter_mut()
+            .find(|layout| layout.root.contains(session_id))
+            .ok_or_else(|| anyhow::anyhow!("Session not found"))?;
+
+        update(layout)?;
+        let layout = layout.clone();
+        drop(layouts);
+
+        Self::notify(&self.snapshot_notifier);
+        Ok(layout)
+    }
+
+    /// Layout of the tab holding a session
+    pub fn get_layout(&self, session_id: &str) -> Option<PaneLayout> {
+        self.layouts
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|layout| layout.root.contains(session_id))
+            .cloned()
+    }
+
+    /// Layouts of all tabs, in tab order
+    pub fn list_layouts(&self) -> Vec<PaneLayout> {
+        self.layouts.lock().unwrap().clone()
+    }
+
+    /// Bring back the sessions of a snapshot. Sessions the host still runs
+    /// (a `zenterm-server` that outlived the window) are attached again as
+    /// they are; the rest are recreated in their last directories, with
+    /// their saved command history as read-only scrollback. Sessions that
+    /// fail to start are skipped, and running sessions the snapshot does
+    /// not know get tabs of their own. Returns the ids in tab order.
+    pub fn restore(&self, snapshot: SessionSnapshot) -> Vec<String> {
+        let mut restored = Vec::new();
+        let mut renamed = HashMap::new();
+        let mut active = None;
+        let mut running: HashMap<String, HostedSession> = match self.pty_manager.list_sessions() {
+            Ok(sessions) => sessions.into_iter().map(|s| (s.id.clone(), s)).collect(),
+            Err(e) => {
+                eprintln!("Failed to list running sessions: {}", e);
+                HashMap::new()
+            }
+        };
+
+        for (index, saved) in snapshot.sessions.into_iter().enumerate() {
+            let (session_id, current_directory) = match running.remove(&saved.id) {
+                Some(hosted) if self.pty_manager.attach(&hosted.id).is_ok() => {
+                    (hosted.id, hosted.cwd)
+                }
+                _ => {
+                    let spec = saved.restore_spec();
+                    let history = render_history(&saved.commands);
+                    match self.pty_manager.create_session_with_history(&spec, &history) {
+                        Ok(session_id) => (session_id, spec.resolve_cwd()),
+                        Err(e) => {
+                            eprintln!("Failed to restore session {:?}: {}", saved.name, e);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            if snapshot.active == Some(index) || active.is_none() {
+                active = Some(session_id.clone());
+            }
+            renamed.insert(saved.id, session_id.clone());
+            self.sessions.lock().unwrap().insert(session_id.clone(), TerminalSession {
+                id: session_id.clone(),
+                name: saved.name,
+                active: true,
+                current_directory,
+                commands: Vec::new(),
+                restored_commands: saved.commands,
+                spec: saved.spec,
+                exit_code: None,
+            });
+            restored.push(session_id);
+        }
+
+        for hosted in running.into_values() {
+            if self.pty_manager.attach(&hosted.id).is_err() {
+                continue;
+            }
+            self.sessions.lock().unwrap().insert(hosted.id.clone(), TerminalSession {
+                id: hosted.id.clone(),
+                name: "Terminal".to_string(),
+                active: true,
+                current_directory: hosted.cwd,
+                commands: Vec::new(),
+                restored_commands: Vec::new(),
+                spec: hosted.spec,
+                exit_code: None,
+            });
+            active.get_or_insert_with(|| hosted.id.clone());
+            restored.push(hosted.id);
+        }
+
+        // Rebuild the tabs around the new session ids, dropping panes that
+        // failed to start. Sessions no layout mentions get a tab of their own.
+        let mut layouts: Vec<PaneLayout> = snapshot
+            .layouts
+            .into_iter()
+            .filter_map(|layout| {
+                let root = layout.root.map_sessions(&|id| renamed.get(id).cloned())?;
+                let focused = renamed
+                    .get(&layout.focused)
+                    .filter(|id| root.contains(id))
+                    .cloned()
+                    .unwrap_or_else(|| root.session_ids().remove(0));
+                Some(PaneLayout {
+                    id: layout.id,
+                    root,
+                    focused,
+                })
+            })
+            .collect();
+        for session_id in &restored {
+            if !layouts.iter().any(|layout| layout.root.contains(session_id)) {
+                layouts.push(PaneLayout::new(session_id));
+            }
+        }
+        self.layouts.lock().unwrap().extend(layouts);
+
+        if let Some(active) = active {
+            let _ = self.set_active_session(active);
+        }
+        restored
+    }
+
+    /// Snapshot of the open sessions in tab order
+    pub fn snapshot(&self) -> SessionSnapshot {
+        Self::build_snapshot(&self.sessions, &self.layouts, &self.active_session)
+    }
+
+    /// Write a snapshot to `path` now, e.g. on shutdown
+    pub fn save_snapshot(&self, path: &Path) -> Result<()> {
+        self.snapshot().save(path)
+    }
+
+    /// Keep a snapshot at `path` up to date from now on, rewriting it shortly
+    /// after sessions are opened, closed, switched or change directory
+    pub fn persist_to(&self, path: PathBuf) {
+        let (sender, receiver) = mpsc::channel();
+        *self.snapshot_notifier.lock().unwrap() = Some(sender);
+
+        let sessions = Arc::clone(&self.sessions);
+        let layouts = Arc::clone(&self.layouts);
+        let active_session = Arc::clone(&self.active_session);
+        thread::spawn(move || {
+            while receiver.recv().is_ok() {
+                // Coalesce bursts of changes into one write
+                thread::sleep(SNAPSHOT_DEBOUNCE);
+                while receiver.try_recv().is_ok() {}
+
+                let snapshot = Self::build_snapshot(&sessions, &layouts, &active_session);
+                if let Err(e) = snapshot.save(&path) {
+                    eprintln!("Failed to save session snapshot: {}", e);
+                }
+            }
+        });
+    }
+
     pub fn get_session(&self, session_id: &str) -> Option<TerminalSession> {
         self.sessions.lock().unwrap().get(session_id).cloned()
     }
 
+    /// Sessions in tab order, and in pane order within a tab
     pub fn list_sessions(&self) -> Vec<TerminalSession> {
-        self.sessions.lock().unwrap().values().cloned().collect()
+        let layouts = self.layouts.lock().unwrap();
+        let sessions = self.sessions.lock().unwrap();
+        layouts
+            .iter()
+            .flat_map(|layout| layout.root.session_ids())
+            .filter_map(|id| sessions.get(&id).cloned())
+            .collect()
     }
 
+    /// Make a session active, focusing its pane within its tab
     pub fn set_active_session(&self, session_id: String) -> Result<()> {
         if self.sessions.lock().unwrap().contains_key(&session_id) {
+            let mut layouts = self.layouts.lock().unwrap();
+            if let Some(layout) = layouts.iter_mut().find(|l| l.root.contains(&session_id)) {
+                layout.focused = session_id.clone();
+            }
+            drop(layouts);
+
             *self.active_session.lock().unwrap() = Some(session_id);
+            Self::notify(&self.snapshot_notifier);
             Ok(())
         } else {
             Err(anyhow::anyhow!("Session not found"))
@@ -67,30 +772,387 @@ impl SessionManager {
         self.active_session.lock().unwrap().clone()
     }
 
-    pub fn execute_command(&self, session_id: &str, command: &str) -> Result<String> {
-        self.pty_manager.write_command(session_id, command)
+    /// Run a command line, in every session of the broadcast group if
+    /// `session_id` is broadcasting. Returns the command id in `session_id`.
+    /// Like pasted input, a broadcast command of several lines has to be
+    /// `confirmed`.
+    pub fn execute_command(
+        &self,
+        session_id: &str,
+        command: &str,
+        confirmed: bool,
+    ) -> Result<String> {
+        let others = self.other_targets(session_id);
+        if !others.is_empty() && !confirmed && is_multiline(command.as_bytes()) {
+            return Err(anyhow::anyhow!(
+                "Running several lines in {} sessions needs confirmation",
+                others.len() + 1
+            ));
+        }
+
+        let command_id = self.pty_manager.write_command(session_id, command)?;
+        for target in others {
+            if let Err(e) = self.pty_manager.write_command(&target, command) {
+                eprintln!("Failed to broadcast command to {}: {}", target, e);
+            }
+        }
+        Ok(command_id)
+    }
+
+    /// Write raw input, to every session of the broadcast group if
+    /// `session_id` is broadcasting. Broadcasting input that spans several
+    /// lines, like a paste, has to be `confirmed`.
+    pub fn write_input(&self, session_id: &str, data: &[u8], confirmed: bool) -> Result<()> {
+        let others = self.other_targets(session_id);
+        if !others.is_empty() && !confirmed && is_multiline(data) {
+            return Err(anyhow::anyhow!(
+                "Pasting several lines into {} sessions needs confirmation",
+                others.len() + 1
+            ));
+        }
+
+        self.pty_manager.write_input(session_id, data)?;
+        for target in others {
+            if let Err(e) = self.pty_manager.write_input(&target, data) {
+                eprintln!("Failed to broadcast input to {}: {}", target, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The other sessions that input to `session_id` is broadcast to
+    fn other_targets(&self, session_id: &str) -> Vec<String> {
+        let mut targets = self.broadcast.lock().unwrap().targets(session_id);
+        targets.retain(|id| id != session_id);
+        targets
+    }
+
+    /// Add a session to a broadcast group, creating the group if needed.
+    /// A session is in at most one group.
+    pub fn join_broadcast_group(&self, name: &str, session_id: &str) -> Result<BroadcastGroup> {
+        if !self.sessions.lock().unwrap().contains_key(session_id) {
+            return Err(anyhow::anyhow!("Session not found"));
+        }
+        self.broadcast.lock().unwrap().join(name, session_id)
+    }
+
+    pub fn leave_broadcast_group(&self, session_id: &str) -> Result<()> {
+        if !self.broadcast.lock().unwrap().leave(session_id) {
+            return Err(anyhow::anyhow!("Session is not in a broadcast group"));
+        }
+        Ok(())
+    }
+
+    /// Stop or resume broadcasting for a session without leaving its group
+    pub fn set_broadcast_opt_out(
+        &self,
+        session_id: &str,
+        opted_out: bool,
+    ) -> Result<BroadcastGroup> {
+        self.broadcast.lock().unwrap().set_opted_out(session_id, opted_out)
+    }
+
+    pub fn list_broadcast_groups(&self) -> Vec<BroadcastGroup> {
+        self.broadcast.lock().unwrap().list()
+    }
+
+    pub fn send_signal(&self, session_id: &str, signal: SessionSignal) -> Result<()> {
+        self.pty_manager.send_signal(session_id, signal)
+    }
+
+    /// Shell process ids of this manager's sessions, for resource sampling
+    pub fn shell_pids(&self) -> Result<Vec<(String, u32)>> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(self
+            .pty_manager
+            .list_sessions()?
+            .into_iter()
+            .filter(|hosted| sessions.contains_key(&hosted.id))
+            .filter_map(|hosted| Some((hosted.id, hosted.pid?)))
+            .collect())
+    }
+
+    pub fn foreground_process(&self, session_id: &str) -> Result<Option<ForegroundProcess>> {
+        self.pty_manager.foreground_process(session_id)
+    }
+
+    /// Start or stop recording a session; recordings are titled with the
+    /// session name
+    pub fn toggle_recording(
+        &self,
+        session_id: &str,
+        path: Option<PathBuf>,
+    ) -> Result<RecordingState> {
+        let title = self.get_session(session_id).map(|session| session.name);
+        self.pty_manager.toggle_recording(session_id, path, title)
     }
 
     pub fn get_output_receiver(&self) -> broadcast::Receiver<TerminalOutput> {
         self.pty_manager.get_output_receiver()
     }
 
+    /// Forwarder that delivers this manager's output to a frontend
+    pub fn output_forwarder(&self) -> OutputForwarder {
+        OutputForwarder::new(Arc::clone(&self.pty_manager))
+    }
+
+    /// Replace the output triggers of every session
+    pub fn set_triggers(&self, triggers: TriggerSet) -> Result<()> {
+        self.pty_manager.set_triggers(triggers)
+    }
+
+    /// Replace the secret detectors of every session
+    pub fn set_redaction(&self, config: &RedactionConfig) -> Result<()> {
+        self.pty_manager.set_redaction(config)
+    }
+
+    pub fn get_event_receiver(&self) -> broadcast::Receiver<SessionEvent> {
+        self.pty_manager.get_event_receiver()
+    }
+
     pub fn resize_session(&self, session_id: &str, rows: u16, cols: u16) -> Result<()> {
         self.pty_manager.resize_session(session_id, rows, cols)
     }
 
+    pub fn get_buffer(&self, session_id: &str, from_offset: u64) -> Option<BufferSlice> {
+        self.pty_manager.get_buffer(session_id, from_offset)
+    }
+
+    pub fn get_screen(&self, session_id: &str) -> Option<ScreenSnapshot> {
+        self.pty_manager.get_screen(session_id)
+    }
+
     pub fn close_session(&self, session_id: &str) -> Result<()> {
         self.pty_manager.close_session(session_id)?;
         self.sessions.lock().unwrap().remove(session_id);
+        self.broadcast.lock().unwrap().leave(session_id);
+
+        // Close the pane, and the tab with it if it was the last one
+        let mut layouts = self.layouts.lock().unwrap();
+        let index = layouts.iter().position(|l| l.root.contains(session_id));
+        let mut next_active = None;
+        if let Some(index) = index {
+            if layouts[index].remove(session_id) {
+                next_active = Some(layouts[index].focused.clone());
+            } else {
+                layouts.remove(index);
+                next_active = layouts
+                    .get(index.min(layouts.len().saturating_sub(1)))
+                    .map(|layout| layout.focused.clone());
+            }
+        }
+        drop(layouts);
         
         // If this was the active session, find another one or clear it
         let active = self.active_session.lock().unwrap().clone();
         if active.as_ref() == Some(&session_id.to_string()) {
-            let sessions = self.sessions.lock().unwrap();
-            let new_active = sessions.keys().next().cloned();
-            *self.active_session.lock().unwrap() = new_active;
+            *self.active_session.lock().unwrap() = next_active;
         }
+        Self::notify(&self.snapshot_notifier);
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::CommandStatus;
+    use notifications::MemorySink;
+
+    #[test]
+    fn test_split_and_remove_panes() {
+        let mut layout = PaneLayout::new("a");
+        assert!(layout.split("a", "b", SplitDirection::Horizontal));
+        assert!(layout.split("b", "c", SplitDirection::Vertical));
+        assert!(!layout.split("missing", "d", SplitDirection::Vertical));
+        assert_eq!(layout.root.session_ids(), vec!["a", "b", "c"]);
+        assert_eq!(layout.focused, "c");
+
+        // Closing "b" lets "c" take its half of the first split
+        assert!(layout.remove("b"));
+        assert_eq!(
+            layout.root,
+            PaneNode::Split {
+                direction: SplitDirection::Horizontal,
+                ratio: 0.5,
+                first: Box::new(PaneNode::pane("a")),
+                second: Box::new(PaneNode::pane("c")),
+            }
+        );
+
+        assert!(layout.remove("c"));
+        assert_eq!(layout.root, PaneNode::pane("a"));
+        assert_eq!(layout.focused, "a");
+        assert!(!layout.remove("a"));
+    }
+
+    #[test]
+    fn test_resize_and_swap_panes() {
+        let mut layout = PaneLayout::new("a");
+        assert!(!layout.set_ratio("a", 0.3));
+
+        layout.split("a", "b", SplitDirection::Horizontal);
+        layout.split("b", "c", SplitDirection::Vertical);
+
+        // Dividers are named by the pane before them: "a" moves the outer
+        // one, "b" the nested one, and nothing follows "c"
+        assert!(layout.set_ratio("b", 0.75));
+        assert!(layout.set_ratio("a", 0.99));
+        assert!(!layout.set_ratio("c", 0.5));
+        let PaneNode::Split { ratio, second, .. } = &layout.root else {
+            panic!("expected a split");
+        };
+        assert_eq!(*ratio, 1.0 - MIN_SPLIT_RATIO);
+        let PaneNode::Split { ratio, .. } = second.as_ref() else {
+            panic!("expected a nested split");
+        };
+        assert_eq!(*ratio, 0.75);
+
+        assert!(layout.swap("a", "c"));
+        assert_eq!(layout.root.session_ids(), vec!["c", "b", "a"]);
+        assert!(!layout.swap("a", "missing"));
+    }
+
+    #[test]
+    fn test_layout_serialization() {
+        let mut layout = PaneLayout::new("a");
+        layout.split("a", "b", SplitDirection::Vertical);
+
+        let json = serde_json::to_value(&layout.root).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "split",
+                "direction": "vertical",
+                "ratio": 0.5,
+                "first": { "type": "pane", "session_id": "a" },
+                "second": { "type": "pane", "session_id": "b" },
+            })
+        );
+
+        let parsed: PaneLayout =
+            serde_json::from_str(&serde_json::to_string(&layout).unwrap()).unwrap();
+        assert_eq!(parsed, layout);
+    }
+
+    #[test]
+    fn test_notify_finished_in_background_only() {
+        let sink = MemorySink::new();
+        let notifier = Mutex::new(Some(CommandNotifier::new(
+            Duration::from_secs(10),
+            Box::new(sink.clone()),
+        )));
+        let active = Mutex::new(Some("a".to_string()));
+        let block = |session_id: &str, secs: u64| CommandBlock {
+            id: "c".to_string(),
+            session_id: session_id.to_string(),
+            command: "cargo build".to_string(),
+            output: Vec::new(),
+            exit_code: Some(0),
+            start_time: 1_000,
+            end_time: Some(1_000 + secs * 1000),
+            status: CommandStatus::Completed,
+            cwd: None,
+        };
+
+        SessionManager::notify_finished(&active, &notifier, &block("a", 60));
+        SessionManager::notify_finished(&active, &notifier, &block("b", 2));
+        assert!(sink.sent().is_empty());
+
+        SessionManager::notify_finished(&active, &notifier, &block("b", 60));
+        let sent = sink.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].body, "cargo build succeeded after 1m 00s");
+    }
+
+    #[test]
+    fn test_command_blocks_are_capped() {
+        let session = TerminalSession {
+            id: "a".to_string(),
+            name: "Terminal".to_string(),
+            active: true,
+            current_directory: "/".to_string(),
+            commands: Vec::new(),
+            restored_commands: Vec::new(),
+            spec: SessionSpec::default(),
+            exit_code: None,
+        };
+        let sessions = Mutex::new(HashMap::from([("a".to_string(), session)]));
+        let history = Mutex::new(None);
+
+        for i in 0..MAX_COMMAND_BLOCKS + 5 {
+            let block = CommandBlock {
+                id: i.to_string(),
+                session_id: "a".to_string(),
+                command: "true".to_string(),
+                output: Vec::new(),
+                exit_code: None,
+                start_time: 1_000,
+                end_time: None,
+                status: CommandStatus::Running,
+                cwd: None,
+            };
+            SessionManager::apply_event(&sessions, &history, SessionEvent::CommandStarted(block));
+        }
+
+        let sessions = sessions.lock().unwrap();
+        let commands = &sessions["a"].commands;
+        assert_eq!(commands.len(), MAX_COMMAND_BLOCKS);
+        assert_eq!(commands[0].id, "5");
+    }
+
+    #[test]
+    fn test_history_keeps_the_directory_a_command_started_in() {
+        let session = TerminalSession {
+            id: "a".to_string(),
+            name: "Terminal".to_string(),
+            active: true,
+            current_directory: "/src".to_string(),
+            commands: Vec::new(),
+            restored_commands: Vec::new(),
+            spec: SessionSpec::default(),
+            exit_code: None,
+        };
+        let sessions = Mutex::new(HashMap::from([("a".to_string(), session)]));
+        let history = Mutex::new(Some(HistoryDb::open_in_memory().unwrap()));
+        let mut block = CommandBlock {
+            id: "c".to_string(),
+            session_id: "a".to_string(),
+            command: "make -C sub".to_string(),
+            output: Vec::new(),
+            exit_code: None,
+            start_time: 1_000,
+            end_time: None,
+            status: CommandStatus::Running,
+            cwd: None,
+        };
+
+        let event = SessionEvent::CommandStarted(block.clone());
+        SessionManager::apply_event(&sessions, &history, event);
+        // The watcher sees make change directory while it runs
+        let event = SessionEvent::DirectoryChanged {
+            session_id: "a".to_string(),
+            path: "/src/sub".to_string(),
+        };
+        SessionManager::apply_event(&sessions, &history, event);
+        block.exit_code = Some(0);
+        block.end_time = Some(2_000);
+        block.status = CommandStatus::Completed;
+        SessionManager::apply_event(&sessions, &history, SessionEvent::CommandFinished(block));
+
+        let query = HistoryQuery::Prefix {
+            text: "make".to_string(),
+        };
+        let entries = history
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .search(&query, 10)
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].cwd, "/src");
+        let sessions = sessions.lock().unwrap();
+        assert_eq!(sessions["a"].commands[0].cwd.as_deref(), Some("/src"));
+    }
+}
@@ -0,0 +1,714 @@
+use crate::terminal::runtime_dir::{create_private_dir, runtime_dir};
+use crate::terminal::{now_millis, CommandBlock, CommandStatus, TerminalOutput};
+use anyhow::Result;
+use portable_pty::CommandBuilder;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Hook scripts emitting OSC 133 prompt/command markers, one per supported shell
+pub const BASH_INTEGRATION: &str = include_str!("../../shell-integration/zenterm.bash");
+pub const ZSH_INTEGRATION: &str = include_str!("../../shell-integration/zenterm.zsh");
+pub const FISH_INTEGRATION: &str = include_str!("../../shell-integration/zenterm.fish");
+
+const OSC_INTRODUCER: &str = "\x1b]";
+
+/// OSC sequences consumed by shell integration: semantic prompt markers
+/// (133) and working directory reports (7)
+const TRACKED_OSCS: [&str; 2] = ["133;", "7;"];
+
+// Sequences longer than this are not ours; give up and pass the bytes through
+const MAX_MARKER_LEN: usize = 4096;
+
+/// Output kept per command block; beyond it the oldest output is dropped.
+/// The full stream stays in the session's `OutputBuffer`.
+pub const MAX_BLOCK_OUTPUT: usize = 1024 * 1024;
+
+/// Semantic prompt markers defined by OSC 133
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellMarker {
+    /// `A`: the shell is about to draw the prompt
+    PromptStart,
+    /// `B`: the prompt is drawn, user input follows
+    CommandStart,
+    /// `C`: the command line was accepted, command output follows
+    CommandExecuted,
+    /// `D[;exit]`: the command finished
+    CommandFinished(Option<i32>),
+}
+
+/// A piece of PTY output split at marker boundaries
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellEvent {
+    Output(String),
+    Marker(ShellMarker),
+    /// OSC 7 report of the shell's current directory
+    WorkingDirectory(String),
+}
+
+/// Streaming scanner that pulls shell integration sequences (OSC 133 and
+/// OSC 7) out of decoded PTY output. Sequences may be split across reads,
+/// so an unterminated one is held back until the rest of it arrives.
+#[derive(Debug, Default)]
+pub struct OscParser {
+    pending: String,
+}
+
+impl OscParser {
+    pub fn feed(&mut self, text: &str) -> Vec<ShellEvent> {
+        self.pending.push_str(text);
+        let input = std::mem::take(&mut self.pending);
+
+        let mut events = Vec::new();
+        let mut output = String::new();
+        let mut rest = input.as_str();
+
+        while !rest.is_empty() {
+            let Some(start) = rest.find(OSC_INTRODUCER) else {
+                // Hold back a trailing ESC that may start an introducer
+                let keep = usize::from(rest.ends_with('\x1b'));
+                output.push_str(&rest[..rest.len() - keep]);
+                self.pending.push_str(&rest[rest.len() - keep..]);
+                break;
+            };
+
+            output.push_str(&rest[..start]);
+            let body = &rest[start + OSC_INTRODUCER.len()..];
+
+            if !TRACKED_OSCS.iter().any(|prefix| body.starts_with(prefix)) {
+                if TRACKED_OSCS.iter().any(|prefix| prefix.starts_with(body)) {
+                    // Input ends inside the OSC number, e.g. "\x1b]13"
+                    self.pending.push_str(&rest[start..]);
+                    break;
+                }
+                // Some other OSC (window title, hyperlink...), leave it alone
+                output.push_str(OSC_INTRODUCER);
+                rest = body;
+                continue;
+            }
+
+            match find_terminator(body) {
+                Some((end, terminator_len)) => {
+                    if !output.is_empty() {
+                        events.push(ShellEvent::Output(std::mem::take(&mut output)));
+                    }
+                    if let Some(event) = parse_osc(&body[..end]) {
+                        events.push(event);
+                    }
+                    rest = &body[end + terminator_len..];
+                }
+                None if body.len() <= MAX_MARKER_LEN => {
+                    self.pending.push_str(&rest[start..]);
+                    break;
+                }
+                None => {
+                    // Not terminated in time, treat the introducer as plain output
+                    output.push_str(OSC_INTRODUCER);
+                    rest = body;
+                }
+            }
+        }
+
+        if !output.is_empty() {
+            events.push(ShellEvent::Output(output));
+        }
+        events
+    }
+}
+
+/// Find the BEL or ST (`ESC \`) that ends an OSC sequence
+fn find_terminator(body: &str) -> Option<(usize, usize)> {
+    let bel = body.find('\x07').map(|i| (i, 1));
+    let st = body.find("\x1b\\").map(|i| (i, 2));
+    match (bel, st) {
+        (Some(a), Some(b)) => Some(if a.0 < b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn parse_osc(payload: &str) -> Option<ShellEvent> {
+    if let Some(marker) = payload.strip_prefix("133;") {
+        return parse_marker(marker).map(ShellEvent::Marker);
+    }
+    payload
+        .strip_prefix("7;")
+        .and_then(parse_file_url)
+        .map(ShellEvent::WorkingDirectory)
+}
+
+fn parse_marker(payload: &str) -> Option<ShellMarker> {
+    let mut params = payload.split(';');
+    match params.next()? {
+        "A" => Some(ShellMarker::PromptStart),
+        "B" => Some(ShellMarker::CommandStart),
+        "C" => Some(ShellMarker::CommandExecuted),
+        "D" => Some(ShellMarker::CommandFinished(
+            params.next().and_then(|code| code.trim().parse().ok()),
+        )),
+        _ => None,
+    }
+}
+
+/// Extract the percent-decoded path from `file://host/path`. Reports from
+/// another host, e.g. a shell on the far side of `ssh`, name a directory
+/// that does not exist here and are ignored.
+fn parse_file_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    let (host, path) = rest.split_at(rest.find('/')?);
+    if !(host.is_empty()
+        || host.eq_ignore_ascii_case("localhost")
+        || host.eq_ignore_ascii_case(&history::hostname()))
+    {
+        return None;
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    Input,
+    Running,
+}
+
+/// Changes to a session's command blocks driven by shell markers
+#[derive(Debug, Clone)]
+pub enum BlockUpdate {
+    Started(CommandBlock),
+    Finished(CommandBlock),
+}
+
+/// Turns the marker stream of one session into command blocks
+#[derive(Debug)]
+pub struct CommandTracker {
+    session_id: String,
+    phase: Phase,
+    input: String,
+    /// Bytes of output in the running block
+    output_len: usize,
+}
+
+impl CommandTracker {
+    pub fn new(session_id: String) -> Self {
+        Self {
+            session_id,
+            phase: Phase::Idle,
+            input: String::new(),
+            output_len: 0,
+        }
+    }
+
+    /// Apply one event to the session's in-flight block.
+    ///
+    /// `current` is the block created by `write_command`, if any; it is
+    /// reused when the shell starts executing so the returned command id
+    /// stays valid. Commands typed directly at the prompt get a new block
+    /// named after the echoed input line.
+    pub fn handle(
+        &mut self,
+        event: ShellEvent,
+        current: &mut Option<CommandBlock>,
+    ) -> Option<BlockUpdate> {
+        match event {
+            ShellEvent::Output(content) => {
+                match self.phase {
+                    Phase::Input => self.input.push_str(&content),
+                    Phase::Running => {
+                        if let Some(block) = current.as_mut() {
+                            self.output_len += content.len();
+                            block.output.push(TerminalOutput {
+                                session_id: self.session_id.clone(),
+                                content,
+                                is_error: false,
+                                timestamp: now_millis(),
+                                offset: None,
+                                highlighted: None,
+                            });
+                            self.output_len = keep_tail(&mut block.output, self.output_len);
+                        }
+                    }
+                    Phase::Idle => {}
+                }
+                None
+            }
+            ShellEvent::WorkingDirectory(_) => None,
+            ShellEvent::Marker(ShellMarker::PromptStart) => {
+                self.phase = Phase::Idle;
+                None
+            }
+            ShellEvent::Marker(ShellMarker::CommandStart) => {
+                self.phase = Phase::Input;
+                self.input.clear();
+                None
+            }
+            ShellEvent::Marker(ShellMarker::CommandExecuted) => {
+                self.phase = Phase::Running;
+                let typed = strip_control_sequences(&std::mem::take(&mut self.input));
+
+                let block = match current.take() {
+                    Some(block) if block.end_time.is_none() => block,
+                    _ => CommandBlock {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        session_id: self.session_id.clone(),
+                        command: typed,
+                        output: Vec::new(),
+                        exit_code: None,
+                        start_time: now_millis(),
+                        end_time: None,
+                        status: CommandStatus::Running,
+                        cwd: None,
+                    },
+                };
+
+                self.output_len = block.output.iter().map(|o| o.content.len()).sum();
+                *current = Some(block.clone());
+                Some(BlockUpdate::Started(block))
+            }
+            ShellEvent::Marker(ShellMarker::CommandFinished(exit_code)) => {
+                if self.phase != Phase::Running {
+                    // Empty command line or interrupted prompt, nothing ran
+                    self.phase = Phase::Idle;
+                    return None;
+                }
+                self.phase = Phase::Idle;
+
+                let mut block = current.take()?;
+                block.exit_code = exit_code;
+                block.end_time = Some(now_millis());
+                block.status = match exit_code {
+                    Some(code) if code != 0 => CommandStatus::Failed,
+                    _ => CommandStatus::Completed,
+                };
+                Some(BlockUpdate::Finished(block))
+            }
+        }
+    }
+}
+
+/// Drop the oldest of `output`, `len` bytes in all, until it fits in
+/// `MAX_BLOCK_OUTPUT`. Returns the new length.
+fn keep_tail(output: &mut Vec<TerminalOutput>, mut len: usize) -> usize {
+    while len > MAX_BLOCK_OUTPUT {
+        let excess = len - MAX_BLOCK_OUTPUT;
+        let first = &mut output[0].content;
+        if first.len() <= excess {
+            len -= first.len();
+            output.remove(0);
+        } else {
+            let mut cut = excess;
+            while !first.is_char_boundary(cut) {
+                cut += 1;
+            }
+            first.drain(..cut);
+            len -= cut;
+        }
+    }
+    len
+}
+
+/// Reduce an echoed input line to its text, dropping escape sequences
+/// (syntax highlighting, cursor movement) and control characters
+fn strip_control_sequences(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters until a final byte in 0x40..=0x7e
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC: until BEL or ST
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\x08' => {
+                out.pop();
+            }
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+
+    out.trim().to_string()
+}
+
+/// Directory holding the generated rc files that load the hook scripts,
+/// inside the private runtime directory so no other user can swap them
+fn integration_dir() -> Result<PathBuf> {
+    let runtime = runtime_dir();
+    create_private_dir(&runtime)?;
+    Ok(runtime.join("shell-integration"))
+}
+
+/// Write through a temp file and rename it into place, so a shell starting
+/// in another tab never sources a half-written script
+fn write_script(dir: &Path, name: &str, content: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+    let temp = dir.join(format!(".{}.{}", name, uuid::Uuid::new_v4()));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&temp)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .and_then(|()| fs::rename(&temp, &path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(path)
+}
+
+/// `path` as one single-quoted word for sh-like shells
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
+
+/// `path` as one single-quoted word for fish, where a backslash escapes a
+/// quote or another backslash even inside quotes
+fn fish_quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    format!("'{}'", path.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Arrange for `shell` to load the ZenTerm hooks on top of the user's own
+/// startup files, starting it as a login shell if requested. Returns
+/// `false` for shells we don't know, which are left untouched and simply
+/// produce no command blocks.
+pub fn inject(cmd: &mut CommandBuilder, shell: &str, login: bool) -> Result<bool> {
+    let dir = integration_dir()?;
+    let name = Path::new(shell)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(shell);
+
+    match name {
+        "bash" => {
+            // bash ignores --rcfile in login mode, so the rc file replays
+            // the login startup sequence itself
+            let startup = if login {
+                "[ -f /etc/profile ] && . /etc/profile\n\
+                 for f in ~/.bash_profile ~/.bash_login ~/.profile; do\n\
+                 \x20   [ -f \"$f\" ] && . \"$f\" && break\n\
+                 done\n"
+            } else {
+                "[ -f ~/.bashrc ] && . ~/.bashrc\n"
+            };
+            let rc = format!("{}{}", startup, BASH_INTEGRATION);
+            let file = if login { "bash_profile" } else { "bashrc" };
+            let path = write_script(&dir, file, &rc)?;
+            cmd.arg("--rcfile");
+            cmd.arg(path);
+        }
+        "zsh" => {
+            // zsh has no --rcfile; point ZDOTDIR at our directory and
+            // restore the user's value before sourcing their config
+            let zdotdir = dir.join("zsh");
+            let user_zdotdir = std::env::var("ZDOTDIR")
+                .or_else(|_| std::env::var("HOME"))
+                .unwrap_or_default();
+            // A .zshenv that moves ZDOTDIR (e.g. to ~/.config/zsh) says
+            // where the rest of the user's files are; keep it for them and
+            // point zsh back at ours so our .zshrc still runs
+            write_script(
+                &zdotdir,
+                ".zshenv",
+                "ZENTERM_ZDOTDIR=\"$ZDOTDIR\"\n\
+                 [[ -f \"$ZENTERM_USER_ZDOTDIR/.zshenv\" ]] && source \"$ZENTERM_USER_ZDOTDIR/.zshenv\"\n\
+                 if [[ \"$ZDOTDIR\" != \"$ZENTERM_ZDOTDIR\" ]]; then\n\
+                 \x20   ZENTERM_USER_ZDOTDIR=\"$ZDOTDIR\"\n\
+                 \x20   ZDOTDIR=\"$ZENTERM_ZDOTDIR\"\n\
+                 fi\n",
+            )?;
+            write_script(
+                &zdotdir,
+                ".zprofile",
+                "[[ -f \"$ZENTERM_USER_ZDOTDIR/.zprofile\" ]] && source \"$ZENTERM_USER_ZDOTDIR/.zprofile\"\n",
+            )?;
+            let integration = write_script(&zdotdir, "zenterm.zsh", ZSH_INTEGRATION)?;
+            write_script(
+                &zdotdir,
+                ".zshrc",
+                &format!(
+                    "ZDOTDIR=\"$ZENTERM_USER_ZDOTDIR\"\n\
+                     [[ -f \"$ZDOTDIR/.zshrc\" ]] && source \"$ZDOTDIR/.zshrc\"\n\
+                     source {}\n",
+                    shell_quote(&integration)
+                ),
+            )?;
+            cmd.env("ZENTERM_USER_ZDOTDIR", user_zdotdir);
+            cmd.env("ZDOTDIR", zdotdir);
+            if login {
+                cmd.arg("-l");
+            }
+        }
+        "fish" => {
+            let path = write_script(&dir, "zenterm.fish", FISH_INTEGRATION)?;
+            if login {
+                cmd.arg("--login");
+            }
+            cmd.arg("--init-command");
+            cmd.arg(format!("source {}", fish_quote(&path)));
+        }
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(m: ShellMarker) -> ShellEvent {
+        ShellEvent::Marker(m)
+    }
+
+    fn output(s: &str) -> ShellEvent {
+        ShellEvent::Output(s.to_string())
+    }
+
+    #[test]
+    fn test_parser_extracts_markers() {
+        let mut parser = OscParser::default();
+        let events = parser.feed("\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07a b\r\n\x1b]133;D;2\x1b\\");
+
+        assert_eq!(
+            events,
+            vec![
+                marker(ShellMarker::PromptStart),
+                output("$ "),
+                marker(ShellMarker::CommandStart),
+                output("ls\r\n"),
+                marker(ShellMarker::CommandExecuted),
+                output("a b\r\n"),
+                marker(ShellMarker::CommandFinished(Some(2))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_handles_markers_split_across_reads() {
+        let mut parser = OscParser::default();
+
+        assert_eq!(parser.feed("out\x1b]1"), vec![output("out")]);
+        assert_eq!(parser.feed("33;D"), vec![]);
+        assert_eq!(
+            parser.feed(";0\x07more"),
+            vec![marker(ShellMarker::CommandFinished(Some(0))), output("more")]
+        );
+    }
+
+    #[test]
+    fn test_parser_extracts_working_directory() {
+        let mut parser = OscParser::default();
+        let report = format!(
+            "\x1b]7;file://{}/home/me/My%20Docs\x07$ ",
+            history::hostname()
+        );
+        assert_eq!(
+            parser.feed(&report),
+            vec![
+                ShellEvent::WorkingDirectory("/home/me/My Docs".to_string()),
+                output("$ "),
+            ]
+        );
+
+        // Directories on other hosts are not ours
+        assert_eq!(parser.feed("\x1b]7;file://remote.example/srv\x07"), vec![]);
+        assert_eq!(
+            parser.feed("\x1b]7;file://localhost/srv\x07"),
+            vec![ShellEvent::WorkingDirectory("/srv".to_string())]
+        );
+
+        // Split inside the OSC number
+        assert_eq!(parser.feed("\x1b]"), vec![]);
+        assert_eq!(parser.feed("7"), vec![]);
+        assert_eq!(
+            parser.feed(";file:///tmp\x1b\\"),
+            vec![ShellEvent::WorkingDirectory("/tmp".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parser_passes_through_other_escapes() {
+        let mut parser = OscParser::default();
+        let text = "\x1b]0;title\x07\x1b[1;31mred\x1b[0m";
+        assert_eq!(parser.feed(text), vec![output(text)]);
+    }
+
+    #[test]
+    fn test_tracker_builds_blocks_from_typed_commands() {
+        let mut tracker = CommandTracker::new("s1".to_string());
+        let mut current = None;
+        let mut parser = OscParser::default();
+        let mut updates = Vec::new();
+
+        let stream = "\x1b]133;A\x07$ \x1b]133;B\x07false\x1b[K\r\n\x1b]133;C\x07oops\r\n\x1b]133;D;1\x07\x1b]133;A\x07";
+        for event in parser.feed(stream) {
+            updates.extend(tracker.handle(event, &mut current));
+        }
+
+        assert_eq!(updates.len(), 2);
+        match &updates[1] {
+            BlockUpdate::Finished(block) => {
+                assert_eq!(block.command, "false");
+                assert_eq!(block.exit_code, Some(1));
+                assert!(matches!(block.status, CommandStatus::Failed));
+                assert!(block.end_time.is_some());
+                let text: String = block.output.iter().map(|o| o.content.as_str()).collect();
+                assert_eq!(text, "oops\r\n");
+            }
+            other => panic!("expected finished block, got {:?}", other),
+        }
+        assert!(current.is_none());
+    }
+
+    #[test]
+    fn test_tracker_reuses_block_from_write_command() {
+        let mut tracker = CommandTracker::new("s1".to_string());
+        let mut current = Some(CommandBlock {
+            id: "cmd-1".to_string(),
+            session_id: "s1".to_string(),
+            command: "true".to_string(),
+            output: Vec::new(),
+            exit_code: None,
+            start_time: 1,
+            end_time: None,
+            status: CommandStatus::Running,
+            cwd: None,
+        });
+
+        tracker.handle(marker(ShellMarker::CommandStart), &mut current);
+        tracker.handle(output("true\r\n"), &mut current);
+        tracker.handle(marker(ShellMarker::CommandExecuted), &mut current);
+        let update = tracker.handle(marker(ShellMarker::CommandFinished(Some(0))), &mut current);
+
+        match update {
+            Some(BlockUpdate::Finished(block)) => {
+                assert_eq!(block.id, "cmd-1");
+                assert_eq!(block.exit_code, Some(0));
+                assert!(matches!(block.status, CommandStatus::Completed));
+            }
+            other => panic!("expected finished block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tracker_keeps_the_tail_of_long_output() {
+        let mut tracker = CommandTracker::new("s1".to_string());
+        let mut current = None;
+        tracker.handle(marker(ShellMarker::CommandStart), &mut current);
+        tracker.handle(marker(ShellMarker::CommandExecuted), &mut current);
+
+        let chunk = "é".repeat(30_000) + "\r\n";
+        for _ in 0..20 {
+            tracker.handle(output(&chunk), &mut current);
+        }
+        tracker.handle(output("done\r\n"), &mut current);
+
+        let block = current.unwrap();
+        let text: String = block.output.iter().map(|o| o.content.as_str()).collect();
+        assert!(text.len() <= MAX_BLOCK_OUTPUT && text.len() > MAX_BLOCK_OUTPUT - 4);
+        assert!(text.ends_with("é\r\ndone\r\n"));
+    }
+
+    #[test]
+    fn test_tracker_ignores_empty_command_lines() {
+        let mut tracker = CommandTracker::new("s1".to_string());
+        let mut current = None;
+
+        tracker.handle(marker(ShellMarker::CommandStart), &mut current);
+        let update = tracker.handle(marker(ShellMarker::CommandFinished(Some(0))), &mut current);
+        assert!(update.is_none());
+        assert!(current.is_none());
+    }
+
+    #[test]
+    fn test_paths_are_quoted_for_the_shell() {
+        let path = Path::new("/tmp/it's a \"dir\"/$HOME/zenterm.zsh");
+        let output = std::process::Command::new("/bin/sh")
+            .arg("-c")
+            .arg(format!("printf %s {}", shell_quote(path)))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            path.to_str().unwrap()
+        );
+
+        assert_eq!(fish_quote(Path::new(r"/a\b'c")), r"'/a\\b\'c'");
+    }
+
+    #[test]
+    fn test_zshenv_follows_a_moved_zdotdir() {
+        let user =
+            std::env::temp_dir().join(format!("zenterm_test_zdotdir_{}", std::process::id()));
+        fs::create_dir_all(user.join("config")).unwrap();
+        fs::write(user.join(".zshenv"), "ZDOTDIR=\"$HOME/config\"\n").unwrap();
+
+        let mut cmd = CommandBuilder::new("zsh");
+        assert!(inject(&mut cmd, "zsh", false).unwrap());
+        let ours = cmd.get_env("ZDOTDIR").unwrap().to_owned();
+
+        // bash understands the generated .zshenv well enough to check it
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg("source \"$ZDOTDIR/.zshenv\"; printf '%s\\n%s' \"$ZDOTDIR\" \"$ZENTERM_USER_ZDOTDIR\"")
+            .env("HOME", &user)
+            .env("ZDOTDIR", &ours)
+            .env("ZENTERM_USER_ZDOTDIR", &user)
+            .output()
+            .unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let expected = format!(
+            "{}\n{}",
+            Path::new(&ours).display(),
+            user.join("config").display()
+        );
+        assert_eq!(output, expected);
+
+        fs::remove_dir_all(&user).unwrap();
+    }
+
+    #[test]
+    fn test_strip_control_sequences() {
+        assert_eq!(strip_control_sequences("\x1b[32mgit\x1b[0m status\r\n"), "git status");
+        assert_eq!(strip_control_sequences("lss\x08 -la"), "ls -la");
+    }
+}
@@ -0,0 +1,273 @@
+use crate::terminal::{
+    CommandBlock, Emulator, PaneLayout, SessionSpec, TerminalOutput, TerminalSession,
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever the snapshot layout changes incompatibly
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Command blocks kept per session across restarts
+const MAX_SAVED_COMMANDS: usize = 100;
+
+/// Output bytes kept per saved command block
+const MAX_SAVED_OUTPUT: usize = 64 * 1024;
+
+/// Width used to flatten saved output into plain lines
+const HISTORY_COLS: usize = 200;
+
+/// Everything needed to bring the open tabs back after a restart
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub version: u32,
+    /// Index of the active session in `sessions`
+    pub active: Option<usize>,
+    /// Sessions in tab order
+    pub sessions: Vec<SavedSession>,
+    /// Split layout of each tab, referring to sessions by their saved ids
+    #[serde(default)]
+    pub layouts: Vec<PaneLayout>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSession {
+    /// Id the session had when saved; restored sessions get new ones
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub current_directory: String,
+    pub spec: SessionSpec,
+    /// Oldest first, including history restored from earlier snapshots
+    pub commands: Vec<CommandBlock>,
+}
+
+impl SessionSnapshot {
+    /// Where the snapshot lives unless told otherwise
+    pub fn default_path() -> PathBuf {
+        match dirs::config_dir() {
+            Some(config_dir) => config_dir.join("zenterm").join("sessions.json"),
+            None => PathBuf::from("zenterm_sessions.json"),
+        }
+    }
+
+    /// Read a snapshot; a missing file is not an error
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let snapshot: Self = serde_json::from_str(&content)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "Unsupported session snapshot version {}",
+                snapshot.version
+            ));
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Write the snapshot atomically, so a crash mid-write keeps the old one
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string(self)?)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+impl SavedSession {
+    pub fn from_session(session: &TerminalSession) -> Self {
+        let all = session
+            .restored_commands
+            .iter()
+            .chain(session.commands.iter());
+        let skip = (session.restored_commands.len() + session.commands.len())
+            .saturating_sub(MAX_SAVED_COMMANDS);
+
+        Self {
+            id: session.id.clone(),
+            name: session.name.clone(),
+            current_directory: session.current_directory.clone(),
+            spec: session.spec.clone(),
+            commands: all.skip(skip).map(truncate_output).collect(),
+        }
+    }
+
+    /// The spec to relaunch with: the original one, started in the last
+    /// known directory if that still exists
+    pub fn restore_spec(&self) -> SessionSpec {
+        let mut spec = self.spec.clone();
+        if Path::new(&self.current_directory).is_dir() {
+            spec.cwd = Some(self.current_directory.clone());
+        }
+        spec
+    }
+}
+
+/// Keep only the tail of a block's output, merged into one chunk
+fn truncate_output(block: &CommandBlock) -> CommandBlock {
+    let mut block = block.clone();
+    let Some(last) = block.output.last() else {
+        return block;
+    };
+
+    let content: String = block.output.iter().map(|o| o.content.as_str()).collect();
+    let mut start = content.len().saturating_sub(MAX_SAVED_OUTPUT);
+    while !content.is_char_boundary(start) {
+        start += 1;
+    }
+
+    block.output = vec![TerminalOutput {
+        session_id: last.session_id.clone(),
+        content: content[start..].to_string(),
+        is_error: false,
+        timestamp: last.timestamp,
+        offset: None,
+        highlighted: None,
+    }];
+    block
+}
+
+/// Render restored command blocks as dimmed plain text, to seed a new
+/// session's scrollback. Escape sequences in the saved output are played
+/// through an emulator first, so progress bars and redraws collapse to
+/// what was last on screen.
+pub fn render_history(commands: &[CommandBlock]) -> String {
+    if commands.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("\x1b[2m");
+    for block in commands {
+        out.push_str(&format!("$ {}\r\n", block.command));
+
+        let output: String = block.output.iter().map(|o| o.content.as_str()).collect();
+        for line in plain_lines(&output) {
+            out.push_str(&line);
+            out.push_str("\r\n");
+        }
+    }
+    out.push_str(&format!(
+        "--- restored {} command{} ---\x1b[0m\r\n",
+        commands.len(),
+        if commands.len() == 1 { "" } else { "s" }
+    ));
+    out
+}
+
+fn plain_lines(output: &str) -> Vec<String> {
+    let mut emulator = Emulator::new(24, HISTORY_COLS);
+    emulator.process(output.as_bytes());
+
+    let screen = emulator.screen();
+    let mut lines: Vec<String> = screen.scrollback().map(|row| row.text()).collect();
+    lines.extend(screen.visible_rows().iter().map(|row| row.text()));
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::CommandStatus;
+
+    fn block(command: &str, output: &str) -> CommandBlock {
+        CommandBlock {
+            id: command.to_string(),
+            session_id: "s".to_string(),
+            command: command.to_string(),
+            output: vec![TerminalOutput {
+                session_id: "s".to_string(),
+                content: output.to_string(),
+                is_error: false,
+                timestamp: 0,
+                offset: None,
+                highlighted: None,
+            }],
+            exit_code: Some(0),
+            start_time: 0,
+            end_time: Some(1),
+            status: CommandStatus::Completed,
+            cwd: None,
+        }
+    }
+
+    #[test]
+    fn test_render_history_flattens_output() {
+        let history = render_history(&[
+            block("ls", "a\r\nb\r\n"),
+            block("build", "50%\r100%\r\n\x1b[32mdone\x1b[0m\r\n"),
+        ]);
+        assert_eq!(
+            history,
+            "\x1b[2m$ ls\r\na\r\nb\r\n$ build\r\n100%\r\ndone\r\n--- restored 2 commands ---\x1b[0m\r\n"
+        );
+        assert_eq!(render_history(&[]), "");
+    }
+
+    #[test]
+    fn test_saved_session_caps_history() {
+        let mut session = TerminalSession {
+            id: "s".to_string(),
+            name: "build".to_string(),
+            active: true,
+            current_directory: "/definitely/not/here".to_string(),
+            commands: Vec::new(),
+            restored_commands: vec![block("old", "x")],
+            spec: SessionSpec::default(),
+            exit_code: None,
+        };
+        for i in 0..MAX_SAVED_COMMANDS {
+            session
+                .commands
+                .push(block(&format!("cmd{}", i), &"y".repeat(MAX_SAVED_OUTPUT + 10)));
+        }
+
+        let saved = SavedSession::from_session(&session);
+        assert_eq!(saved.commands.len(), MAX_SAVED_COMMANDS);
+        assert_eq!(saved.commands[0].command, "cmd0");
+        assert_eq!(saved.commands[0].output[0].content.len(), MAX_SAVED_OUTPUT);
+
+        // A directory that no longer exists falls back to the spec's own
+        assert_eq!(saved.restore_spec().cwd, None);
+    }
+
+    #[test]
+    fn test_snapshot_save_and_load() {
+        let path = std::env::temp_dir()
+            .join(format!("zenterm-test-snapshot-{}.json", std::process::id()));
+        let snapshot = SessionSnapshot {
+            version: SNAPSHOT_VERSION,
+            active: Some(0),
+            sessions: vec![SavedSession {
+                id: "s".to_string(),
+                name: "tab".to_string(),
+                current_directory: "/tmp".to_string(),
+                spec: SessionSpec::default(),
+                commands: vec![block("pwd", "/tmp\r\n")],
+            }],
+            layouts: vec![PaneLayout::new("s")],
+        };
+
+        snapshot.save(&path).unwrap();
+        let loaded = SessionSnapshot::load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.active, Some(0));
+        assert_eq!(loaded.sessions.len(), 1);
+        assert_eq!(loaded.sessions[0].name, "tab");
+        assert_eq!(loaded.sessions[0].commands[0].command, "pwd");
+        assert_eq!(loaded.sessions[0].restore_spec().cwd.as_deref(), Some("/tmp"));
+        assert_eq!(loaded.layouts[0].focused, "s");
+
+        assert!(SessionSnapshot::load(&path).unwrap().is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::terminal::shell_integration;
+use anyhow::Result;
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Fallback when neither the spec nor `$SHELL` names a shell
+const FALLBACK_SHELL: &str = "/bin/bash";
+
+/// Describes how to launch the process behind a terminal session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionSpec {
+    /// Program to run; defaults to `$SHELL`, then `/bin/bash`
+    pub shell: Option<String>,
+    /// Extra arguments passed after any login/integration flags
+    pub args: Vec<String>,
+    /// Environment variables set on top of the inherited environment
+    pub env: BTreeMap<String, String>,
+    /// Starting directory; `~` expands to `$HOME`. Defaults to `$HOME`
+    pub cwd: Option<String>,
+    /// Start the shell as a login shell
+    pub login: bool,
+    /// Profile the spec was resolved from, for triggers scoped to it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl SessionSpec {
+    /// The program this spec will run
+    pub fn resolve_shell(&self) -> String {
+        self.shell
+            .clone()
+            .filter(|shell| !shell.trim().is_empty())
+            .or_else(|| std::env::var("SHELL").ok().filter(|s| !s.is_empty()))
+            .unwrap_or_else(|| FALLBACK_SHELL.to_string())
+    }
+
+    /// The directory the session starts in
+    pub fn resolve_cwd(&self) -> String {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/".to_string());
+        match self.cwd.as_deref() {
+            None | Some("") => home,
+            Some("~") => home,
+            Some(path) => match path.strip_prefix("~/") {
+                Some(rest) => format!("{}/{}", home.trim_end_matches('/'), rest),
+                None => path.to_string(),
+            },
+        }
+    }
+
+    /// Build the command to spawn into the PTY
+    pub fn build_command(&self) -> Result<CommandBuilder> {
+        let shell = self.resolve_shell();
+        let mut cmd = CommandBuilder::new(&shell);
+        cmd.env("TERM", "xterm-256color");
+        cmd.cwd(self.resolve_cwd());
+
+        // Known shells get the hooks and their own login handling
+        let known_shell = shell_integration::inject(&mut cmd, &shell, self.login)?;
+        if self.login && !known_shell {
+            cmd.arg("-l");
+        }
+
+        cmd.args(&self.args);
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+
+        Ok(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explicit_shell_wins() {
+        let spec = SessionSpec {
+            shell: Some("/usr/bin/fish".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(spec.resolve_shell(), "/usr/bin/fish");
+    }
+
+    #[test]
+    fn test_cwd_expands_home() {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/".to_string());
+
+        let spec = SessionSpec::default();
+        assert_eq!(spec.resolve_cwd(), home);
+
+        let spec = SessionSpec {
+            cwd: Some("~/src/zenterm".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            spec.resolve_cwd(),
+            format!("{}/src/zenterm", home.trim_end_matches('/'))
+        );
+
+        let spec = SessionSpec {
+            cwd: Some("/tmp".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(spec.resolve_cwd(), "/tmp");
+    }
+
+    #[test]
+    fn test_spec_deserializes_with_defaults() {
+        let spec: SessionSpec =
+            serde_json::from_str(r#"{"shell": "nix", "args": ["develop"]}"#).unwrap();
+        assert_eq!(spec.shell.as_deref(), Some("nix"));
+        assert_eq!(spec.args, vec!["develop"]);
+        assert!(spec.env.is_empty());
+        assert!(!spec.login);
+    }
+}
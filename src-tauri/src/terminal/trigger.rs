@@ -0,0 +1,552 @@
+use crate::terminal::emulator::Emulator;
+use crate::terminal::screen::CellAttrs;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::process::{Command, Stdio};
+use std::thread;
+use vte::{Parser, Perform};
+
+/// Longest partial line kept while waiting for its newline
+const MAX_LINE_LEN: usize = 4096;
+
+/// Palette index used by highlights that name no colour (yellow)
+const DEFAULT_HIGHLIGHT: u8 = 3;
+
+/// A regex watcher over session output, configured in `terminal.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    /// Regular expression matched against each output line, with escape
+    /// sequences removed
+    pub pattern: String,
+    #[serde(default)]
+    pub scope: TriggerScope,
+    pub action: TriggerAction,
+}
+
+/// Which sessions a trigger watches
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerScope {
+    #[default]
+    All,
+    /// Only sessions started from this profile
+    Profile(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TriggerAction {
+    /// Colour the matched text in the output stream
+    Highlight {
+        /// 256-colour palette index of the background
+        #[serde(default)]
+        color: Option<u8>,
+    },
+    /// Ask the frontend to raise a notification
+    Notify {
+        #[serde(default)]
+        title: Option<String>,
+    },
+    /// Run a command registered under `commands` in the config
+    RunCommand { command: String },
+    /// Write text to the session as if typed, e.g. `"y\r"`
+    Reply { text: String },
+}
+
+/// External program triggers can run by name. It gets the match in
+/// `ZENTERM_TRIGGER`, `ZENTERM_SESSION_ID`, `ZENTERM_MATCH` and `ZENTERM_LINE`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredCommand {
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// A trigger that matched, published as `SessionEvent::TriggerFired`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerFired {
+    pub session_id: String,
+    pub trigger: String,
+    pub action: TriggerAction,
+    /// The text the pattern matched
+    pub matched: String,
+    /// The whole line it matched in, so far
+    pub line: String,
+}
+
+struct CompiledTrigger {
+    trigger: Trigger,
+    regex: Regex,
+}
+
+impl CompiledTrigger {
+    fn applies_to(&self, profile: Option<&str>) -> bool {
+        match &self.trigger.scope {
+            TriggerScope::All => true,
+            TriggerScope::Profile(name) => profile == Some(name.as_str()),
+        }
+    }
+}
+
+/// The configured triggers with their patterns compiled
+#[derive(Default)]
+pub struct TriggerSet {
+    triggers: Vec<CompiledTrigger>,
+    commands: BTreeMap<String, RegisteredCommand>,
+}
+
+impl TriggerSet {
+    /// Compile the triggers, rejecting bad patterns and unknown commands
+    pub fn compile(
+        triggers: &[Trigger],
+        commands: &BTreeMap<String, RegisteredCommand>,
+    ) -> Result<Self> {
+        let triggers = triggers
+            .iter()
+            .map(|trigger| {
+                let regex = Regex::new(&trigger.pattern)
+                    .map_err(|e| anyhow!("Trigger '{}': {}", trigger.name, e))?;
+                if let TriggerAction::RunCommand { command } = &trigger.action {
+                    if !commands.contains_key(command) {
+                        return Err(anyhow!(
+                            "Trigger '{}' runs unknown command '{}'",
+                            trigger.name,
+                            command
+                        ));
+                    }
+                }
+                Ok(CompiledTrigger {
+                    trigger: trigger.clone(),
+                    regex,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            triggers,
+            commands: commands.clone(),
+        })
+    }
+
+    /// The triggers this set was compiled from
+    pub fn triggers(&self) -> Vec<Trigger> {
+        self.triggers.iter().map(|t| t.trigger.clone()).collect()
+    }
+
+    pub fn commands(&self) -> &BTreeMap<String, RegisteredCommand> {
+        &self.commands
+    }
+
+    /// Wrap the matches of highlight triggers in colour, or `None` if
+    /// nothing matched. `pen` is the SGR state the chunk starts in; it is
+    /// followed through the chunk and restored after each match. Text
+    /// between escape sequences is matched chunk by chunk, so a match split
+    /// across reads or styles is not highlighted.
+    pub fn highlight(
+        &self,
+        content: &str,
+        profile: Option<&str>,
+        pen: CellAttrs,
+    ) -> Option<String> {
+        let highlights: Vec<(&Regex, u8)> = self
+            .triggers
+            .iter()
+            .filter(|t| t.applies_to(profile))
+            .filter_map(|t| match t.trigger.action {
+                TriggerAction::Highlight { color } => {
+                    Some((&t.regex, color.unwrap_or(DEFAULT_HIGHLIGHT)))
+                }
+                _ => None,
+            })
+            .collect();
+        if highlights.is_empty() {
+            return None;
+        }
+
+        // Only the pen of this scratch screen is used
+        let mut sgr = Emulator::new(1, 1);
+        sgr.process(pen.to_sgr().as_bytes());
+
+        let mut out = String::with_capacity(content.len());
+        let mut changed = false;
+        for (text, is_escape) in split_escapes(content) {
+            if is_escape {
+                out.push_str(text);
+                sgr.process(text.as_bytes());
+                continue;
+            }
+
+            // The first highlight to match a piece of text wins
+            let mut last = 0;
+            let mut ranges: Vec<(usize, usize, u8)> = Vec::new();
+            for (regex, color) in &highlights {
+                for m in regex.find_iter(text) {
+                    if !m.is_empty()
+                        && ranges
+                            .iter()
+                            .all(|&(s, e, _)| m.end() <= s || m.start() >= e)
+                    {
+                        ranges.push((m.start(), m.end(), *color));
+                    }
+                }
+            }
+            ranges.sort_unstable_by_key(|&(start, _, _)| start);
+            let restore = sgr.screen().cursor().attrs.to_sgr();
+            for (start, end, color) in ranges {
+                out.push_str(&text[last..start]);
+                out.push_str(&format!(
+                    "\x1b[30;48;5;{}m{}{}",
+                    color,
+                    &text[start..end],
+                    restore
+                ));
+                last = end;
+                changed = true;
+            }
+            out.push_str(&text[last..]);
+        }
+
+        changed.then_some(out)
+    }
+
+    /// Start a registered command for a trigger that fired, without waiting
+    /// for it
+    pub fn run_command(&self, name: &str, fired: &TriggerFired) {
+        let Some(command) = self.commands.get(name) else {
+            return;
+        };
+
+        let spawned = Command::new(&command.program)
+            .args(&command.args)
+            .env("ZENTERM_TRIGGER", &fired.trigger)
+            .env("ZENTERM_SESSION_ID", &fired.session_id)
+            .env("ZENTERM_MATCH", &fired.matched)
+            .env("ZENTERM_LINE", &fired.line)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match spawned {
+            // Reap it in the background so it does not linger as a zombie
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(e) => eprintln!(
+                "Trigger '{}' failed to run '{}': {}",
+                fired.trigger, name, e
+            ),
+        }
+    }
+}
+
+/// Split output into runs of text and escape sequences, flagging the latter
+fn split_escapes(content: &str) -> Vec<(&str, bool)> {
+    let bytes = content.as_bytes();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != 0x1b {
+            i += 1;
+            continue;
+        }
+        if start < i {
+            parts.push((&content[start..i], false));
+        }
+
+        let end = match bytes.get(i + 1) {
+            // CSI: parameters up to a final byte in @..~
+            Some(b'[') => bytes[i + 2..]
+                .iter()
+                .position(|b| (0x40..=0x7e).contains(b))
+                .map_or(bytes.len(), |p| i + 3 + p),
+            // OSC and other strings: up to BEL or ST
+            Some(b']' | b'P' | b'_' | b'^') => {
+                let mut j = i + 2;
+                while j < bytes.len()
+                    && bytes[j] != 0x07
+                    && !(bytes[j] == 0x1b && bytes.get(j + 1) == Some(&b'\\'))
+                {
+                    j += 1;
+                }
+                match bytes.get(j) {
+                    Some(0x07) => j + 1,
+                    Some(_) => j + 2,
+                    None => j,
+                }
+            }
+            Some(_) => i + 2,
+            None => i + 1,
+        };
+        // Never split inside a multi-byte character
+        let mut end = end.min(bytes.len());
+        while !content.is_char_boundary(end) {
+            end += 1;
+        }
+        parts.push((&content[i..end], true));
+        start = end;
+        i = end;
+    }
+    if start < bytes.len() {
+        parts.push((&content[start..], false));
+    }
+    parts
+}
+
+/// Collects the printable text of the output, line by line. With shell
+/// integration, the prompt and the echo of the command line are skipped, so
+/// only what programs print is matched.
+#[derive(Default)]
+struct LineCollector {
+    line: String,
+    complete: Vec<String>,
+    /// Between an OSC 133 prompt start and the command's output start
+    at_prompt: bool,
+}
+
+impl Perform for LineCollector {
+    fn print(&mut self, c: char) {
+        if !self.at_prompt && self.line.len() < MAX_LINE_LEN {
+            self.line.push(c);
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        if byte == b'\n' && !self.at_prompt {
+            self.complete.push(std::mem::take(&mut self.line));
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        let at_prompt = match params {
+            [b"133", marker, ..] if marker.starts_with(b"A") => true,
+            [b"133", marker, ..] if marker.starts_with(b"C") => false,
+            _ => return,
+        };
+        self.at_prompt = at_prompt;
+        self.line.clear();
+    }
+}
+
+/// Follows one session's output and reports the triggers it sets off. Each
+/// trigger fires at most once per line, but also on a line still waiting
+/// for its newline, so prompts like `Continue? [y/N]` can be answered.
+pub struct TriggerScanner {
+    session_id: String,
+    profile: Option<String>,
+    parser: Parser,
+    lines: LineCollector,
+    /// Triggers that already fired on the current line, by index
+    fired: HashSet<usize>,
+}
+
+impl TriggerScanner {
+    pub fn new(session_id: String, profile: Option<String>) -> Self {
+        Self {
+            session_id,
+            profile,
+            parser: Parser::new(),
+            lines: LineCollector::default(),
+            fired: HashSet::new(),
+        }
+    }
+
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Feed a chunk of output and collect the triggers it fires
+    pub fn scan(&mut self, triggers: &TriggerSet, content: &str) -> Vec<TriggerFired> {
+        self.parser.advance(&mut self.lines, content.as_bytes());
+
+        let mut fired = Vec::new();
+        for line in std::mem::take(&mut self.lines.complete) {
+            self.match_line(triggers, &line, &mut fired);
+            self.fired.clear();
+        }
+        let partial = std::mem::take(&mut self.lines.line);
+        if !partial.is_empty() {
+            self.match_line(triggers, &partial, &mut fired);
+        }
+        self.lines.line = partial;
+        fired
+    }
+
+    fn match_line(&mut self, triggers: &TriggerSet, line: &str, fired: &mut Vec<TriggerFired>) {
+        for (index, compiled) in triggers.triggers.iter().enumerate() {
+            // Highlights act on the stream itself
+            if matches!(compiled.trigger.action, TriggerAction::Highlight { .. })
+                || !compiled.applies_to(self.profile.as_deref())
+                || self.fired.contains(&index)
+            {
+                continue;
+            }
+            if let Some(m) = compiled.regex.find(line) {
+                self.fired.insert(index);
+                fired.push(TriggerFired {
+                    session_id: self.session_id.clone(),
+                    trigger: compiled.trigger.name.clone(),
+                    action: compiled.trigger.action.clone(),
+                    matched: m.as_str().to_string(),
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(name: &str, pattern: &str, scope: TriggerScope, action: TriggerAction) -> Trigger {
+        Trigger {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            scope,
+            action,
+        }
+    }
+
+    fn compile(triggers: &[Trigger]) -> TriggerSet {
+        TriggerSet::compile(triggers, &BTreeMap::new()).unwrap()
+    }
+
+    #[test]
+    fn test_scanner_fires_once_per_line() {
+        let set = compile(&[
+            trigger(
+                "rust-error",
+                r"error\[E\d+\]",
+                TriggerScope::All,
+                TriggerAction::Notify { title: None },
+            ),
+            trigger(
+                "continue",
+                r"Continue\? \[y/N\]",
+                TriggerScope::All,
+                TriggerAction::Reply {
+                    text: "y\r".to_string(),
+                },
+            ),
+        ]);
+        let mut scanner = TriggerScanner::new("s".to_string(), None);
+
+        // Split across reads and wrapped in colour
+        assert!(scanner.scan(&set, "\x1b[31merr").is_empty());
+        let fired = scanner.scan(&set, "or[E0308]\x1b[0m: mismatched types\r\n");
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].matched, "error[E0308]");
+        assert_eq!(fired[0].line, "error[E0308]: mismatched types");
+
+        // A prompt fires before its newline, and not again when the echoed
+        // answer completes the line
+        let fired = scanner.scan(&set, "Continue? [y/N] ");
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].trigger, "continue");
+        assert!(scanner.scan(&set, "y\r\n").is_empty());
+
+        // The next line can fire it again
+        assert_eq!(scanner.scan(&set, "Continue? [y/N] ").len(), 1);
+    }
+
+    #[test]
+    fn test_scanner_skips_command_line() {
+        let set = compile(&[trigger(
+            "rust-error",
+            r"error\[E",
+            TriggerScope::All,
+            TriggerAction::Notify { title: None },
+        )]);
+        let mut scanner = TriggerScanner::new("s".to_string(), None);
+
+        // Typing a command that mentions the pattern does not set it off
+        let stream = "\x1b]133;A\x07$ \x1b]133;B\x07grep 'error[E' log\r\n\x1b]133;C\x07";
+        assert!(scanner.scan(&set, stream).is_empty());
+        let fired = scanner.scan(&set, "error[E0425]\r\n\x1b]133;D;0\x07");
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].line, "error[E0425]");
+    }
+
+    #[test]
+    fn test_profile_scope() {
+        let set = compile(&[trigger(
+            "panic",
+            "panicked at",
+            TriggerScope::Profile("service".to_string()),
+            TriggerAction::Notify {
+                title: Some("Service panicked".to_string()),
+            },
+        )]);
+        let line = "thread 'main' panicked at src/main.rs:2:5\n";
+
+        let mut other = TriggerScanner::new("a".to_string(), Some("zsh".to_string()));
+        assert!(other.scan(&set, line).is_empty());
+        let mut service = TriggerScanner::new("b".to_string(), Some("service".to_string()));
+        assert_eq!(service.scan(&set, line).len(), 1);
+    }
+
+    #[test]
+    fn test_highlight_skips_escape_sequences() {
+        let set = compile(&[trigger(
+            "error",
+            "error",
+            TriggerScope::All,
+            TriggerAction::Highlight { color: Some(1) },
+        )]);
+
+        let pen = CellAttrs::default();
+        assert_eq!(set.highlight("all good\r\n", None, pen), None);
+        assert_eq!(
+            set.highlight("\x1b]0;error\x07an error\r\n", None, pen).unwrap(),
+            "\x1b]0;error\x07an \x1b[30;48;5;1merror\x1b[0m\r\n"
+        );
+
+        // The program's colours carry on after the match, including those
+        // set in an earlier chunk
+        let bold = CellAttrs {
+            bold: true,
+            ..pen
+        };
+        assert_eq!(
+            set.highlight("\x1b[34mno error here\x1b[0m", None, bold).unwrap(),
+            "\x1b[34mno \x1b[30;48;5;1merror\x1b[0;1;34m here\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_compile_rejects_bad_triggers() {
+        let bad_pattern = trigger(
+            "bad",
+            "error[",
+            TriggerScope::All,
+            TriggerAction::Notify { title: None },
+        );
+        assert!(TriggerSet::compile(&[bad_pattern], &BTreeMap::new()).is_err());
+
+        let unknown = trigger(
+            "page",
+            "FATAL",
+            TriggerScope::All,
+            TriggerAction::RunCommand {
+                command: "page-oncall".to_string(),
+            },
+        );
+        let unknown = std::slice::from_ref(&unknown);
+        assert!(TriggerSet::compile(unknown, &BTreeMap::new()).is_err());
+
+        let mut commands = BTreeMap::new();
+        commands.insert(
+            "page-oncall".to_string(),
+            RegisteredCommand {
+                program: "true".to_string(),
+                args: Vec::new(),
+            },
+        );
+        assert!(TriggerSet::compile(unknown, &commands).is_ok());
+    }
+}
@@ -1,24 +1,51 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { TerminalSession } from "./types";
-import TerminalView from "./components/TerminalView";
+import { listen } from "@tauri-apps/api/event";
+import { BroadcastGroup, ForegroundProcess, PaneLayout, SessionUsage, SplitDirection, TerminalSession, TriggerFired } from "./types";
+import PaneView from "./components/PaneView";
+import HistorySearch from "./components/HistorySearch";
 import TabBar from "./components/TabBar";
 import "./App.css";
 
 function App() {
   const [sessions, setSessions] = useState<TerminalSession[]>([]);
+  const [layouts, setLayouts] = useState<PaneLayout[]>([]);
   const [activeSessionId, setActiveSessionId] = useState<string | null>(null);
   const [isLoading, setIsLoading] = useState(true);
+  const [foreground, setForeground] = useState<ForegroundProcess | null>(null);
+  const [historyOpen, setHistoryOpen] = useState(false);
+  const [notice, setNotice] = useState<TriggerFired | null>(null);
+  const [broadcastGroups, setBroadcastGroups] = useState<BroadcastGroup[]>([]);
+  const [resourceUsage, setResourceUsage] = useState<SessionUsage[]>([]);
 
   // Initialize the app
   useEffect(() => {
     initializeApp();
   }, []);
 
+  // Sessions and pane layouts live in the backend; fetch them after every
+  // change instead of tracking them here
+  const syncSessions = async () => {
+    const [allSessions, allLayouts, activeId, groups] = await Promise.all([
+      invoke<TerminalSession[]>("get_sessions"),
+      invoke<PaneLayout[]>("get_layouts"),
+      invoke<string | null>("get_active_session"),
+      invoke<BroadcastGroup[]>("get_broadcast_groups"),
+    ]);
+    setSessions(allSessions);
+    setLayouts(allLayouts);
+    setActiveSessionId(activeId);
+    setBroadcastGroups(groups);
+    return allSessions;
+  };
+
   const initializeApp = async () => {
     try {
-      // Create initial session
-      await createNewSession();
+      // Pick up sessions restored from the last run, or start a fresh one
+      const restored = await syncSessions();
+      if (restored.length === 0) {
+        await createNewSession();
+      }
       setIsLoading(false);
     } catch (error) {
       console.error("Failed to initialize app:", error);
@@ -28,52 +55,162 @@ function App() {
 
   const createNewSession = async () => {
     try {
-      const sessionName = `Terminal ${sessions.length + 1}`;
-      const sessionId = await invoke<string>("create_terminal_session", { 
+      const sessionName = `Terminal ${layouts.length + 1}`;
+      await invoke<string>("create_terminal_session", { 
         name: sessionName 
       });
-      
-      const newSession: TerminalSession = {
-        id: sessionId,
-        name: sessionName,
-        active: true,
-        current_directory: "~",
-        commands: [],
-      };
-      
-      setSessions(prev => [...prev, newSession]);
-      setActiveSessionId(sessionId);
-      
-      // Set as active session in backend
-      await invoke("set_active_session", { sessionId });
+      await syncSessions();
     } catch (error) {
       console.error("Failed to create session:", error);
     }
   };
 
+  const splitPane = async (direction: SplitDirection) => {
+    if (!activeSessionId) return;
+    try {
+      await invoke<string>("split_pane", { sessionId: activeSessionId, direction });
+      await syncSessions();
+    } catch (error) {
+      console.error("Failed to split pane:", error);
+    }
+  };
+
+  const replaceLayout = (layout: PaneLayout) =>
+    setLayouts(prev => prev.map(l => (l.id === layout.id ? layout : l)));
+
+  const focusPane = async (sessionId: string) => {
+    if (sessionId === activeSessionId) return;
+    try {
+      replaceLayout(await invoke<PaneLayout>("focus_pane", { sessionId }));
+      setActiveSessionId(sessionId);
+    } catch (error) {
+      console.error("Failed to focus pane:", error);
+    }
+  };
+
+  const resizePane = async (sessionId: string, ratio: number) => {
+    try {
+      replaceLayout(await invoke<PaneLayout>("resize_pane", { sessionId, ratio }));
+    } catch (error) {
+      console.error("Failed to resize pane:", error);
+    }
+  };
+
+  const groupOf = (sessionId: string) =>
+    broadcastGroups.find(group => group.sessions.includes(sessionId));
+
+  // Put the active session into a broadcast group, or take it out again
+  const toggleBroadcast = async () => {
+    if (!activeSessionId) return;
+    try {
+      if (groupOf(activeSessionId)) {
+        await invoke("leave_broadcast_group", { sessionId: activeSessionId });
+      } else {
+        const name = window.prompt("Broadcast input to group:", broadcastGroups[0]?.name ?? "broadcast");
+        if (!name) return;
+        await invoke<BroadcastGroup>("join_broadcast_group", { name, sessionId: activeSessionId });
+      }
+      setBroadcastGroups(await invoke<BroadcastGroup[]>("get_broadcast_groups"));
+    } catch (error) {
+      console.error("Failed to change broadcast group:", error);
+    }
+  };
+
+  const toggleOptOut = async (sessionId: string) => {
+    const group = groupOf(sessionId);
+    if (!group) return;
+    try {
+      const updated = await invoke<BroadcastGroup>("set_broadcast_opt_out", {
+        sessionId,
+        optedOut: !group.opted_out.includes(sessionId),
+      });
+      setBroadcastGroups(prev => prev.map(g => (g.name === updated.name ? updated : g)));
+    } catch (error) {
+      console.error("Failed to change broadcast opt-out:", error);
+    }
+  };
+
+  // Ctrl+Shift+D splits side by side, Ctrl+Shift+E splits top and bottom,
+  // Ctrl+Shift+B toggles broadcast input, Ctrl+R searches the shared
+  // command history
+  useEffect(() => {
+    const onKeyDown = (event: KeyboardEvent) => {
+      if (event.ctrlKey && !event.shiftKey && event.key === "r" && !historyOpen) {
+        event.preventDefault();
+        event.stopPropagation();
+        setHistoryOpen(true);
+        return;
+      }
+      if (!event.ctrlKey || !event.shiftKey) return;
+      const key = event.key.toLowerCase();
+      if (key !== "d" && key !== "e" && key !== "b") return;
+      event.preventDefault();
+      event.stopPropagation();
+      if (key === "b") {
+        toggleBroadcast();
+      } else {
+        splitPane(key === "d" ? "horizontal" : "vertical");
+      }
+    };
+    window.addEventListener("keydown", onKeyDown, true);
+    return () => window.removeEventListener("keydown", onKeyDown, true);
+  }, [activeSessionId, historyOpen, broadcastGroups]);
+
+  // Show notify triggers from any session for a few seconds
+  useEffect(() => {
+    let timer: ReturnType<typeof setTimeout> | undefined;
+    const unlisten = listen<TriggerFired>("trigger-fired", (event) => {
+      if (event.payload.action.type !== "notify") return;
+      setNotice(event.payload);
+      clearTimeout(timer);
+      timer = setTimeout(() => setNotice(null), 5000);
+    });
+
+    return () => {
+      clearTimeout(timer);
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Track the resource usage of every session for the status bar
+  useEffect(() => {
+    invoke<SessionUsage[]>("get_resource_usage").then(setResourceUsage).catch(console.error);
+    const unlisten = listen<SessionUsage[]>("resource-usage", (event) => setResourceUsage(event.payload));
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  // Poll what the active session is running for the status bar
+  useEffect(() => {
+    if (!activeSessionId) return;
+
+    const poll = () =>
+      invoke<ForegroundProcess | null>("get_foreground_process", { sessionId: activeSessionId })
+        .then(setForeground)
+        .catch(() => setForeground(null));
+    poll();
+    const timer = setInterval(poll, 1000);
+
+    return () => {
+      clearInterval(timer);
+      setForeground(null);
+    };
+  }, [activeSessionId]);
+
   const closeSession = async (sessionId: string) => {
     try {
+      const process = await invoke<ForegroundProcess | null>("get_foreground_process", { sessionId });
+      if (process && !process.is_shell && !window.confirm(`"${process.command}" is still running. Close anyway?`)) {
+        return;
+      }
+
+      // The backend closes the pane and picks the next active session
       await invoke("close_session", { sessionId });
-      
-      setSessions(prev => {
-        const newSessions = prev.filter(s => s.id !== sessionId);
-        
-        // If we closed the active session, switch to another one
-        if (sessionId === activeSessionId) {
-          const nextSession = newSessions[0];
-          if (nextSession) {
-            setActiveSessionId(nextSession.id);
-            invoke("set_active_session", { sessionId: nextSession.id });
-          } else {
-            setActiveSessionId(null);
-          }
-        }
-        
-        return newSessions;
-      });
+      const remaining = await syncSessions();
       
       // If no sessions left, create a new one
-      if (sessions.length === 1) {
+      if (remaining.length === 0) {
         await createNewSession();
       }
     } catch (error) {
@@ -83,19 +220,43 @@ function App() {
 
   const switchSession = async (sessionId: string) => {
     try {
-      setActiveSessionId(sessionId);
       await invoke("set_active_session", { sessionId });
+      await syncSessions();
     } catch (error) {
       console.error("Failed to switch session:", error);
     }
   };
 
+  const stopForeground = async () => {
+    if (!activeSessionId) return;
+    try {
+      await invoke("send_signal", { sessionId: activeSessionId, signal: "SIGINT" });
+    } catch (error) {
+      console.error("Failed to stop process:", error);
+    }
+  };
+
   const handleCommand = (command: string) => {
     console.log("Command executed:", command);
     // Command execution is handled by the TerminalView component
   };
 
-  const activeSession = sessions.find(s => s.id === activeSessionId);
+  // One tab per layout, named after the pane that has focus in it
+  const tabs = layouts
+    .map(layout => sessions.find(s => s.id === layout.focused))
+    .filter((session): session is TerminalSession => session !== undefined);
+  const activeLayout = layouts.find(layout =>
+    activeSessionId !== null && layout.focused === activeSessionId
+  );
+  const activeGroup = activeSessionId ? groupOf(activeSessionId) : undefined;
+  // The tab burning the most CPU, if any uses a noticeable amount
+  const busiest = resourceUsage
+    .filter(entry => entry.usage.cpu_percent >= 1)
+    .sort((a, b) => b.usage.cpu_percent - a.usage.cpu_percent)[0];
+  const busiestSession = busiest && sessions.find(s => s.id === busiest.session_id);
+  const activeTargets = activeGroup && activeSessionId && !activeGroup.opted_out.includes(activeSessionId)
+    ? activeGroup.sessions.filter(id => !activeGroup.opted_out.includes(id))
+    : [];
 
   if (isLoading) {
     return (
@@ -122,22 +283,100 @@ function App() {
       overflow: 'hidden',
     }}>
       <TabBar
-        sessions={sessions}
+        sessions={tabs}
         activeSessionId={activeSessionId}
         onSessionChange={switchSession}
         onSessionClose={closeSession}
         onNewSession={createNewSession}
+        broadcastGroups={broadcastGroups}
       />
+
+      {activeGroup && (
+        <div style={{
+          padding: '2px 8px',
+          backgroundColor: activeTargets.length > 1 ? '#ff9e64' : '#414868',
+          color: '#15161e',
+          fontFamily: 'JetBrains Mono, Consolas, "Courier New", monospace',
+          fontSize: '12px',
+        }}>
+          {activeTargets.length > 1
+            ? `Broadcasting input to ${activeTargets.length} sessions in "${activeGroup.name}"`
+            : `Not broadcasting: this session is opted out of "${activeGroup.name}"`}
+          {' '}(Ctrl+Shift+B to leave)
+        </div>
+      )}
       
       <div style={{ flex: 1, position: 'relative' }}>
-        {activeSession && (
-          <TerminalView
-            key={activeSession.id}
-            session={activeSession}
+        {activeLayout && (
+          <PaneView
+            key={activeLayout.id}
+            node={activeLayout.root}
+            sessions={sessions}
+            focusedSessionId={activeLayout.focused}
+            onFocus={focusPane}
+            onResize={resizePane}
             onCommand={handleCommand}
+            broadcastGroups={broadcastGroups}
+            onToggleOptOut={toggleOptOut}
+          />
+        )}
+        {historyOpen && activeSessionId && (
+          <HistorySearch
+            sessionId={activeSessionId}
+            onClose={() => setHistoryOpen(false)}
           />
         )}
       </div>
+
+      {notice && notice.action.type === "notify" && (
+        <div
+          onClick={() => {
+            switchSession(notice.session_id);
+            setNotice(null);
+          }}
+          style={{
+            padding: '2px 8px',
+            backgroundColor: '#e0af68',
+            color: '#15161e',
+            fontFamily: 'JetBrains Mono, Consolas, "Courier New", monospace',
+            fontSize: '12px',
+            cursor: 'pointer',
+            whiteSpace: 'nowrap',
+            overflow: 'hidden',
+            textOverflow: 'ellipsis',
+          }}
+        >
+          {notice.action.title ?? notice.trigger}: {notice.line}
+        </div>
+      )}
+
+      {((foreground && !foreground.is_shell) || busiestSession) && (
+        <div style={{
+          display: 'flex',
+          justifyContent: 'space-between',
+          alignItems: 'center',
+          gap: '12px',
+          padding: '2px 8px',
+          backgroundColor: '#16161e',
+          color: '#a9b1d6',
+          fontFamily: 'JetBrains Mono, Consolas, "Courier New", monospace',
+          fontSize: '12px',
+        }}>
+          <span>{foreground && !foreground.is_shell && `running: ${foreground.command}`}</span>
+          {busiest && busiestSession && (
+            <span
+              onClick={() => switchSession(busiestSession.id)}
+              title={`${busiest.usage.processes} processes, ${busiest.usage.threads} threads, ${busiest.usage.open_files} open files`}
+              style={{ marginLeft: 'auto', cursor: 'pointer', color: busiest.usage.cpu_percent >= 50 ? '#ff9e64' : '#a9b1d6' }}
+            >
+              {busiestSession.name}: {busiest.usage.cpu_percent.toFixed(0)}% CPU, {(busiest.usage.rss_bytes / 1048576).toFixed(0)} MiB
+            </span>
+          )}
+          {foreground && !foreground.is_shell && (
+            <button onClick={stopForeground} title="Send SIGINT">Stop</button>
+          )}
+        </div>
+      )}
     </div>
   );
 }
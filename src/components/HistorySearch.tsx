@@ -0,0 +1,107 @@
+import React, { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { HistoryEntry } from '../types';
+
+interface HistorySearchProps {
+  sessionId: string;
+  onClose: () => void;
+}
+
+// Ctrl-R search over the command history of every tab, past and present.
+// The chosen command is typed into the session's prompt without running it.
+export const HistorySearch: React.FC<HistorySearchProps> = ({ sessionId, onClose }) => {
+  const [query, setQuery] = useState('');
+  const [results, setResults] = useState<HistoryEntry[]>([]);
+  const [selected, setSelected] = useState(0);
+
+  useEffect(() => {
+    invoke<HistoryEntry[]>('search_history', { query: { type: 'fuzzy', text: query } })
+      .then((entries) => {
+        setResults(entries);
+        setSelected(0);
+      })
+      .catch(console.error);
+  }, [query]);
+
+  const choose = (entry: HistoryEntry) => {
+    invoke('write_input', {
+      sessionId,
+      data: Array.from(new TextEncoder().encode(entry.command)),
+    }).catch(console.error);
+    onClose();
+  };
+
+  const onKeyDown = (event: React.KeyboardEvent) => {
+    if (event.key === 'Escape') {
+      onClose();
+    } else if (event.key === 'Enter' && results[selected]) {
+      choose(results[selected]);
+    } else if (event.key === 'ArrowDown' || (event.ctrlKey && event.key === 'r')) {
+      setSelected((i) => Math.min(i + 1, results.length - 1));
+    } else if (event.key === 'ArrowUp') {
+      setSelected((i) => Math.max(i - 1, 0));
+    } else {
+      return;
+    }
+    event.preventDefault();
+  };
+
+  return (
+    <div style={{
+      position: 'absolute',
+      left: '10%',
+      right: '10%',
+      bottom: '16px',
+      maxHeight: '50%',
+      display: 'flex',
+      flexDirection: 'column',
+      backgroundColor: '#16161e',
+      border: '1px solid #414868',
+      borderRadius: '6px',
+      fontFamily: 'JetBrains Mono, Consolas, "Courier New", monospace',
+      fontSize: '13px',
+      color: '#c0caf5',
+      zIndex: 10,
+    }}>
+      <div style={{ overflowY: 'auto', flex: 1 }}>
+        {results.map((entry, i) => (
+          <div
+            key={entry.id}
+            onMouseDown={() => choose(entry)}
+            style={{
+              display: 'flex',
+              justifyContent: 'space-between',
+              padding: '2px 8px',
+              cursor: 'pointer',
+              backgroundColor: i === selected ? '#283457' : 'transparent',
+            }}
+          >
+            <span style={{ color: entry.exit_code ? '#f7768e' : 'inherit' }}>{entry.command}</span>
+            <span style={{ color: '#737aa2', marginLeft: '16px', whiteSpace: 'nowrap' }}>
+              {entry.cwd}
+            </span>
+          </div>
+        ))}
+      </div>
+      <input
+        autoFocus
+        value={query}
+        onChange={(e) => setQuery(e.target.value)}
+        onKeyDown={onKeyDown}
+        onBlur={onClose}
+        placeholder="Search history"
+        style={{
+          background: 'none',
+          border: 'none',
+          borderTop: '1px solid #283457',
+          color: 'inherit',
+          font: 'inherit',
+          padding: '6px 8px',
+          outline: 'none',
+        }}
+      />
+    </div>
+  );
+};
+
+export default HistorySearch;
@@ -0,0 +1,133 @@
+import React, { useRef } from 'react';
+import { BroadcastGroup, PaneNode, TerminalSession } from '../types';
+import TerminalView from './TerminalView';
+
+interface PaneViewProps {
+  node: PaneNode;
+  sessions: TerminalSession[];
+  focusedSessionId: string;
+  onFocus: (sessionId: string) => void;
+  onResize: (sessionId: string, ratio: number) => void;
+  onCommand?: (command: string) => void;
+  broadcastGroups: BroadcastGroup[];
+  onToggleOptOut: (sessionId: string) => void;
+}
+
+// Session of the last pane in a subtree; the divider after it belongs to
+// the split the subtree is the first half of
+const lastSessionId = (node: PaneNode): string =>
+  node.type === 'pane' ? node.session_id : lastSessionId(node.second);
+
+export const PaneView: React.FC<PaneViewProps> = (props) => {
+  const { node, sessions, focusedSessionId, onFocus, onResize, onCommand, broadcastGroups, onToggleOptOut } = props;
+  const containerRef = useRef<HTMLDivElement>(null);
+
+  if (node.type === 'pane') {
+    const session = sessions.find(s => s.id === node.session_id);
+    if (!session) return null;
+
+    // Panes that share input get an orange frame and a label; clicking the
+    // label opts the pane out of the broadcast or back in
+    const group = broadcastGroups.find(g => g.sessions.includes(session.id));
+    const optedOut = group?.opted_out.includes(session.id) ?? false;
+    const broadcasting = group !== undefined && !optedOut;
+    const targets = broadcasting
+      ? group.sessions.filter(id => !group.opted_out.includes(id)).length
+      : 1;
+    const borderColor = broadcasting
+      ? '#ff9e64'
+      : session.id === focusedSessionId ? '#7aa2f7' : 'transparent';
+
+    return (
+      <div
+        onMouseDown={() => onFocus(session.id)}
+        style={{
+          width: '100%',
+          height: '100%',
+          boxSizing: 'border-box',
+          border: `1px ${optedOut ? 'dashed #ff9e64' : `solid ${borderColor}`}`,
+          display: 'flex',
+          flexDirection: 'column',
+        }}
+      >
+        {group && (
+          <div
+            onMouseDown={(e) => e.stopPropagation()}
+            onClick={() => onToggleOptOut(session.id)}
+            title={optedOut ? 'Click to broadcast again' : 'Click to stop broadcasting to this pane'}
+            style={{
+              padding: '0 6px',
+              backgroundColor: optedOut ? 'transparent' : '#ff9e64',
+              color: optedOut ? '#ff9e64' : '#15161e',
+              fontFamily: 'JetBrains Mono, Consolas, "Courier New", monospace',
+              fontSize: '11px',
+              cursor: 'pointer',
+            }}
+          >
+            {optedOut ? `${group.name} (opted out)` : `broadcast: ${group.name}`}
+          </div>
+        )}
+        <div style={{ flex: 1, minHeight: 0 }}>
+          <TerminalView
+            key={session.id}
+            session={session}
+            onCommand={onCommand}
+            broadcastTargets={targets}
+          />
+        </div>
+      </div>
+    );
+  }
+
+  const horizontal = node.direction === 'horizontal';
+
+  // Drag the divider, reporting the new share of the first half
+  const startDrag = (event: React.MouseEvent) => {
+    event.preventDefault();
+    const container = containerRef.current;
+    if (!container) return;
+
+    const rect = container.getBoundingClientRect();
+    const onMove = (move: MouseEvent) => {
+      const ratio = horizontal
+        ? (move.clientX - rect.left) / rect.width
+        : (move.clientY - rect.top) / rect.height;
+      onResize(lastSessionId(node.first), ratio);
+    };
+    const onUp = () => {
+      window.removeEventListener('mousemove', onMove);
+      window.removeEventListener('mouseup', onUp);
+    };
+    window.addEventListener('mousemove', onMove);
+    window.addEventListener('mouseup', onUp);
+  };
+
+  return (
+    <div
+      ref={containerRef}
+      style={{
+        display: 'flex',
+        flexDirection: horizontal ? 'row' : 'column',
+        width: '100%',
+        height: '100%',
+      }}
+    >
+      <div style={{ flex: `${node.ratio} 1 0`, minWidth: 0, minHeight: 0 }}>
+        <PaneView {...props} node={node.first} />
+      </div>
+      <div
+        onMouseDown={startDrag}
+        style={{
+          flex: '0 0 4px',
+          backgroundColor: '#283457',
+          cursor: horizontal ? 'col-resize' : 'row-resize',
+        }}
+      />
+      <div style={{ flex: `${1 - node.ratio} 1 0`, minWidth: 0, minHeight: 0 }}>
+        <PaneView {...props} node={node.second} />
+      </div>
+    </div>
+  );
+};
+
+export default PaneView;
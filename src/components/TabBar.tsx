@@ -1,6 +1,6 @@
 import React from 'react';
 import { X, Plus } from 'lucide-react';
-import { TerminalSession } from '../types';
+import { BroadcastGroup, TerminalSession } from '../types';
 
 interface TabBarProps {
   sessions: TerminalSession[];
@@ -8,6 +8,7 @@ interface TabBarProps {
   onSessionChange: (sessionId: string) => void;
   onSessionClose: (sessionId: string) => void;
   onNewSession: () => void;
+  broadcastGroups?: BroadcastGroup[];
 }
 
 export const TabBar: React.FC<TabBarProps> = ({
@@ -16,6 +17,7 @@ export const TabBar: React.FC<TabBarProps> = ({
   onSessionChange,
   onSessionClose,
   onNewSession,
+  broadcastGroups = [],
 }) => {
   return (
     <div style={{
@@ -54,6 +56,14 @@ export const TabBar: React.FC<TabBarProps> = ({
           }}>
             {session.name}
           </span>
+          {broadcastGroups.some(g => g.sessions.includes(session.id) && !g.opted_out.includes(session.id)) && (
+            <span
+              title="Broadcasting input"
+              style={{ color: '#ff9e64', marginRight: '6px' }}
+            >
+              ⇶
+            </span>
+          )}
           <button
             onClick={(e) => {
               e.stopPropagation();
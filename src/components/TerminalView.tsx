@@ -3,16 +3,24 @@ import { Terminal } from '@xterm/xterm';
 import { FitAddon } from '@xterm/addon-fit';
 import { WebLinksAddon } from '@xterm/addon-web-links';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import '@xterm/xterm/css/xterm.css';
-import { TerminalSession } from '../types';
+import { BufferSlice, TerminalOutput, TerminalResync, TerminalSession } from '../types';
 
 interface TerminalViewProps {
   session: TerminalSession;
   onCommand?: (command: string) => void;
+  /** Sessions that input typed here reaches, including this one */
+  broadcastTargets?: number;
 }
 
-export const TerminalView: React.FC<TerminalViewProps> = ({ session, onCommand }) => {
+// More than one line, not counting a trailing line break
+const isMultiline = (data: string) => /[\r\n]/.test(data.replace(/[\r\n]+$/, ''));
+
+export const TerminalView: React.FC<TerminalViewProps> = ({ session, onCommand, broadcastTargets = 1 }) => {
   const terminalRef = useRef<any>(null);
+  const targets = useRef(broadcastTargets);
+  targets.current = broadcastTargets;
   const terminal = useRef<Terminal | null>(null);
   const fitAddon = useRef<FitAddon | null>(null);
   const [isInitialized, setIsInitialized] = useState(false);
@@ -64,42 +72,26 @@ export const TerminalView: React.FC<TerminalViewProps> = ({ session, onCommand }
     terminal.current = term;
     fitAddon.current = fit;
     
-    // Handle data input
-    let currentLine = '';
+    // Send keystrokes to the PTY verbatim; the shell does the echoing and
+    // line editing. A multi-line paste into a broadcast group is confirmed
+    // first, since it runs in every session of the group.
+    const encoder = new TextEncoder();
     term.onData((data) => {
-      // Handle enter key
-      if (data === '\r') {
-        term.write('\r\n');
-        if (currentLine.trim()) {
-          onCommand?.(currentLine.trim());
-          executeCommand(currentLine.trim());
-        }
-        currentLine = '';
+      const broadcast = targets.current > 1 && isMultiline(data);
+      const lines = data.replace(/[\r\n]+$/, '').split(/\r\n|\r|\n/).length;
+      if (broadcast && !window.confirm(`Paste ${lines} lines into ${targets.current} sessions?`)) {
         return;
       }
-      
-      // Handle backspace
-      if (data === '\x7F') {
-        if (currentLine.length > 0) {
-          currentLine = currentLine.slice(0, -1);
-          term.write('\b \b');
-        }
-        return;
-      }
-      
-      // Handle regular characters
-      if (data.charCodeAt(0) >= 32) {
-        currentLine += data;
-        term.write(data);
-      }
+      invoke('write_input', {
+        sessionId: session.id,
+        data: Array.from(encoder.encode(data)),
+        confirmed: broadcast,
+      }).catch(console.error);
     });
 
-    // Show initial prompt
-    term.write('$ ');
-    
     setIsInitialized(true);
 
-    // Handle resize
+    // Handle resize, of the window or of the pane the terminal sits in
     const handleResize = () => {
       if (fitAddon.current) {
         fitAddon.current.fit();
@@ -112,27 +104,100 @@ export const TerminalView: React.FC<TerminalViewProps> = ({ session, onCommand }
       }
     };
 
-    window.addEventListener('resize', handleResize);
+    const observer = new ResizeObserver(handleResize);
+    observer.observe(terminalRef.current);
 
     return () => {
-      window.removeEventListener('resize', handleResize);
+      observer.disconnect();
       term.dispose();
     };
-  }, [isInitialized, session.id, onCommand]);
+  }, [isInitialized, session.id]);
 
+  // Replay the session's buffered output, then stream live output from the
+  // backend. Offsets let live chunks that overlap the replay be trimmed; a
+  // resync redraws the whole screen after the backend had to drop output.
+  useEffect(() => {
+    if (!isInitialized) return;
+
+    const encoder = new TextEncoder();
+    const decoder = new TextDecoder();
+    let written = 0;
+    let replayed = false;
+    const queued: TerminalOutput[] = [];
+
+    const writeOutput = (output: TerminalOutput) => {
+      if (output.offset === undefined || output.offset === null) {
+        terminal.current?.write(output.highlighted ?? output.content);
+        return;
+      }
+      const bytes = encoder.encode(output.content);
+      const end = output.offset + bytes.length;
+      if (end <= written) return;
+      // Offsets count the plain content; a partly replayed chunk is shown plain
+      const skip = Math.max(0, written - output.offset);
+      terminal.current?.write(
+        skip > 0 ? decoder.decode(bytes.subarray(skip)) : output.highlighted ?? output.content,
+      );
+      written = end;
+    };
+
+    const unlisteners = [
+      listen<TerminalOutput>(`terminal-output:${session.id}`, (event) => {
+        if (replayed) {
+          writeOutput(event.payload);
+        } else {
+          queued.push(event.payload);
+        }
+      }),
+      listen<TerminalResync>(`terminal-resync:${session.id}`, (event) => {
+        terminal.current?.reset();
+        terminal.current?.write(event.payload.content);
+        written = event.payload.offset;
+      }),
+    ];
+
+    Promise.all(unlisteners)
+      .then(() => invoke<BufferSlice>('get_session_buffer', { sessionId: session.id }))
+      .then((slice) => {
+        terminal.current?.write(slice.content);
+        written = slice.end_offset;
+      })
+      .catch(console.error)
+      .finally(() => {
+        replayed = true;
+        queued.splice(0).forEach(writeOutput);
+      });
+
+    return () => {
+      unlisteners.forEach((unlisten) => unlisten.then((fn) => fn()));
+    };
+  }, [isInitialized, session.id]);
+
+  // Run a whole command line as a command block (the prompt input path)
   const executeCommand = async (command: string) => {
     if (!terminal.current) return;
 
+    // Like a paste, several lines broadcast to a group are confirmed first
+    const broadcast = targets.current > 1 && isMultiline(command);
+    if (broadcast) {
+      const lines = command.replace(/[\r\n]+$/, '').split(/\r\n|\r|\n/).length;
+      if (!window.confirm(`Run ${lines} lines in ${targets.current} sessions?`)) {
+        return;
+      }
+    }
+
     try {
+      onCommand?.(command);
       await invoke('execute_command', {
         sessionId: session.id,
         command,
+        confirmed: broadcast,
       });
       
-      // The output will be handled by the output listener in the parent component
+      // The output arrives through the terminal-output listener above
     } catch (error) {
       console.error('Failed to execute command:', error);
-      terminal.current.write(`\r\nError: ${error}\r\n$ `);
+      terminal.current.write(`\r\nError: ${error}\r\n`);
     }
   };
 
@@ -145,6 +210,7 @@ export const TerminalView: React.FC<TerminalViewProps> = ({ session, onCommand }
   // Expose method to parent component
   React.useImperativeHandle(terminalRef, () => ({
     writeOutput,
+    executeCommand,
     fit: () => fitAddon.current?.fit(),
   }));
 
@@ -4,6 +4,82 @@ export interface TerminalSession {
   active: boolean;
   current_directory: string;
   commands: CommandBlock[];
+  /** Commands from before the last restart, shown read-only in scrollback */
+  restored_commands: CommandBlock[];
+  spec: SessionSpec;
+  exit_code?: number;
+}
+
+/** A recorded command from the history shared by all tabs and frontends */
+export interface HistoryEntry {
+  id: number;
+  command: string;
+  cwd: string;
+  exit_code?: number;
+  duration_ms: number;
+  session_id: string;
+  hostname: string;
+  start_time: number;
+  end_time: number;
+}
+
+export type HistoryQuery =
+  | { type: "prefix"; text: string }
+  | { type: "fuzzy"; text: string }
+  | { type: "directory"; cwd: string }
+  | { type: "failed"; since?: number };
+
+export type TriggerAction =
+  | { type: "highlight"; color?: number }
+  | { type: "notify"; title?: string }
+  | { type: "run_command"; command: string }
+  | { type: "reply"; text: string };
+
+/** Payload of the `trigger-fired` event */
+export interface TriggerFired {
+  session_id: string;
+  trigger: string;
+  action: TriggerAction;
+  matched: string;
+  line: string;
+}
+
+/** "horizontal" puts panes side by side, "vertical" stacks them */
+export type SplitDirection = "horizontal" | "vertical";
+
+export type PaneNode =
+  | { type: "pane"; session_id: string }
+  | {
+      type: "split";
+      direction: SplitDirection;
+      /** Share of the space given to `first` */
+      ratio: number;
+      first: PaneNode;
+      second: PaneNode;
+    };
+
+/** The panes of one tab */
+export interface PaneLayout {
+  id: string;
+  root: PaneNode;
+  focused: string;
+}
+
+/** Sessions sharing input; opted-out members neither send nor receive it */
+export interface BroadcastGroup {
+  name: string;
+  sessions: string[];
+  opted_out: string[];
+}
+
+export interface SessionSpec {
+  shell?: string;
+  args: string[];
+  env: Record<string, string>;
+  cwd?: string;
+  login: boolean;
+  /** Profile the spec was resolved from */
+  profile?: string;
 }
 
 export interface CommandBlock {
@@ -15,6 +91,8 @@ export interface CommandBlock {
   start_time: number;
   end_time?: number;
   status: CommandStatus;
+  /** Directory the session was in when the command started */
+  cwd?: string;
 }
 
 export interface TerminalOutput {
@@ -22,6 +100,90 @@ export interface TerminalOutput {
   content: string;
   is_error: boolean;
   timestamp: number;
+  offset?: number;
+  highlighted?: string;
+}
+
+export type SessionSignal = "SIGINT" | "SIGTERM" | "SIGKILL" | "SIGTSTP" | "SIGCONT";
+
+/** Usage of a session's shell and everything it started */
+export interface ResourceUsage {
+  cpu_percent: number; // of one core, so up to 100 × cores
+  rss_bytes: number;
+  threads: number;
+  open_files: number;
+  processes: number;
+}
+
+/** Payload of the `resource-usage` event, one per session */
+export interface SessionUsage {
+  session_id: string;
+  usage: ResourceUsage;
+}
+
+export interface ForegroundProcess {
+  pid: number;
+  name: string;
+  command: string;
+  is_shell: boolean;
+}
+
+export interface RecordingState {
+  recording: boolean;
+  path: string;
+}
+
+export type PlaybackEvent =
+  | { type: "Resize"; data: { cols: number; rows: number } }
+  | { type: "Finished" };
+
+export interface BufferSlice {
+  start_offset: number;
+  end_offset: number;
+  content: string;
+  truncated: boolean;
+}
+
+export interface TerminalResync {
+  session_id: string;
+  skipped: number;
+  content: string;
+  offset: number;
+}
+
+export type Color = "Default" | { Indexed: number } | { Rgb: [number, number, number] };
+
+export interface CellAttrs {
+  fg: Color;
+  bg: Color;
+  bold: boolean;
+  dim: boolean;
+  italic: boolean;
+  underline: boolean;
+  inverse: boolean;
+  hidden: boolean;
+  strikethrough: boolean;
+}
+
+export interface TextRun {
+  text: string;
+  attrs: CellAttrs;
+}
+
+export interface StyledLine {
+  runs: TextRun[];
+  wrapped: boolean;
+}
+
+export interface ScreenSnapshot {
+  rows: number;
+  cols: number;
+  cursor_row: number;
+  cursor_col: number;
+  cursor_visible: boolean;
+  alternate_screen: boolean;
+  title: string;
+  lines: StyledLine[];
 }
 
 export enum CommandStatus {